@@ -0,0 +1,344 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::time::Duration;
+use crate::config::Config;
+use crate::output_format::OutputFormat;
+use crate::ranking::SortOrder;
+use crate::search_options::{CaseMode, SearchOptions, Syntax};
+
+pub const DEFAULT_PRINT_TEMPLATE: &str = "{path}:{page}";
+
+pub const HELP: &str = "Search the text of pdf files and open the matches at the right page.
+
+Usage: pdf_search [OPTIONS] <SEARCH_TERM>
+       pdf_search [OPTIONS] <GLOB> <SEARCH_TERM>
+       pdf_search index build [FOLDER]
+       pdf_search index status [FOLDER]
+       pdf_search index watch [FOLDER]
+       pdf_search config show
+
+Commands:
+  index build [FOLDER]     Extract the text of every pdf in the folder once and store it, later
+                           searches in that folder use it [default: .]
+  index status [FOLDER]    Show what the index of the folder holds [default: .]
+  index watch [FOLDER]     Build the index and keep it up to date while pdfs are added, changed
+                           or removed, until stopped with ctrl-c. Linux only [default: .]
+  config show              Print the settings from the config files and where each one came from.
+                           The user's is $XDG_CONFIG_HOME/pdf_search/config.toml, a project's is
+                           .pdf_search.toml in the current folder or above it. Options win over both
+
+Options:
+  -g, --glob <GLOB>        Only search files matching the glob [default: *.pdf]
+  -p, --path <PATH>        Folder to search in, can be given more than once [default: .]
+  -C, --context <N>        Lines of context around each match [default: 8]
+  -i, --ignore-case        Ignore case when matching (default)
+  -s, --case-sensitive     Match case exactly
+  -S, --smart-case         Ignore case unless the search term has an uppercase letter
+  -e, --regex              Treat the search term as a regex (default)
+  -F, --fixed-strings      Treat the search term as plain text
+  -q, --query              Treat the search term as a query of words and \"phrases\" with AND, OR,
+                           NOT, parentheses and NEAR/n. It matches pages, not lines, and can
+                           have fields: file:, page:1-20, title:, author:, subject:, keywords:
+                           and creationdate:2019..2021
+  -m, --max-results <N>    Stop after N matches
+      --timeout <SECONDS>  Stop rga when the search takes longer, the matches so far are kept
+      --no-index           Extract the pdfs even when the folder has an index
+      --sort <ORDER>       Order the matches by relevance, path or page. The tui starts in
+                           relevance order, printed matches are in the order they were found
+                           Without an index, relevance only compares the matches with each other
+      --no-tui             Print the matches instead of opening the picker
+  -f, --format <FORMAT>    Print the matches as plain, jsonl, csv or vimgrep, implies --no-tui
+      --print              Enter prints the selected match and exits instead of opening it
+      --print-template <T> What --print prints, with {path}, {page}, {line} and {content}
+                           filled in, implies --print [default: {path}:{page}]
+  -h, --help               Print this help
+  -V, --version            Print the version
+";
+
+#[derive(Debug, PartialEq)]
+pub enum CliCommand {
+    Search(SearchArgs),
+    Index(IndexCommand),
+    ShowConfig,
+    Help,
+    Version,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum IndexCommand {
+    Build(PathBuf),
+    Status(PathBuf),
+    Watch(PathBuf),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SearchArgs {
+    pub options: SearchOptions,
+    pub no_index: bool,
+    pub no_tui: bool,
+    pub format: Option<OutputFormat>,
+    pub sort: Option<SortOrder>,
+    // Set when enter should print the match, see --print.
+    pub print_template: Option<String>,
+}
+
+impl SearchArgs {
+    // The format to print in, None means the tui is used.
+    pub fn output_format(&self) -> Option<OutputFormat> {
+        self.format.or(self.no_tui.then_some(OutputFormat::Plain))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct CliError(pub String);
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "error: {}\n\nRun 'pdf_search --help' for usage.", self.0)
+    }
+}
+
+/// Reads the arguments, without the program name. What they don't say is taken from the config.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I, config: &Config) -> Result<CliCommand, CliError> {
+    let args: Vec<String> = args.into_iter().collect();
+    if args == ["config", "show"] {
+        return Ok(CliCommand::ShowConfig);
+    }
+    // Searching for the word index still works, it is only a command when build or status follows.
+    if let [command, subcommand, rest @ ..] = args.as_slice() {
+        if command == "index" && ["build", "status", "watch"].contains(&subcommand.as_str()) {
+            return parse_index_args(subcommand, rest);
+        }
+    }
+    let mut args = args.into_iter();
+    let mut positional: Vec<String> = Vec::new();
+    let mut glob: Option<String> = None;
+    let mut options = SearchOptions::new(&config.glob.value, "");
+    options.context = config.context.value;
+    options.case = config.case.value;
+    options.syntax = config.syntax.value;
+    options.timeout = config.timeout.value;
+    let mut no_index = false;
+    let mut no_tui = false;
+    let mut format: Option<OutputFormat> = None;
+    let mut sort: Option<SortOrder> = config.sort.value;
+    let mut print_template: Option<String> = None;
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.by_ref());
+            break;
+        }
+        // Long options can have their value after an equals sign, --context=3.
+        let (flag, mut inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| -> Result<String, CliError> {
+            inline_value
+                .take()
+                .or_else(|| args.next())
+                .ok_or_else(|| CliError(format!("{} needs a value", name)))
+        };
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(CliCommand::Help),
+            "-V" | "--version" => return Ok(CliCommand::Version),
+            "-g" | "--glob" => glob = Some(value(&flag)?),
+            "-p" | "--path" => options.paths.push(PathBuf::from(value(&flag)?)),
+            "-C" | "--context" => options.context = parse_number(&flag, &value(&flag)?)?,
+            "-i" | "--ignore-case" => options.case = CaseMode::Insensitive,
+            "-s" | "--case-sensitive" => options.case = CaseMode::Sensitive,
+            "-S" | "--smart-case" => options.case = CaseMode::Smart,
+            "-e" | "--regex" => options.syntax = Syntax::Regex,
+            "-F" | "--fixed-strings" => options.syntax = Syntax::Fixed,
+            "-q" | "--query" => options.syntax = Syntax::Query,
+            "-m" | "--max-results" => {
+                let max_results = parse_number(&flag, &value(&flag)?)?;
+                if max_results == 0 {
+                    return Err(CliError(format!("{} must be at least 1", flag)));
+                }
+                options.max_results = Some(max_results);
+            }
+            "--timeout" => {
+                let seconds = parse_number(&flag, &value(&flag)?)?;
+                if seconds == 0 {
+                    return Err(CliError(format!("{} must be at least 1", flag)));
+                }
+                options.timeout = Some(Duration::from_secs(seconds as u64));
+            }
+            "--no-index" => no_index = true,
+            "--no-tui" => no_tui = true,
+            "-f" | "--format" => format = Some(value(&flag)?.parse().map_err(CliError)?),
+            "--sort" => sort = Some(value(&flag)?.parse().map_err(CliError)?),
+            "--print" => print_template = print_template.or(Some(DEFAULT_PRINT_TEMPLATE.to_string())),
+            "--print-template" => print_template = Some(value(&flag)?),
+            _ if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(CliError(format!("unknown option '{}'", flag)));
+            }
+            _ => positional.push(arg.clone()),
+        }
+        if inline_value.is_some() {
+            return Err(CliError(format!("{} does not take a value", flag)));
+        }
+    }
+
+    // The glob used to be the first positional argument, that still works.
+    let search_term = match (positional.len(), &glob) {
+        (0, _) => return Err(CliError("missing the search term".to_string())),
+        (1, _) => positional.remove(0),
+        (2, None) => {
+            glob = Some(positional.remove(0));
+            positional.remove(0)
+        }
+        (2, Some(_)) => return Err(CliError("the glob was given both with --glob and as an argument".to_string())),
+        _ => return Err(CliError(format!("too many arguments: {}", positional.join(" ")))),
+    };
+    if print_template.is_some() && (no_tui || format.is_some()) {
+        return Err(CliError("--print needs the tui, it can not be used with --no-tui or --format".to_string()));
+    }
+    if search_term.is_empty() {
+        return Err(CliError("the search term is empty".to_string()));
+    }
+    if let Some(path) = options.paths.iter().find(|path| !path.is_dir()) {
+        return Err(CliError(format!("'{}' is not a folder", path.display())));
+    }
+    options.search_term = search_term;
+    if let Some(glob) = glob {
+        options.glob = glob;
+    }
+    Ok(CliCommand::Search(SearchArgs { options, no_index, no_tui, format, sort, print_template }))
+}
+
+fn parse_index_args(subcommand: &str, rest: &[String]) -> Result<CliCommand, CliError> {
+    let folder = match rest {
+        [] => PathBuf::from("."),
+        [folder] => PathBuf::from(folder),
+        _ => return Err(CliError(format!("too many arguments: {}", rest.join(" ")))),
+    };
+    if !folder.is_dir() {
+        return Err(CliError(format!("'{}' is not a folder", folder.display())));
+    }
+    match subcommand {
+        "build" => Ok(CliCommand::Index(IndexCommand::Build(folder))),
+        "watch" => Ok(CliCommand::Index(IndexCommand::Watch(folder))),
+        _ => Ok(CliCommand::Index(IndexCommand::Status(folder))),
+    }
+}
+
+fn parse_number(flag: &str, value: &str) -> Result<usize, CliError> {
+    value
+        .parse()
+        .map_err(|_| CliError(format!("{} expects a number, got '{}'", flag, value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<CliCommand, CliError> {
+        parse_args(args.iter().map(|arg| arg.to_string()), &Config::default())
+    }
+
+    fn search_args(args: &[&str]) -> SearchArgs {
+        match parse(args) {
+            Ok(CliCommand::Search(search_args)) => search_args,
+            other => panic!("Expected a search, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn old_positional_usage() {
+        let args = search_args(&["test_assets/*.pdf", "subheading"]);
+        assert_eq!(args.options, SearchOptions::new("test_assets/*.pdf", "subheading"));
+        assert!(!args.no_tui);
+        assert_eq!(args.output_format(), None);
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(search_args(&["--no-tui", "x"]).output_format(), Some(OutputFormat::Plain));
+        assert_eq!(search_args(&["--format", "csv", "x"]).output_format(), Some(OutputFormat::Csv));
+        assert_eq!(search_args(&["--format=vimgrep", "x"]).output_format(), Some(OutputFormat::Vimgrep));
+        assert!(parse(&["--format", "xml", "x"]).is_err());
+    }
+
+    #[test]
+    fn print() {
+        assert_eq!(search_args(&["x"]).print_template, None);
+        assert_eq!(search_args(&["--print", "x"]).print_template.as_deref(), Some(DEFAULT_PRINT_TEMPLATE));
+        let args = search_args(&["--print-template", "{path}\\t{page}", "--print", "x"]);
+        assert_eq!(args.print_template.as_deref(), Some("{path}\\t{page}"));
+        assert!(parse(&["--print", "--no-tui", "x"]).is_err());
+    }
+
+    #[test]
+    fn all_options() {
+        let args = search_args(&[
+            "--glob=*.PDF", "-p", "test_assets", "--path", "src", "-C", "3", "--smart-case", "-F", "--max-results", "10",
+            "--timeout=30", "--no-tui", "--", "-torque",
+        ]);
+        let options = args.options;
+        assert_eq!(options.glob, "*.PDF");
+        assert_eq!(options.paths, vec![PathBuf::from("test_assets"), PathBuf::from("src")]);
+        assert_eq!(options.context, 3);
+        assert_eq!(options.case, CaseMode::Smart);
+        assert_eq!(options.syntax, Syntax::Fixed);
+        assert_eq!(options.max_results, Some(10));
+        assert_eq!(options.timeout, Some(Duration::from_secs(30)));
+        assert_eq!(options.search_term, "-torque");
+        assert!(args.no_tui);
+    }
+
+    #[test]
+    fn index_commands() {
+        assert_eq!(parse(&["index", "build"]), Ok(CliCommand::Index(IndexCommand::Build(PathBuf::from(".")))));
+        assert_eq!(parse(&["index", "status", "test_assets"]), Ok(CliCommand::Index(IndexCommand::Status(PathBuf::from("test_assets")))));
+        assert!(parse(&["index", "build", "no_such_folder"]).is_err());
+        assert_eq!(parse(&["index", "watch", "."]), Ok(CliCommand::Index(IndexCommand::Watch(PathBuf::from(".")))));
+        assert_eq!(search_args(&["index"]).options.search_term, "index");
+        assert!(search_args(&["--no-index", "x"]).no_index);
+    }
+
+    #[test]
+    fn sort_order() {
+        assert_eq!(search_args(&["x"]).sort, None);
+        assert_eq!(search_args(&["--sort=page", "x"]).sort, Some(SortOrder::Page));
+        assert!(parse(&["--sort", "size", "x"]).is_err());
+    }
+
+    #[test]
+    fn config_is_the_default() {
+        let mut config = Config::default();
+        config.context.value = 2;
+        config.case.value = CaseMode::Smart;
+        config.sort.value = Some(SortOrder::Path);
+        config.glob.value = "*.PDF".to_string();
+        let args = match parse_args(["-C", "4", "x"].map(String::from), &config) {
+            Ok(CliCommand::Search(search_args)) => search_args,
+            other => panic!("Expected a search, got {:?}", other),
+        };
+        assert_eq!((args.options.context, args.options.case, args.sort), (4, CaseMode::Smart, Some(SortOrder::Path)));
+        assert_eq!(args.options.glob, "*.PDF");
+        assert_eq!(parse(&["config", "show"]), Ok(CliCommand::ShowConfig));
+        assert_eq!(search_args(&["config"]).options.search_term, "config");
+    }
+
+    #[test]
+    fn help_and_version() {
+        assert_eq!(parse(&["torque", "--help"]), Ok(CliCommand::Help));
+        assert_eq!(parse(&["-V"]), Ok(CliCommand::Version));
+    }
+
+    #[test]
+    fn validation_errors() {
+        assert_eq!(parse(&[]), Err(CliError("missing the search term".to_string())));
+        assert_eq!(parse(&["-C", "many", "x"]), Err(CliError("-C expects a number, got 'many'".to_string())));
+        assert_eq!(parse(&["--context"]), Err(CliError("--context needs a value".to_string())));
+        assert_eq!(parse(&["--bogus", "x"]), Err(CliError("unknown option '--bogus'".to_string())));
+        assert_eq!(parse(&["--no-tui=yes", "x"]), Err(CliError("--no-tui does not take a value".to_string())));
+        assert_eq!(parse(&["-m", "0", "x"]), Err(CliError("-m must be at least 1".to_string())));
+        assert!(parse(&["-g", "*.pdf", "a", "b"]).is_err());
+        assert!(parse(&["a", "b", "c"]).is_err());
+        assert!(parse(&["-p", "no_such_folder", "x"]).is_err());
+    }
+}
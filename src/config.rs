@@ -0,0 +1,481 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fs};
+use serde::Deserialize;
+use tui::style::Color;
+use crate::keymap::{Action, Key, Keymap, Preset};
+use crate::pdf_opener::VIEWER_ENV_VAR;
+use crate::ranking::SortOrder;
+use crate::search_options::{CaseMode, Syntax, DEFAULT_CONTEXT, DEFAULT_GLOB};
+use crate::terminal_image::{Protocol, IMAGES_ENV_VAR};
+
+// Looked for in the current folder and the folders above it, the closest one wins.
+pub const PROJECT_CONFIG_FILE: &str = ".pdf_search.toml";
+
+const COLOUR_NAMES: [(&str, Color); 16] = [
+    ("black", Color::Black),
+    ("red", Color::Red),
+    ("green", Color::Green),
+    ("yellow", Color::Yellow),
+    ("blue", Color::Blue),
+    ("magenta", Color::Magenta),
+    ("cyan", Color::Cyan),
+    ("gray", Color::Gray),
+    ("darkgray", Color::DarkGray),
+    ("lightred", Color::LightRed),
+    ("lightgreen", Color::LightGreen),
+    ("lightyellow", Color::LightYellow),
+    ("lightblue", Color::LightBlue),
+    ("lightmagenta", Color::LightMagenta),
+    ("lightcyan", Color::LightCyan),
+    ("white", Color::White),
+];
+
+/// Where a setting came from, `pdf_search config show` tells.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Environment(&'static str),
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Environment(variable) => write!(f, "${}", variable),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+impl<T> Setting<T> {
+    fn default(value: T) -> Setting<T> {
+        Setting { value, source: Source::Default }
+    }
+
+    fn set(&mut self, value: T, source: &Source) {
+        self.value = value;
+        self.source = source.clone();
+    }
+}
+
+/// The defaults for a search and the tui. Later files win over earlier ones: the built in defaults,
+/// the user's config file, the project's, then the environment. The command line wins over all of them.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Config {
+    pub glob: Setting<String>,
+    pub context: Setting<usize>,
+    pub case: Setting<CaseMode>,
+    pub syntax: Setting<Syntax>,
+    // How long rga may search, None is as long as it takes.
+    pub timeout: Setting<Option<Duration>>,
+    // None keeps the tui in relevance order and printed matches in the order they were found.
+    pub sort: Setting<Option<SortOrder>>,
+    // A viewer profile name or a command template, see pdf_opener. None picks the first one installed.
+    pub viewer: Setting<Option<String>>,
+    // None guesses from the terminal.
+    pub images: Setting<Option<Protocol>>,
+    pub term_colours: Setting<Vec<Color>>,
+    // The selected match in the list and the matched line in the page preview.
+    pub selection_colour: Setting<Color>,
+    pub key_preset: Setting<Preset>,
+    // The actions the config files give other keys, in the order they were read. They go on top of the preset.
+    pub key_bindings: Vec<(Action, Vec<Key>, Source)>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            glob: Setting::default(DEFAULT_GLOB.to_string()),
+            context: Setting::default(DEFAULT_CONTEXT),
+            case: Setting::default(CaseMode::Insensitive),
+            syntax: Setting::default(Syntax::Regex),
+            timeout: Setting::default(None),
+            sort: Setting::default(None),
+            viewer: Setting::default(None),
+            images: Setting::default(None),
+            // Light, so the text stays readable on them.
+            term_colours: Setting::default(vec![Color::LightYellow, Color::LightCyan, Color::LightMagenta, Color::LightGreen, Color::LightRed, Color::LightBlue]),
+            selection_colour: Setting::default(Color::LightGreen),
+            key_preset: Setting::default(Preset::Default),
+            key_bindings: vec![],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ConfigError(pub String);
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "error in the configuration: {}", self.0)
+    }
+}
+
+// What a config file can have, everything is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    glob: Option<String>,
+    context: Option<usize>,
+    case: Option<String>,
+    syntax: Option<String>,
+    // In seconds.
+    timeout: Option<u64>,
+    sort: Option<String>,
+    viewer: Option<String>,
+    images: Option<String>,
+    colours: Option<ColoursFile>,
+    // The preset and action names, every action takes a key or a list of them.
+    keys: Option<BTreeMap<String, KeysFile>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ColoursFile {
+    terms: Option<Vec<String>>,
+    selection: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum KeysFile {
+    One(String),
+    More(Vec<String>),
+}
+
+impl Config {
+    /// Reads the user's and the project's config file, when they are there, and the environment.
+    /// A file or variable with a mistake is left out and the rest still counts, the mistakes come with where they are.
+    pub fn load() -> (Config, Vec<(Source, ConfigError)>) {
+        let mut config = Config::default();
+        let mut mistakes = Vec::new();
+        for path in Config::files().into_iter().flatten() {
+            if path.is_file() {
+                let mut read = config.clone();
+                match read.read_file(&path) {
+                    Ok(()) => config = read,
+                    Err(err) => mistakes.push((Source::File(path), err)),
+                }
+            }
+        }
+        let mut read = config.clone();
+        match read.apply_environment(env::var(VIEWER_ENV_VAR).ok(), env::var(IMAGES_ENV_VAR).ok()) {
+            Ok(()) => config = read,
+            Err(err) => mistakes.push((Source::Environment(IMAGES_ENV_VAR), err)),
+        }
+        (config, mistakes)
+    }
+
+    // The user's config file and the project's, in the order they are read.
+    fn files() -> [Option<PathBuf>; 2] {
+        let project = env::current_dir().ok().and_then(|folder| project_config_path(&folder));
+        [user_config_path(), project]
+    }
+
+    fn read_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let text = fs::read_to_string(path).map_err(|err| ConfigError(format!("{}: {}", path.display(), err)))?;
+        let file: ConfigFile = toml::from_str(&text).map_err(|err| ConfigError(format!("{}: {}", path.display(), err.message())))?;
+        self.apply(file, &Source::File(path.to_path_buf()))
+            .map_err(|message| ConfigError(format!("{}: {}", path.display(), message)))
+    }
+
+    fn apply(&mut self, file: ConfigFile, source: &Source) -> Result<(), String> {
+        if let Some(glob) = file.glob {
+            self.glob.set(glob, source);
+        }
+        if let Some(context) = file.context {
+            self.context.set(context, source);
+        }
+        if let Some(case) = file.case {
+            self.case.set(case.parse()?, source);
+        }
+        if let Some(syntax) = file.syntax {
+            self.syntax.set(syntax.parse()?, source);
+        }
+        if let Some(timeout) = file.timeout {
+            if timeout == 0 {
+                return Err("timeout must be at least 1 second".to_string());
+            }
+            self.timeout.set(Some(Duration::from_secs(timeout)), source);
+        }
+        if let Some(sort) = file.sort {
+            self.sort.set(Some(sort.parse()?), source);
+        }
+        if let Some(viewer) = file.viewer {
+            self.viewer.set(Some(viewer), source);
+        }
+        if let Some(images) = file.images {
+            self.images.set(Some(images.parse()?), source);
+        }
+        let colours = file.colours.unwrap_or_default();
+        if let Some(terms) = colours.terms {
+            if terms.is_empty() {
+                return Err("colours.terms needs at least one colour".to_string());
+            }
+            self.term_colours.set(terms.iter().map(|name| parse_colour(name)).collect::<Result<_, _>>()?, source);
+        }
+        if let Some(selection) = colours.selection {
+            self.selection_colour.set(parse_colour(&selection)?, source);
+        }
+        for (name, keys) in file.keys.unwrap_or_default() {
+            match (name.as_str(), keys) {
+                ("preset", KeysFile::One(preset)) => self.key_preset.set(preset.parse()?, source),
+                ("preset", KeysFile::More(_)) => return Err("keys.preset takes one name".to_string()),
+                (action, keys) => {
+                    let keys = match keys {
+                        KeysFile::One(key) => vec![key],
+                        KeysFile::More(keys) => keys,
+                    };
+                    let keys = keys.iter().map(|key| key.parse()).collect::<Result<_, _>>()?;
+                    self.key_bindings.push((action.parse()?, keys, source.clone()));
+                }
+            }
+        }
+        // Raw mode leaves no other way out of the tui.
+        let keymap = self.keymap();
+        if keymap.keys(Action::Quit).is_empty() && keymap.keys(Action::Cancel).is_empty() {
+            return Err("keys.quit and keys.cancel can not both be unbound, nothing would close the picker".to_string());
+        }
+        if keymap.keys(Action::EndEditing).is_empty() {
+            return Err("keys.done can not be unbound, nothing would stop typing".to_string());
+        }
+        Ok(())
+    }
+
+    // Empty variables count as not set, like they always did for the viewer.
+    fn apply_environment(&mut self, viewer: Option<String>, images: Option<String>) -> Result<(), ConfigError> {
+        if let Some(viewer) = viewer.filter(|viewer| !viewer.trim().is_empty()) {
+            self.viewer.set(Some(viewer), &Source::Environment(VIEWER_ENV_VAR));
+        }
+        if let Some(images) = images.filter(|images| !images.trim().is_empty()) {
+            let protocol = images.parse().map_err(|message| ConfigError(format!("${}: {}", IMAGES_ENV_VAR, message)))?;
+            self.images.set(Some(protocol), &Source::Environment(IMAGES_ENV_VAR));
+        }
+        Ok(())
+    }
+
+    /// The preset with the bindings of the config files on top.
+    pub fn keymap(&self) -> Keymap {
+        let mut keymap = Keymap::preset(self.key_preset.value);
+        for (action, keys, _) in &self.key_bindings {
+            keymap.bind(*action, keys);
+        }
+        keymap
+    }
+
+    /// The settings as a config file, with where every value came from. Settings that are not set are commented out.
+    /// The mistakes `load` found are shown under the file they are in, that file's settings are not used.
+    pub fn show(&self, mistakes: &[(Source, ConfigError)]) -> String {
+        let [user, project] = Config::files();
+        let mistake = |path: &Path| {
+            let source = Source::File(path.to_path_buf());
+            mistakes.iter().find(|(from, _)| *from == source).map_or_else(String::new, |(_, err)| format!("\n#     not used, {}", err))
+        };
+        let user = match user {
+            Some(path) if path.is_file() => format!("{}{}", path.display(), mistake(&path)),
+            Some(path) => format!("{} (not there)", path.display()),
+            None => "none, neither XDG_CONFIG_HOME nor HOME is set".to_string(),
+        };
+        let project = project.map_or_else(
+            || format!("none, no {} here or in a folder above", PROJECT_CONFIG_FILE),
+            |path| format!("{}{}", path.display(), mistake(&path)),
+        );
+        let mut text = format!("# Config files, later ones win:\n#   {}\n#   {}\n", user, project);
+        for (_, err) in mistakes.iter().filter(|(source, _)| matches!(source, Source::Environment(_))) {
+            text.push_str(&format!("#   not used, {}\n", err));
+        }
+        text.push('\n');
+        text.push_str(&self.show_settings());
+        text
+    }
+
+    fn show_settings(&self) -> String {
+        let string = |value: &str| toml::Value::String(value.to_string()).to_string();
+        let line = |key: &str, value: Option<String>, source: &Source| {
+            let setting = match value {
+                Some(value) => format!("{} = {}", key, value),
+                None => format!("# {} is not set", key),
+            };
+            format!("{:<40} # {}\n", setting, source)
+        };
+        let term_colours: Vec<String> = self.term_colours.value.iter().map(|colour| string(&colour_name(*colour))).collect();
+        let keymap = self.keymap();
+        // An action the files don't bind has the keys of the preset.
+        let keys = Action::ALL.map(|action| {
+            let keys: Vec<String> = keymap.keys(action).iter().map(|key| string(&key.to_string())).collect();
+            let source = self.key_bindings.iter().rev().find(|(bound, _, _)| *bound == action).map_or(&self.key_preset.source, |(_, _, source)| source);
+            line(action.name(), Some(format!("[{}]", keys.join(", "))), source)
+        });
+        [
+            line("glob", Some(string(&self.glob.value)), &self.glob.source),
+            line("context", Some(self.context.value.to_string()), &self.context.source),
+            line("case", Some(string(self.case.value.name())), &self.case.source),
+            line("syntax", Some(string(self.syntax.value.name())), &self.syntax.source),
+            line("timeout", self.timeout.value.map(|timeout| timeout.as_secs().to_string()), &self.timeout.source),
+            line("sort", self.sort.value.map(|sort| string(sort.name())), &self.sort.source),
+            line("viewer", self.viewer.value.as_deref().map(string), &self.viewer.source),
+            line("images", self.images.value.map(|images| string(images.name())), &self.images.source),
+            "\n[colours]\n".to_string(),
+            line("terms", Some(format!("[{}]", term_colours.join(", "))), &self.term_colours.source),
+            line("selection", Some(string(&colour_name(self.selection_colour.value))), &self.selection_colour.source),
+            "\n[keys]\n".to_string(),
+            line("preset", Some(string(self.key_preset.value.name())), &self.key_preset.source),
+        ]
+        .into_iter()
+        .chain(keys)
+        .collect()
+    }
+}
+
+/// `$XDG_CONFIG_HOME/pdf_search/config.toml`, or where the config folder is when it is not set.
+pub fn user_config_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|folder| !folder.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").filter(|_| cfg!(windows)).map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("pdf_search").join("config.toml"))
+}
+
+/// The project's config file, in `folder` or the closest folder above it.
+pub fn project_config_path(folder: &Path) -> Option<PathBuf> {
+    folder.ancestors().map(|folder| folder.join(PROJECT_CONFIG_FILE)).find(|path| path.is_file())
+}
+
+/// A colour by name, like `lightyellow`, or as `#rrggbb`.
+pub fn parse_colour(name: &str) -> Result<Color, String> {
+    let unknown = || format!("unknown colour '{}', expected a name like lightyellow or #rrggbb", name);
+    if let Some(hex) = name.strip_prefix('#') {
+        let channel = |index: usize| hex.get(index..index + 2).and_then(|channel| u8::from_str_radix(channel, 16).ok()).ok_or_else(unknown);
+        return match hex.len() {
+            6 => Ok(Color::Rgb(channel(0)?, channel(2)?, channel(4)?)),
+            _ => Err(unknown()),
+        };
+    }
+    let name = name.to_lowercase().replace(['_', '-', ' '], "");
+    let name = name.replace("grey", "gray");
+    COLOUR_NAMES.iter().find(|(known, _)| *known == name).map(|(_, colour)| *colour).ok_or_else(unknown)
+}
+
+pub fn colour_name(colour: Color) -> String {
+    match colour {
+        Color::Rgb(red, green, blue) => format!("#{:02x}{:02x}{:02x}", red, green, blue),
+        _ => COLOUR_NAMES.iter().find(|(_, known)| *known == colour).map_or_else(|| format!("{:?}", colour).to_lowercase(), |(name, _)| name.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_folder(name: &str) -> PathBuf {
+        let folder = env::temp_dir().join(format!("pdf_search_config_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    #[test]
+    fn files_in_order() {
+        let folder = temp_folder("order");
+        let user = folder.join("config.toml");
+        fs::write(&user, "context = 3\ncase = \"smart\"\nviewer = \"zathura\"\n[colours]\nterms = [\"yellow\", \"#ff8000\"]\n").unwrap();
+        let project = folder.join(PROJECT_CONFIG_FILE);
+        fs::write(&project, "context = 5\nsort = \"page\"\ntimeout = 60\n").unwrap();
+        let mut config = Config::default();
+        config.read_file(&user).unwrap();
+        config.read_file(&project).unwrap();
+        config.apply_environment(Some("okular".to_string()), Some(" ".to_string())).unwrap();
+        assert_eq!(config.context, Setting { value: 5, source: Source::File(project.clone()) });
+        assert_eq!(config.case, Setting { value: CaseMode::Smart, source: Source::File(user.clone()) });
+        assert_eq!(config.sort.value, Some(SortOrder::Page));
+        assert_eq!(config.timeout.value, Some(Duration::from_secs(60)));
+        assert_eq!(config.viewer, Setting { value: Some("okular".to_string()), source: Source::Environment(VIEWER_ENV_VAR) });
+        assert_eq!(config.images, Setting::default(None));
+        assert_eq!(config.term_colours.value, vec![Color::Yellow, Color::Rgb(255, 128, 0)]);
+        assert_eq!(config.glob, Setting::default("*.pdf".to_string()));
+    }
+
+    #[test]
+    fn mistakes_name_the_file() {
+        let folder = temp_folder("mistakes");
+        let path = folder.join("config.toml");
+        for text in ["contxt = 3", "context = \"three\"", "case = \"loud\"", "timeout = 0", "[colours]\nselection = \"plaid\"", "[colours]\nterms = []", "[keys]\njump = \"x\"", "[keys]\nquit = \"hyper-q\"", "[keys]\npreset = \"nano\"", "[keys]\nquit = []\ncancel = []", "[keys]\ndone = []"] {
+            fs::write(&path, text).unwrap();
+            let err = Config::default().read_file(&path).unwrap_err();
+            assert!(err.0.starts_with(&path.display().to_string()), "{}", err);
+        }
+        assert!(Config::default().apply_environment(None, Some("png".to_string())).is_err());
+    }
+
+    #[test]
+    fn keys_on_top_of_the_preset() {
+        let folder = temp_folder("keys");
+        let user = folder.join("config.toml");
+        fs::write(&user, "[keys]\nquit = [\"ctrl-c\", \"x\"]\nhelp = []\n").unwrap();
+        let project = folder.join(PROJECT_CONFIG_FILE);
+        fs::write(&project, "[keys]\npreset = \"vim\"\nnext_file = \"ctrl-j\"\n").unwrap();
+        let mut config = Config::default();
+        config.read_file(&user).unwrap();
+        config.read_file(&project).unwrap();
+        assert_eq!(config.key_preset, Setting { value: Preset::Vim, source: Source::File(project.clone()) });
+        let keymap = config.keymap();
+        assert_eq!(keymap.hint(Action::Quit), "ctrl-c");
+        assert_eq!(keymap.hint(Action::Help), "unbound");
+        assert_eq!(keymap.hint(Action::NextFile), "ctrl-j");
+        assert_eq!(keymap.hint(Action::NextPage), "right");
+        assert_eq!(keymap.keys(Action::NextPage).len(), 2);
+    }
+
+    #[test]
+    fn project_file_in_a_parent() {
+        let folder = temp_folder("project");
+        fs::create_dir_all(folder.join("manuals/old")).unwrap();
+        assert_eq!(project_config_path(&folder.join("manuals/old")), None);
+        fs::write(folder.join(PROJECT_CONFIG_FILE), "").unwrap();
+        assert_eq!(project_config_path(&folder.join("manuals/old")), Some(folder.join(PROJECT_CONFIG_FILE)));
+    }
+
+    #[test]
+    fn colours() {
+        assert_eq!(parse_colour("Light-Yellow"), Ok(Color::LightYellow));
+        assert_eq!(parse_colour("dark grey"), Ok(Color::DarkGray));
+        assert_eq!(parse_colour("#0a0B0c"), Ok(Color::Rgb(10, 11, 12)));
+        assert!(parse_colour("#abc").is_err());
+        assert!(parse_colour("#gg0000").is_err());
+        assert_eq!(colour_name(Color::LightCyan), "lightcyan");
+        assert_eq!(colour_name(Color::Rgb(255, 0, 16)), "#ff0010");
+    }
+
+    #[test]
+    fn shown_with_sources() {
+        let mut config = Config::default();
+        config.apply(ConfigFile { context: Some(2), ..ConfigFile::default() }, &Source::File(PathBuf::from("p.toml"))).unwrap();
+        let text = config.show_settings();
+        let line = |key: &str| text.lines().find(|line| line.starts_with(key)).unwrap().split_whitespace().collect::<Vec<_>>().join(" ");
+        assert_eq!(line("context"), "context = 2 # p.toml");
+        assert_eq!(line("glob"), "glob = \"*.pdf\" # default");
+        assert_eq!(line("# viewer"), "# viewer is not set # default");
+        assert!(text.contains("\n[colours]\nterms = [\"lightyellow\", "));
+        assert_eq!(line("quit"), "quit = [\"q\"] # default");
+        // What is shown can be read back.
+        let shown: ConfigFile = toml::from_str(&text).unwrap();
+        assert_eq!(shown.context, Some(2));
+        let mut read_back = Config::default();
+        read_back.apply(shown, &Source::Default).unwrap();
+        assert!(Action::ALL.iter().all(|action| read_back.keymap().keys(*action) == config.keymap().keys(*action)));
+        // Mistakes are shown instead of stopping it.
+        let mistake = (Source::Environment(IMAGES_ENV_VAR), ConfigError(format!("${}: unknown image protocol 'png'", IMAGES_ENV_VAR)));
+        assert!(config.show(&[mistake]).contains("#   not used, error in the configuration: $PDF_SEARCH_IMAGES: unknown image protocol 'png'\n"));
+    }
+}
@@ -1,10 +1,185 @@
-pub struct FuzzyFinder {}
+use crate::search_match::SearchMatch;
+
+// Scores in the spirit of fzf. Every matched character is worth something, characters at the start of a word
+// and runs of characters next to each other are worth more, and skipping characters between two matches costs.
+// A run keeps the bonus of its first character, so a whole word beats letters scattered over word starts.
+const SCORE_MATCH: i64 = 16;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CAMEL_CASE: i64 = 7;
+const BONUS_CONSECUTIVE: i64 = 4;
+const GAP_START: i64 = -3;
+const GAP_EXTENSION: i64 = -1;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct FuzzyMatch {
+    pub score: i64,
+    // Char indices of the matched characters in the text.
+    pub positions: Vec<usize>,
+}
+
+/// A search match that survived the filter, with where the query matched its `fuzzy_display()`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FilteredMatch {
+    pub search_match: SearchMatch,
+    pub fuzzy_match: FuzzyMatch,
+}
+
+/// Narrows a list of search matches down to the ones the query fuzzy matches, best first.
+/// Replaces piping the old powershell script into fzf.
+pub struct FuzzyFinder {
+    pub query: String,
+}
 
 impl FuzzyFinder {
     pub fn new() -> FuzzyFinder {
-        FuzzyFinder {}
+        FuzzyFinder { query: String::new() }
+    }
+
+    /// With an empty query every match is kept, in the order it came in.
+    pub fn filter(&self, search_matches: &[SearchMatch]) -> Vec<FilteredMatch> {
+        let mut filtered: Vec<FilteredMatch> = search_matches
+            .iter()
+            .filter_map(|search_match| {
+                let fuzzy_match = fuzzy_match(&self.query, &search_match.fuzzy_display())?;
+                Some(FilteredMatch { search_match: search_match.clone(), fuzzy_match })
+            })
+            .collect();
+        // The sort is stable, so equal scores keep the file and page order.
+        filtered.sort_by_key(|filtered_match| -filtered_match.fuzzy_match.score);
+        filtered
+    }
+}
+
+/// Finds the best way to match the pattern as a subsequence of the text, None when it is not one.
+/// Case is ignored unless the pattern has an uppercase letter.
+pub fn fuzzy_match(pattern: &str, text: &str) -> Option<FuzzyMatch> {
+    let ignore_case = !pattern.chars().any(|c| c.is_uppercase());
+    let fold = |c: char| if ignore_case { c.to_lowercase().next().unwrap_or(c) } else { c };
+    let pattern: Vec<char> = pattern.chars().map(fold).collect();
+    let original: Vec<char> = text.chars().collect();
+    let text: Vec<char> = original.iter().map(|&c| fold(c)).collect();
+    if pattern.is_empty() {
+        return Some(FuzzyMatch { score: 0, positions: vec![] });
+    }
+    if !is_subsequence(&pattern, &text) {
+        return None;
+    }
+
+    // scores[i][j] is the best score with pattern[i] matched at text[j], from[i][j] where pattern[i - 1] was matched then.
+    // run_bonus[i][j] is the bonus the run ending there started with.
+    let (m, n) = (pattern.len(), text.len());
+    let mut scores: Vec<Vec<Option<i64>>> = vec![vec![None; n]; m];
+    let mut from = vec![vec![0; n]; m];
+    let mut run_bonus = vec![vec![0; n]; m];
+    for i in 0..m {
+        // The best way to get to j with a gap after the previous character.
+        let mut gap: Option<(i64, usize)> = None;
+        for j in 0..n {
+            if i > 0 {
+                gap = gap.map(|(score, k)| (score + GAP_EXTENSION, k));
+                if let Some(score) = j.checked_sub(2).and_then(|k| scores[i - 1][k]) {
+                    if gap.is_none_or(|(best, _)| score + GAP_START > best) {
+                        gap = Some((score + GAP_START, j - 2));
+                    }
+                }
+            }
+            if text[j] != pattern[i] {
+                continue;
+            }
+            let bonus = bonus(&original, j);
+            run_bonus[i][j] = bonus;
+            if i == 0 {
+                scores[i][j] = Some(SCORE_MATCH + bonus);
+                continue;
+            }
+            let consecutive = j.checked_sub(1).and_then(|k| {
+                let run_bonus = bonus.max(run_bonus[i - 1][k]);
+                scores[i - 1][k].map(|score| (score + SCORE_MATCH + run_bonus.max(BONUS_CONSECUTIVE), k, run_bonus))
+            });
+            let after_gap = gap.map(|(score, k)| (score + SCORE_MATCH + bonus, k, bonus));
+            let best = match (consecutive, after_gap) {
+                (Some(a), Some(b)) => Some(if a.0 >= b.0 { a } else { b }),
+                (a, b) => a.or(b),
+            };
+            if let Some((score, k, bonus)) = best {
+                scores[i][j] = Some(score);
+                from[i][j] = k;
+                run_bonus[i][j] = bonus;
+            }
+        }
+    }
+
+    let (score, mut j) = (0..n).filter_map(|j| Some((scores[m - 1][j]?, j))).max_by_key(|&(score, j)| (score, std::cmp::Reverse(j)))?;
+    let mut positions = vec![0; m];
+    for i in (0..m).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+    Some(FuzzyMatch { score, positions })
+}
+
+fn is_subsequence(pattern: &[char], text: &[char]) -> bool {
+    let mut text = text.iter();
+    pattern.iter().all(|p| text.any(|t| t == p))
+}
+
+fn bonus(text: &[char], j: usize) -> i64 {
+    let current = text[j];
+    let previous = match j.checked_sub(1) {
+        Some(k) => text[k],
+        None => return BONUS_BOUNDARY,
+    };
+    if current.is_alphanumeric() && !previous.is_alphanumeric() {
+        BONUS_BOUNDARY
+    } else if current.is_uppercase() && previous.is_lowercase() {
+        BONUS_CAMEL_CASE
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn subsequence() {
+        assert_eq!(fuzzy_match("tq", "torque").unwrap().positions, vec![0, 3]);
+        assert_eq!(fuzzy_match("qt", "torque"), None);
+        assert_eq!(fuzzy_match("", "torque").unwrap().score, 0);
+    }
+
+    #[test]
+    fn prefers_boundaries_and_runs() {
+        // The m of manual is at a word start, the one in "aluminium" is not.
+        assert_eq!(fuzzy_match("man", "aluminium manual").unwrap().positions, vec![10, 11, 12]);
+        assert!(fuzzy_match("spec", "docs/specs.pdf").unwrap().score > fuzzy_match("spec", "s_p_e_c.pdf").unwrap().score);
+        assert_eq!(fuzzy_match("ts", "TorqueSpec").unwrap().positions, vec![0, 6]);
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(fuzzy_match("torque", "TORQUE").is_some());
+        assert!(fuzzy_match("Torque", "torque").is_none());
     }
-    pub fn start(&self) {
 
+    #[test]
+    fn filter_sorts_by_score() {
+        let search_match = |path: &str, content: &str| SearchMatch::new(PathBuf::from(path), 1, 1, content.to_string(), String::new());
+        let search_matches = vec![
+            search_match("a.pdf", "bolt torque table"),
+            search_match("b.pdf", "nothing here"),
+            search_match("c.pdf", "torque"),
+        ];
+        let mut fuzzy_finder = FuzzyFinder::new();
+        assert_eq!(fuzzy_finder.filter(&search_matches).len(), 3);
+        fuzzy_finder.query = "c torq".to_string();
+        let filtered = fuzzy_finder.filter(&search_matches);
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].search_match.path, PathBuf::from("c.pdf"));
+        fuzzy_finder.query = "torq".to_string();
+        let paths: Vec<_> = fuzzy_finder.filter(&search_matches).into_iter().map(|filtered| filtered.search_match.path).collect();
+        assert_eq!(paths, vec![PathBuf::from("a.pdf"), PathBuf::from("c.pdf")]);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,123 @@
+use std::ops::Range;
+use regex::Regex;
+use crate::query::Query;
+use crate::search_match::SearchMatch;
+use crate::search_options::{SearchOptions, Syntax};
+
+/// What to highlight in a line: the byte range and the number of the term it belongs to,
+/// so every term can get its own colour.
+pub type Highlights = Vec<(Range<usize>, usize)>;
+
+/// Finds the search terms in lines. A query has a term for every word and phrase. A regex is one
+/// term, but what it matches is told apart by the text, so `torque|bolt` still gets two colours.
+#[derive(Clone)]
+pub struct Highlighter {
+    regexes: Vec<Regex>,
+    // The texts a regex matched so far, lower case. Their index is the term number.
+    texts: Vec<String>,
+}
+
+impl Highlighter {
+    /// Highlights nothing when the search term is not valid.
+    pub fn new(options: &SearchOptions) -> Highlighter {
+        let regexes = match options.syntax {
+            Syntax::Query => Query::parse(&options.search_term, options.case)
+                .map(|query| query.wanted_terms().iter().map(|term| term.regex().clone()).collect())
+                .unwrap_or_default(),
+            _ => options.regex().into_iter().collect(),
+        };
+        Highlighter { regexes, texts: Vec::new() }
+    }
+
+    /// The terms in a line, in order and without overlaps.
+    pub fn find(&mut self, line: &str) -> Highlights {
+        let mut found: Vec<(Range<usize>, usize)> = Vec::new();
+        if self.regexes.len() == 1 {
+            for range in self.regexes[0].find_iter(line).map(|found| found.range()).collect::<Vec<_>>() {
+                let term = self.term_of(&line[range.clone()]);
+                found.push((range, term));
+            }
+        } else {
+            for (term, regex) in self.regexes.iter().enumerate() {
+                found.extend(regex.find_iter(line).map(|found| (found.range(), term)));
+            }
+        }
+        without_overlaps(found)
+    }
+
+    /// Where the terms are in the matched line. The ranges the backend reported are used when it has them,
+    /// for a query they don't say which term they are.
+    pub fn find_in_match(&mut self, search_match: &SearchMatch) -> Highlights {
+        if self.regexes.len() != 1 || search_match.submatches.is_empty() {
+            return self.find(&search_match.content);
+        }
+        let mut found = Vec::new();
+        for range in &search_match.submatches {
+            if let Some(text) = search_match.content.get(range.clone()) {
+                found.push((range.clone(), self.term_of(text)));
+            }
+        }
+        without_overlaps(found)
+    }
+
+    fn term_of(&mut self, text: &str) -> usize {
+        let text = text.to_lowercase();
+        match self.texts.iter().position(|known| *known == text) {
+            Some(term) => term,
+            None => {
+                self.texts.push(text);
+                self.texts.len() - 1
+            }
+        }
+    }
+}
+
+// The first one wins, a phrase and a word in it can both match the same text.
+fn without_overlaps(mut found: Highlights) -> Highlights {
+    found.sort_by_key(|(range, _)| (range.start, usize::MAX - range.end));
+    let mut end = 0;
+    found.retain(|(range, _)| {
+        let keep = range.start >= end && !range.is_empty();
+        if keep {
+            end = range.end;
+        }
+        keep
+    });
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn regex_terms_by_text() {
+        let mut highlighter = Highlighter::new(&SearchOptions::new("*.pdf", "torque|bolts?"));
+        assert_eq!(highlighter.find("Bolt torque, torque bolts"), vec![(0..4, 0), (5..11, 1), (13..19, 1), (20..25, 2)]);
+        assert_eq!(highlighter.find("TORQUE"), vec![(0..6, 1)]);
+    }
+
+    #[test]
+    fn query_terms() {
+        let mut options = SearchOptions::new("*.pdf", "torque AND \"m8 bolt\" NOT nut page:1");
+        options.syntax = Syntax::Query;
+        let mut highlighter = Highlighter::new(&options);
+        assert_eq!(highlighter.find("M8 bolt nut torque"), vec![(0..7, 1), (12..18, 0)]);
+        let search_match = SearchMatch::new(PathBuf::from("a.pdf"), 1, 1, "torque bolt".to_string(), String::new()).with_submatches(vec![0..6, 7..11]);
+        assert_eq!(highlighter.find_in_match(&search_match), vec![(0..6, 0)]);
+    }
+
+    #[test]
+    fn submatches_from_the_backend() {
+        let mut highlighter = Highlighter::new(&SearchOptions::new("*.pdf", "torque"));
+        let search_match = SearchMatch::new(PathBuf::from("a.pdf"), 1, 1, "Torque ok".to_string(), String::new()).with_submatches(vec![0..6, 7..20]);
+        assert_eq!(highlighter.find_in_match(&search_match), vec![(0..6, 0)]);
+        assert!(Highlighter::new(&SearchOptions::new("*.pdf", "(")).find("(").is_empty());
+    }
+
+    #[test]
+    fn overlaps() {
+        assert_eq!(without_overlaps(vec![(2..5, 1), (0..3, 0), (0..1, 2), (5..5, 3)]), vec![(0..3, 0)]);
+    }
+}
@@ -0,0 +1,677 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::metadata::DocumentInfo;
+use crate::pdf_text::{extract, is_pdf, page_lines};
+use crate::search_backend::{find_files, relative_path};
+use crate::search_error::SearchError;
+use crate::search_status::FileWarning;
+
+// The index lives in a hidden folder inside the folder it indexes, so it is skipped when searching
+// and everyone using a shared drive gets the same index.
+pub const INDEX_DIR: &str = ".pdf_search";
+const INDEX_FILE: &str = "index.json";
+// The text of every pdf is in a file of its own in here, named after the hash of the pdf.
+const TEXT_DIR: &str = "text";
+// Bumped when the format changes, an index with another version has to be built again.
+const INDEX_VERSION: u32 = 5;
+
+/// Every pdf in a folder, what they say about themselves and which lines every word is on.
+/// The text of the pdfs is stored apart from this, so a search only reads the text of the pdfs that can match.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Index {
+    pub version: u32,
+    // Seconds since the unix epoch.
+    pub built_at: u64,
+    // Every pdf seen, also the ones that could not be read, to tell what changed since.
+    pub catalogue: BTreeMap<PathBuf, CatalogueEntry>,
+    pub files: Vec<IndexedFile>,
+    // Every word, lowercased and sorted, and where it is. The file is the position in `files`.
+    pub postings: Vec<(String, Vec<Posting>)>,
+    // For every three characters, the positions in `postings` of the words that have them.
+    // A search word is usually part of a word, like bolt of bolts, this finds those words without going through all of them.
+    trigrams: HashMap<String, Vec<usize>>,
+    // The text of the pdfs extracted since the index was loaded, by hash. `save` writes it.
+    #[serde(skip)]
+    unsaved: HashMap<String, Vec<IndexedLine>>,
+}
+
+/// What a pdf looked like when it was indexed. Size and modification time are compared first,
+/// the hash only when those differ, so touched but unchanged files and renamed files are not extracted again.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CatalogueEntry {
+    pub size: u64,
+    // Milliseconds since the unix epoch.
+    pub modified: u64,
+    // Sha-256 of the content, in hex.
+    pub hash: String,
+    // Why the text could not be extracted. The file is not tried again until it changes.
+    pub error: Option<String>,
+}
+
+/// What an update of the index did.
+#[derive(Debug, PartialEq, Default)]
+pub struct IndexChanges {
+    pub added: Vec<PathBuf>,
+    pub modified: Vec<PathBuf>,
+    // From the old path to the new.
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+    pub removed: Vec<PathBuf>,
+    pub unchanged: usize,
+    // Files that could not be extracted this time.
+    pub failed: Vec<FileWarning>,
+    // Pdfs that were still being written, what the index had for them is kept until the next update.
+    pub postponed: Vec<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct IndexedFile {
+    // Relative to the indexed folder, so the folder can be moved or mounted somewhere else.
+    pub path: PathBuf,
+    // Like in the catalogue, the text is stored under it.
+    pub hash: String,
+    pub pages: usize,
+    pub lines: usize,
+    pub info: DocumentInfo,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct IndexedLine {
+    pub page: usize,
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Posting {
+    pub file: usize,
+    pub page: usize,
+    // Counts from 1 across the whole document, like in a SearchMatch.
+    pub line: usize,
+}
+
+impl IndexedFile {
+    // The file with its text, which is stored apart from it.
+    fn extracted(path: PathBuf, hash: String, pages: &[String], info: DocumentInfo) -> (IndexedFile, Vec<IndexedLine>) {
+        let lines: Vec<IndexedLine> = page_lines(pages)
+            .into_iter()
+            .map(|(page, text)| IndexedLine { page, text: text.to_string() })
+            .collect();
+        let file = IndexedFile { path, hash, pages: lines.last().map_or(0, |line| line.page), lines: lines.len(), info };
+        (file, lines)
+    }
+}
+
+/// The lines the way `pdf_text::page_lines` returns them.
+pub fn as_page_lines(lines: &[IndexedLine]) -> Vec<(usize, &str)> {
+    lines.iter().map(|line| (line.page, line.text.as_str())).collect()
+}
+
+impl Index {
+    pub fn path(root: &Path) -> PathBuf {
+        root.join(INDEX_DIR).join(INDEX_FILE)
+    }
+
+    pub fn exists(root: &Path) -> bool {
+        Index::path(root).is_file()
+    }
+
+    pub fn new() -> Index {
+        Index {
+            version: INDEX_VERSION,
+            built_at: now(),
+            catalogue: BTreeMap::new(),
+            files: Vec::new(),
+            postings: Vec::new(),
+            trigrams: HashMap::new(),
+            unsaved: HashMap::new(),
+        }
+    }
+
+    /// Updates the index that is stored under the root, or builds a new one when there is none or it can not be read.
+    /// `on_file` is called before each pdf that has to be extracted, to show progress.
+    pub fn build(root: &Path, on_file: &mut dyn FnMut(&Path)) -> Result<(Index, IndexChanges), SearchError> {
+        let mut index = Index::load(root).unwrap_or_else(|_| Index::new());
+        let changes = index.update(root, on_file)?;
+        Ok((index, changes))
+    }
+
+    /// Brings the index in line with the pdfs under the root, only new and changed pdfs are extracted.
+    pub fn update(&mut self, root: &Path, on_file: &mut dyn FnMut(&Path)) -> Result<IndexChanges, SearchError> {
+        self.update_except(root, &BTreeSet::new(), on_file)
+    }
+
+    /// Like `update`, but the pdfs that are still being written, relative to the root, are left as the index has them.
+    pub fn update_except(&mut self, root: &Path, busy: &BTreeSet<PathBuf>, on_file: &mut dyn FnMut(&Path)) -> Result<IndexChanges, SearchError> {
+        let mut changes = IndexChanges::default();
+        let mut old_catalogue = std::mem::take(&mut self.catalogue);
+        // With their number, their postings are kept.
+        let mut old_files: BTreeMap<PathBuf, (usize, IndexedFile)> = self.files.drain(..).enumerate().map(|(number, file)| (file.path.clone(), (number, file))).collect();
+        let mut files: BTreeMap<PathBuf, (Option<usize>, IndexedFile)> = BTreeMap::new();
+        let root_prefix = relative_path(root);
+        let on_disk: Vec<(PathBuf, PathBuf)> = find_files("*.pdf", &[root.to_path_buf()])?
+            .into_iter()
+            .filter(|path| is_pdf(path))
+            .map(|path| (path.strip_prefix(&root_prefix).unwrap_or(&path).to_path_buf(), path))
+            .collect();
+        let on_disk_paths: BTreeSet<&PathBuf> = on_disk.iter().map(|(relative, _)| relative).collect();
+
+        // The quick check first, a file with the same size and time is not read at all.
+        let mut to_check = Vec::new();
+        for (relative, path) in &on_disk {
+            if busy.contains(relative) {
+                if let Some(entry) = old_catalogue.remove(relative) {
+                    self.catalogue.insert(relative.clone(), entry);
+                }
+                if let Some((number, file)) = old_files.remove(relative) {
+                    files.insert(relative.clone(), (Some(number), file));
+                }
+                changes.postponed.push(relative.clone());
+                continue;
+            }
+            let (size, modified) = match size_and_modified(path) {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    changes.failed.push(FileWarning::new(Some(path.clone()), &err.to_string()));
+                    continue;
+                }
+            };
+            let unchanged = old_catalogue.get(relative).is_some_and(|entry| entry.size == size && entry.modified == modified);
+            if !unchanged {
+                to_check.push((relative, path, size, modified));
+                continue;
+            }
+            if let Some(entry) = old_catalogue.remove(relative) {
+                self.catalogue.insert(relative.clone(), entry);
+            }
+            if let Some((number, file)) = old_files.remove(relative) {
+                files.insert(relative.clone(), (Some(number), file));
+            }
+            changes.unchanged += 1;
+        }
+
+        for (relative, path, size, modified) in to_check {
+            let hash = match hash_file(path) {
+                Ok(hash) => hash,
+                Err(err) => {
+                    changes.failed.push(FileWarning::new(Some(path.clone()), &err.to_string()));
+                    continue;
+                }
+            };
+            let entry = CatalogueEntry { size, modified, hash, error: None };
+            // Same content under the same name, or under a name that is gone, is kept as it is.
+            let same_content = old_catalogue
+                .iter()
+                .find(|(old_path, old_entry)| {
+                    old_entry.hash == entry.hash && (*old_path == relative || !on_disk_paths.contains(old_path))
+                })
+                .map(|(old_path, _)| old_path.clone());
+            if let Some(old_path) = same_content {
+                let old_entry = old_catalogue.remove(&old_path).unwrap_or_else(|| entry.clone());
+                if let Some((number, mut file)) = old_files.remove(&old_path) {
+                    file.path = relative.clone();
+                    files.insert(relative.clone(), (Some(number), file));
+                }
+                if old_path == *relative {
+                    changes.unchanged += 1;
+                } else {
+                    changes.renamed.push((old_path, relative.clone()));
+                }
+                self.catalogue.insert(relative.clone(), CatalogueEntry { error: old_entry.error, ..entry });
+                continue;
+            }
+
+            if old_catalogue.remove(relative).is_some() {
+                changes.modified.push(relative.clone());
+            } else {
+                changes.added.push(relative.clone());
+            }
+            on_file(path);
+            let error = match extract(path) {
+                Ok((pages, info)) => {
+                    let (file, lines) = IndexedFile::extracted(relative.clone(), entry.hash.clone(), &pages, info);
+                    self.unsaved.insert(entry.hash.clone(), lines);
+                    files.insert(relative.clone(), (None, file));
+                    None
+                }
+                Err(err) => {
+                    changes.failed.push(FileWarning::new(Some(path.clone()), &err.to_string()));
+                    Some(err.to_string())
+                }
+            };
+            self.catalogue.insert(relative.clone(), CatalogueEntry { error, ..entry });
+        }
+
+        changes.removed = old_catalogue.into_keys().collect();
+        self.set_files(files.into_values().collect());
+        self.built_at = now();
+        Ok(changes)
+    }
+
+    // The file numbers change whenever a file is added or removed. The postings of the files that were already
+    // indexed, the ones with their old number, are numbered again. The new ones come from their text, which is not saved yet.
+    fn set_files(&mut self, files: Vec<(Option<usize>, IndexedFile)>) {
+        let numbers: HashMap<usize, usize> = files.iter().enumerate().filter_map(|(number, (old, _))| old.map(|old| (old, number))).collect();
+        let mut postings: BTreeMap<String, Vec<Posting>> = BTreeMap::new();
+        for (word, old_postings) in self.postings.drain(..) {
+            let kept: Vec<Posting> = old_postings
+                .into_iter()
+                .filter_map(|posting| numbers.get(&posting.file).map(|&file| Posting { file, ..posting }))
+                .collect();
+            if !kept.is_empty() {
+                postings.insert(word, kept);
+            }
+        }
+        for (file, (_, indexed_file)) in files.iter().enumerate().filter(|(_, (old, _))| old.is_none()) {
+            for (index, line) in self.unsaved.get(&indexed_file.hash).into_iter().flatten().enumerate() {
+                let words: BTreeSet<String> = tokenize(&line.text).collect();
+                for word in words {
+                    postings.entry(word).or_default().push(Posting { file, page: line.page, line: index + 1 });
+                }
+            }
+        }
+        for word_postings in postings.values_mut() {
+            word_postings.sort_by_key(|posting| (posting.file, posting.line));
+        }
+        self.files = files.into_iter().map(|(_, file)| file).collect();
+        self.postings = postings.into_iter().collect();
+        self.trigrams.clear();
+        for (position, (word, _)) in self.postings.iter().enumerate() {
+            let trigrams: BTreeSet<String> = trigrams(word).collect();
+            for trigram in trigrams {
+                self.trigrams.entry(trigram).or_default().push(position);
+            }
+        }
+    }
+
+    /// Where the word is, nothing when no line has it.
+    pub fn word_postings(&self, word: &str) -> &[Posting] {
+        match self.postings.binary_search_by(|(known, _)| known.as_str().cmp(word)) {
+            Ok(position) => &self.postings[position].1,
+            Err(_) => &[],
+        }
+    }
+
+    /// The text of the file at this position in `files`, line by line. Only this file's text is read.
+    pub fn lines(&self, root: &Path, file: usize) -> Result<Vec<IndexedLine>, SearchError> {
+        let hash = &self.files[file].hash;
+        if let Some(lines) = self.unsaved.get(hash) {
+            return Ok(lines.clone());
+        }
+        let path = text_path(root, hash);
+        let corrupt = |message: String| SearchError::CorruptIndex(format!("{}: {}, build the index again", path.display(), message));
+        let json = fs::read_to_string(&path).map_err(|err| corrupt(err.to_string()))?;
+        serde_json::from_str(&json).map_err(|err| corrupt(err.to_string()))
+    }
+
+    /// The pdfs under the root that were added, removed or touched since the index was updated.
+    /// Only sizes and times are compared, so a touched file counts even when its content is the same.
+    pub fn stale_files(&self, root: &Path) -> Result<Vec<PathBuf>, SearchError> {
+        let root_prefix = relative_path(root);
+        let mut seen = BTreeSet::new();
+        let mut stale = Vec::new();
+        for path in find_files("*.pdf", &[root.to_path_buf()])?.into_iter().filter(|path| is_pdf(path)) {
+            let relative = path.strip_prefix(&root_prefix).unwrap_or(&path).to_path_buf();
+            let unchanged = match (self.catalogue.get(&relative), size_and_modified(&path)) {
+                (Some(entry), Ok((size, modified))) => entry.size == size && entry.modified == modified,
+                _ => false,
+            };
+            if !unchanged {
+                stale.push(relative.clone());
+            }
+            seen.insert(relative);
+        }
+        stale.extend(self.catalogue.keys().filter(|path| !seen.contains(*path)).cloned());
+        Ok(stale)
+    }
+
+    pub fn load(root: &Path) -> Result<Index, SearchError> {
+        let path = Index::path(root);
+        let corrupt = |message: String| SearchError::CorruptIndex(format!("{}: {}", path.display(), message));
+        let json = fs::read_to_string(&path)?;
+        let index: Index = serde_json::from_str(&json).map_err(|err| corrupt(err.to_string()))?;
+        if index.version != INDEX_VERSION {
+            return Err(corrupt("it was made by another version of pdf_search, build it again".to_string()));
+        }
+        Ok(index)
+    }
+
+    /// Writes the text extracted since the index was loaded, then the index. Text of pdfs that are not in it any more is removed.
+    pub fn save(&mut self, root: &Path) -> Result<(), SearchError> {
+        let text_dir = root.join(INDEX_DIR).join(TEXT_DIR);
+        fs::create_dir_all(&text_dir)?;
+        let hashes: HashSet<&str> = self.files.iter().map(|file| file.hash.as_str()).collect();
+        // The text goes first, the index never points at text that is not there.
+        for (hash, lines) in self.unsaved.iter().filter(|(hash, _)| hashes.contains(hash.as_str())) {
+            write_replacing(&text_path(root, hash), &to_json(lines)?)?;
+        }
+        write_replacing(&Index::path(root), &to_json(self)?)?;
+        for entry in fs::read_dir(&text_dir)?.flatten() {
+            let path = entry.path();
+            let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+            if !hashes.contains(stem) {
+                let _ = fs::remove_file(&path);
+            }
+        }
+        self.unsaved.clear();
+        Ok(())
+    }
+
+    /// The (file, line) pairs where every word is part of a word on the line, like "bolt" in "bolts".
+    /// This is only a first cut, the lines still have to be checked with the search regex.
+    /// Words shorter than three characters are not looked up, they would be part of too many words.
+    /// None when no word is looked up, then every line is a candidate.
+    pub fn candidate_lines(&self, words: &[String]) -> Option<BTreeSet<(usize, usize)>> {
+        let mut candidates: Option<BTreeSet<(usize, usize)>> = None;
+        for word in words {
+            let Some(positions) = self.words_containing(word) else {
+                continue;
+            };
+            let lines: BTreeSet<(usize, usize)> = positions
+                .into_iter()
+                .flat_map(|position| self.postings[position].1.iter().map(|posting| (posting.file, posting.line)))
+                .collect();
+            candidates = Some(match candidates {
+                Some(candidates) => candidates.intersection(&lines).copied().collect(),
+                None => lines,
+            });
+        }
+        candidates
+    }
+
+    /// How many lines have every word of the term, for ranking. Short words are only counted where they are a whole word.
+    pub fn lines_with(&self, term: &str) -> usize {
+        let words: Vec<String> = tokenize(term).collect();
+        match self.candidate_lines(&words) {
+            Some(lines) => lines.len(),
+            None => words.iter().map(|word| self.word_postings(word).len()).min().unwrap_or(0),
+        }
+    }
+
+    // The positions in `postings` of the words the word is part of, None when it is too short to look up.
+    fn words_containing(&self, word: &str) -> Option<Vec<usize>> {
+        let mut positions: Option<Vec<usize>> = None;
+        for trigram in trigrams(word) {
+            let with = self.trigrams.get(&trigram).map_or(&[][..], Vec::as_slice);
+            positions = Some(match positions {
+                // Both are sorted.
+                Some(positions) => positions.into_iter().filter(|position| with.binary_search(position).is_ok()).collect(),
+                None => with.to_vec(),
+            });
+        }
+        // Having the three characters is not enough, they have to be in the right order.
+        positions.map(|positions| positions.into_iter().filter(|&position| self.postings[position].0.contains(word)).collect())
+    }
+
+    pub fn status_string(&self, root: &Path) -> String {
+        let pages: usize = self.files.iter().map(|file| file.pages).sum();
+        let lines: usize = self.files.iter().map(|file| file.lines).sum();
+        let mut status = format!(
+            "Index of {}: {} files, {} pages, {} lines, {} different words, updated {}.",
+            root.display(),
+            self.files.len(),
+            pages,
+            lines,
+            self.postings.len(),
+            time_ago(now().saturating_sub(self.built_at)),
+        );
+        for (path, entry) in &self.catalogue {
+            if let Some(error) = &entry.error {
+                status += &format!("\nNot indexed, {}: {}", path.display(), error);
+            }
+        }
+        status
+    }
+}
+
+impl IndexChanges {
+    pub fn summary(&self) -> String {
+        format!(
+            "{} added, {} modified, {} renamed, {} removed, {} unchanged, {} could not be read{}.",
+            self.added.len(),
+            self.modified.len(),
+            self.renamed.len(),
+            self.removed.len(),
+            self.unchanged,
+            self.failed.len(),
+            match self.postponed.len() {
+                0 => String::new(),
+                postponed => format!(", {} still being written", postponed),
+            },
+        )
+    }
+}
+
+/// What a file is recognised as unchanged by, its size and when it was last written in milliseconds.
+pub fn size_and_modified(path: &Path) -> io::Result<(u64, u64)> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0);
+    Ok((metadata.len(), modified))
+}
+
+fn to_json(value: &impl Serialize) -> Result<String, SearchError> {
+    serde_json::to_string(value).map_err(|err| SearchError::CorruptIndex(err.to_string()))
+}
+
+fn text_path(root: &Path, hash: &str) -> PathBuf {
+    root.join(INDEX_DIR).join(TEXT_DIR).join(format!("{}.json", hash))
+}
+
+// Written next to the old file first, so a search running meanwhile never sees half of one.
+fn write_replacing(path: &Path, contents: &str) -> io::Result<()> {
+    let temporary = path.with_extension("json.tmp");
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path)
+}
+
+// Every three characters in a row, none for a word shorter than that.
+fn trigrams(word: &str) -> impl Iterator<Item = String> + '_ {
+    let chars: Vec<char> = word.chars().collect();
+    (0..chars.len().saturating_sub(2)).map(move |start| chars[start..start + 3].iter().collect())
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Splits text into lowercase words, anything that is not a letter or a digit separates them.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0)
+}
+
+fn time_ago(seconds: u64) -> String {
+    let (amount, unit) = match seconds {
+        0..=59 => return "just now".to_string(),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86399 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    format!("{} {}{} ago", amount, unit, if amount == 1 { "" } else { "s" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_index() -> Index {
+        let mut index = Index::new();
+        let pdfs = [("a.pdf", vec!["Bolts and nuts\ntorque".to_string(), "bolt torque, M8".to_string()]), ("b.pdf", vec!["Torque table".to_string()])];
+        let files = pdfs
+            .into_iter()
+            .map(|(path, pages)| {
+                let (file, lines) = IndexedFile::extracted(PathBuf::from(path), path.to_string(), &pages, DocumentInfo::default());
+                index.unsaved.insert(file.hash.clone(), lines);
+                (None, file)
+            })
+            .collect();
+        index.set_files(files);
+        index
+    }
+
+    fn temp_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("pdf_search_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("manuals")).unwrap();
+        root
+    }
+
+    #[test]
+    fn tokenize_words() {
+        let words: Vec<String> = tokenize("Bolt-torque: 25 Nm, ÅL").collect();
+        assert_eq!(words, vec!["bolt", "torque", "25", "nm", "ål"]);
+    }
+
+    #[test]
+    fn postings() {
+        let index = test_index();
+        let expected = vec![
+            Posting { file: 0, page: 1, line: 2 },
+            Posting { file: 0, page: 2, line: 3 },
+            Posting { file: 1, page: 1, line: 1 },
+        ];
+        assert_eq!(index.word_postings("torque"), expected);
+        assert_eq!(index.word_postings("torq"), []);
+        assert_eq!(index.files[0].pages, 2);
+        let lines = index.lines(Path::new("nowhere"), 0).unwrap();
+        assert_eq!(as_page_lines(&lines)[2], (2, "bolt torque, M8"));
+    }
+
+    #[test]
+    fn candidates_need_every_word() {
+        let index = test_index();
+        let lines: Vec<(usize, usize)> = index.candidate_lines(&["bolt".to_string()]).unwrap().into_iter().collect();
+        assert_eq!(lines, vec![(0, 1), (0, 3)]);
+        let lines: Vec<(usize, usize)> = index.candidate_lines(&["orq".to_string(), "bolt".to_string()]).unwrap().into_iter().collect();
+        assert_eq!(lines, vec![(0, 3)]);
+        // Part of a word, not at its start.
+        let lines: Vec<(usize, usize)> = index.candidate_lines(&["olts".to_string()]).unwrap().into_iter().collect();
+        assert_eq!(lines, vec![(0, 1)]);
+        assert!(index.candidate_lines(&["bolt".to_string(), "table".to_string()]).unwrap().is_empty());
+        // Too short to narrow anything down.
+        assert_eq!(index.candidate_lines(&["m8".to_string()]), None);
+        assert_eq!(index.candidate_lines(&[]), None);
+    }
+
+    #[test]
+    fn build_save_and_load() {
+        let root = temp_root("index_build");
+        fs::copy("test_assets/test.pdf", root.join("manuals/test.pdf")).unwrap();
+        fs::write(root.join("broken.pdf"), "not a pdf").unwrap();
+        let mut seen = Vec::new();
+        let (mut index, changes) = Index::build(&root, &mut |path| seen.push(path.to_path_buf())).unwrap();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(changes.added.len(), 2);
+        assert_eq!(changes.failed.len(), 1);
+        assert_eq!(index.files.len(), 1);
+        assert_eq!(index.files[0].path, PathBuf::from("manuals/test.pdf"));
+        assert!(index.catalogue[Path::new("broken.pdf")].error.is_some());
+        assert!(!index.word_postings("subheading").is_empty());
+        assert!(!Index::exists(&root));
+        let lines = index.lines(&root, 0).unwrap();
+        index.save(&root).unwrap();
+        let loaded = Index::load(&root).unwrap();
+        assert_eq!(loaded, index);
+        assert_eq!(loaded.lines(&root, 0).unwrap(), lines);
+        // The text of a pdf that is not indexed any more is removed with the next save.
+        fs::write(text_path(&root, "gone"), "[]").unwrap();
+        index.save(&root).unwrap();
+        assert!(!text_path(&root, "gone").exists());
+        assert!(text_path(&root, &index.files[0].hash).exists());
+        assert!(index.status_string(&root).contains("1 files, 1 pages"));
+        assert!(index.status_string(&root).contains("Not indexed, broken.pdf"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn update_only_extracts_what_changed() {
+        let root = temp_root("index_update");
+        fs::copy("test_assets/test.pdf", root.join("manuals/test.pdf")).unwrap();
+        fs::write(root.join("broken.pdf"), "not a pdf").unwrap();
+        let mut index = Index::new();
+        index.update(&root, &mut |_| {}).unwrap();
+
+        // Nothing changed, and the broken pdf is not tried again.
+        let mut extracted = Vec::new();
+        let changes = index.update(&root, &mut |path| extracted.push(path.to_path_buf())).unwrap();
+        assert!(extracted.is_empty());
+        assert_eq!(changes.unchanged, 2);
+        assert!(index.stale_files(&root).unwrap().is_empty());
+
+        // A touched file is hashed but not extracted, a moved one is found by its hash.
+        File::options().write(true).open(root.join("broken.pdf")).unwrap().set_modified(SystemTime::now() + std::time::Duration::from_secs(5)).unwrap();
+        fs::rename(root.join("manuals/test.pdf"), root.join("renamed.pdf")).unwrap();
+        assert_eq!(index.stale_files(&root).unwrap().len(), 3);
+        let changes = index.update(&root, &mut |path| extracted.push(path.to_path_buf())).unwrap();
+        assert!(extracted.is_empty());
+        assert_eq!(changes.renamed, vec![(PathBuf::from("manuals/test.pdf"), PathBuf::from("renamed.pdf"))]);
+        assert_eq!(changes.unchanged, 1);
+        assert_eq!(index.files[0].path, PathBuf::from("renamed.pdf"));
+        assert_eq!(index.word_postings("subheading")[0].file, 0);
+
+        // A fixed pdf is extracted again, a deleted one is dropped.
+        fs::copy("test_assets/test.pdf", root.join("broken.pdf")).unwrap();
+        let changes = index.update(&root, &mut |path| extracted.push(path.to_path_buf())).unwrap();
+        assert_eq!(extracted, vec![root.join("broken.pdf")]);
+        assert_eq!(changes.modified, vec![PathBuf::from("broken.pdf")]);
+        fs::remove_file(root.join("renamed.pdf")).unwrap();
+        let changes = index.update(&root, &mut |_| {}).unwrap();
+        assert_eq!(changes.removed, vec![PathBuf::from("renamed.pdf")]);
+        assert_eq!(index.catalogue.len(), 1);
+        assert_eq!(index.catalogue[Path::new("broken.pdf")].error, None);
+        assert_eq!(index.files[0].path, PathBuf::from("broken.pdf"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn pdfs_being_written_wait() {
+        let root = temp_root("index_busy");
+        fs::copy("test_assets/test.pdf", root.join("done.pdf")).unwrap();
+        let mut index = Index::new();
+        index.update(&root, &mut |_| {}).unwrap();
+        let hash = index.catalogue[Path::new("done.pdf")].hash.clone();
+
+        // Half of a new pdf, and one that is being written over.
+        let pdf = fs::read("test_assets/test.pdf").unwrap();
+        fs::write(root.join("copying.pdf"), &pdf[..pdf.len() / 2]).unwrap();
+        fs::write(root.join("done.pdf"), &pdf[..10]).unwrap();
+        let busy: BTreeSet<PathBuf> = [PathBuf::from("copying.pdf"), PathBuf::from("done.pdf")].into();
+        let mut extracted = Vec::new();
+        let changes = index.update_except(&root, &busy, &mut |path| extracted.push(path.to_path_buf())).unwrap();
+        assert!(extracted.is_empty());
+        assert_eq!(changes.postponed, vec![PathBuf::from("copying.pdf"), PathBuf::from("done.pdf")]);
+        assert!(changes.summary().ends_with("could not be read, 2 still being written."));
+        assert!(!index.catalogue.contains_key(Path::new("copying.pdf")));
+        assert_eq!(index.catalogue[Path::new("done.pdf")].hash, hash);
+        assert_eq!(index.files.len(), 1);
+
+        // Once they are done they are indexed.
+        fs::copy("test_assets/test.pdf", root.join("copying.pdf")).unwrap();
+        fs::copy("test_assets/test.pdf", root.join("done.pdf")).unwrap();
+        let changes = index.update(&root, &mut |_| {}).unwrap();
+        assert_eq!(changes.added, vec![PathBuf::from("copying.pdf")]);
+        assert_eq!(index.files.len(), 2);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn time_ago_units() {
+        assert_eq!(time_ago(5), "just now");
+        assert_eq!(time_ago(60), "1 minute ago");
+        assert_eq!(time_ago(7300), "2 hours ago");
+    }
+}
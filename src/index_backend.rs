@@ -0,0 +1,251 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use crate::index::{as_page_lines, tokenize, Index};
+use crate::metadata::DocumentInfo;
+use crate::ranking::Corpus;
+use crate::pdf_text::{extract_pages, line_match, search_pdf, with_metadata};
+use crate::search_backend::{relative_path, CancelHandle, FileGlob, SearchBackend, SearchedFile};
+use crate::search_error::SearchError;
+use crate::search_options::{SearchOptions, Syntax};
+use crate::search_status::{FileWarning, SearchStatus};
+
+/// Answers searches from the indexes built with `pdf_search index build`. Only the pdfs that were added
+/// or changed since the index was built are extracted.
+pub struct IndexBackend {
+    indexes: Vec<(PathBuf, Index)>,
+    // Of all the indexes, for ranking.
+    lines: usize,
+    words: usize,
+}
+
+impl IndexBackend {
+    pub fn new(indexes: Vec<(PathBuf, Index)>) -> IndexBackend {
+        let lines = indexes.iter().flat_map(|(_, index)| &index.files).map(|file| file.lines).sum();
+        // The postings have every word once per line it is on.
+        let words = indexes.iter().flat_map(|(_, index)| &index.postings).map(|(_, postings)| postings.len()).sum();
+        IndexBackend { indexes, lines, words }
+    }
+
+    /// Loads the index of every root. None when one of them has no index, it can not answer for that root.
+    pub fn open(roots: &[PathBuf]) -> Result<Option<IndexBackend>, SearchError> {
+        if !roots.iter().all(|root| Index::exists(root)) {
+            return Ok(None);
+        }
+        let indexes = roots
+            .iter()
+            .map(|root| Ok((root.clone(), Index::load(root)?)))
+            .collect::<Result<Vec<_>, SearchError>>()?;
+        Ok(Some(IndexBackend::new(indexes)))
+    }
+
+    // The root, the index and the number of the file in it.
+    fn indexed_file(&self, path: &Path) -> Option<(&Path, &Index, usize)> {
+        self.indexes.iter().find_map(|(root, index)| {
+            let number = index.files.iter().position(|file| relative_path(&root.join(&file.path)) == path)?;
+            Some((root.as_path(), index, number))
+        })
+    }
+}
+
+impl SearchBackend for IndexBackend {
+    fn search_files(&self, options: &SearchOptions, cancel: &CancelHandle, on_file: &mut dyn FnMut(SearchedFile)) -> Result<SearchStatus, SearchError> {
+        let regex = options.regex()?;
+        let file_glob = FileGlob::new(&options.glob)?;
+        let words = literal_words(options);
+        let mut searched_any = false;
+        let mut found = false;
+        let mut warnings = Vec::new();
+        for (root, index) in &self.indexes {
+            // What the index has for these is out of date, or the pdf is gone.
+            let stale: BTreeSet<PathBuf> = index.stale_files(root)?.into_iter().collect();
+            let candidates = words.as_ref().and_then(|words| index.candidate_lines(words));
+            for (file_number, file) in index.files.iter().enumerate() {
+                if cancel.is_cancelled() {
+                    return Ok(SearchStatus::Cancelled(vec![]));
+                }
+                if !file_glob.is_match(&file.path) || stale.contains(&file.path) {
+                    continue;
+                }
+                searched_any = true;
+                let path = relative_path(&root.join(&file.path));
+                let line_numbers: Vec<usize> = match &candidates {
+                    Some(candidates) => candidates
+                        .range((file_number, 0)..(file_number + 1, 0))
+                        .map(|&(_, line)| line)
+                        .collect(),
+                    None => (1..=file.lines).collect(),
+                };
+                // The text is only read for the pdfs that can have a match.
+                let indexed_lines = if line_numbers.is_empty() {
+                    vec![]
+                } else {
+                    index.lines(root, file_number).unwrap_or_else(|err| {
+                        warnings.push(FileWarning::new(Some(path.clone()), &err.to_string()));
+                        vec![]
+                    })
+                };
+                let lines = as_page_lines(&indexed_lines);
+                let text_matches: Vec<_> = line_numbers
+                    .into_iter()
+                    .map(|line| line - 1)
+                    .filter(|&index| lines.get(index).is_some_and(|line| regex.is_match(line.1)))
+                    .map(|index| line_match(Path::new(&path), &lines, index, &regex, options.context))
+                    .collect();
+                let search_matches = with_metadata(file.info.search(&path, &regex), text_matches);
+                found |= !search_matches.is_empty();
+                on_file(SearchedFile { path, search_matches });
+            }
+            for relative in stale.iter().filter(|relative| file_glob.is_match(relative)) {
+                let path = relative_path(&root.join(relative));
+                if !path.is_file() {
+                    continue;
+                }
+                if cancel.is_cancelled() {
+                    return Ok(SearchStatus::Cancelled(vec![]));
+                }
+                searched_any = true;
+                let search_matches = search_pdf(&path, &regex, options.context).unwrap_or_else(|err| {
+                    warnings.push(FileWarning::new(Some(path.clone()), &err.to_string()));
+                    vec![]
+                });
+                found |= !search_matches.is_empty();
+                on_file(SearchedFile { path, search_matches });
+            }
+        }
+        if !searched_any {
+            return Ok(SearchStatus::NoFilesFound);
+        }
+        Ok(SearchStatus::from_results(found, warnings))
+    }
+
+    // Stored in the index, so the pdf is not opened.
+    fn document_info(&self, path: &Path) -> Option<DocumentInfo> {
+        self.indexed_file(path).map(|(_, index, number)| index.files[number].info.clone())
+    }
+
+    // Pages without any text at the end of the pdf are not in the index, so they are not shown.
+    fn pages(&self, path: &Path) -> Result<Vec<String>, SearchError> {
+        let Some((root, index, number)) = self.indexed_file(path) else {
+            return Ok(extract_pages(path)?);
+        };
+        let lines = index.lines(root, number)?;
+        let mut pages = vec![String::new(); index.files[number].pages];
+        for line in &lines {
+            pages[line.page - 1].push_str(&line.text);
+            pages[line.page - 1].push('\n');
+        }
+        Ok(pages)
+    }
+
+    fn corpus(&self) -> Option<&dyn Corpus> {
+        Some(self)
+    }
+}
+
+// The pdfs added since the index was built are not in it, they are few enough not to matter.
+impl Corpus for IndexBackend {
+    fn lines(&self) -> usize {
+        self.lines
+    }
+
+    fn words_per_line(&self) -> f64 {
+        self.words as f64 / self.lines.max(1) as f64
+    }
+
+    fn lines_with(&self, term: &str) -> usize {
+        self.indexes.iter().map(|(_, index)| index.lines_with(term)).sum()
+    }
+}
+
+// The words every matching line has to contain. Only known when the search term is plain text,
+// for a real regex every line is checked.
+fn literal_words(options: &SearchOptions) -> Option<Vec<String>> {
+    let term = &options.search_term;
+    // The words of a query can be on different lines of the page.
+    match options.syntax {
+        Syntax::Query => return None,
+        Syntax::Regex if regex::escape(term) != *term => return None,
+        _ => {}
+    }
+    let words: Vec<String> = tokenize(term).collect();
+    (!words.is_empty()).then_some(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf_text::PdfTextBackend;
+
+    fn test_assets_index() -> IndexBackend {
+        let root = PathBuf::from("test_assets");
+        let (index, _) = Index::build(&root, &mut |_| {}).unwrap();
+        IndexBackend::new(vec![(root, index)])
+    }
+
+    #[test]
+    fn same_matches_as_extracting() {
+        let backend = test_assets_index();
+        for term in ["subheading", "is a sub", "te.t", "Test"] {
+            let mut options = SearchOptions::new("*.pdf", term);
+            options.paths = vec![PathBuf::from("test_assets")];
+            assert_eq!(backend.search(&options).unwrap(), PdfTextBackend::new().search(&options).unwrap(), "{}", term);
+        }
+        let path = Path::new("test_assets/test.pdf");
+        assert_eq!(backend.document_info(path), PdfTextBackend::new().document_info(path));
+        assert_eq!(backend.document_info(Path::new("test_assets/other.pdf")), None);
+        let pages = backend.pages(path).unwrap();
+        assert_eq!(pages.len(), PdfTextBackend::new().pages(path).unwrap().len());
+        assert!(pages[0].contains("This is a subheading - Test\n"));
+    }
+
+    #[test]
+    fn corpus_from_the_index() {
+        let backend = test_assets_index();
+        let corpus = backend.corpus().unwrap();
+        assert!(corpus.lines() > 2);
+        assert!(corpus.words_per_line() > 1.0);
+        assert_eq!(corpus.lines_with("subheading"), 1);
+        assert_eq!(corpus.lines_with("is a sub"), 1);
+        assert!(corpus.lines_with("test") >= 2);
+        assert_eq!(corpus.lines_with("no such word"), 0);
+    }
+
+    #[test]
+    fn changes_since_the_build_are_extracted() {
+        let root = std::env::temp_dir().join(format!("pdf_search_stale_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::copy("test_assets/test.pdf", root.join("a.pdf")).unwrap();
+        std::fs::copy("test_assets/test.pdf", root.join("gone.pdf")).unwrap();
+        let (index, _) = Index::build(&root, &mut |_| {}).unwrap();
+        let backend = IndexBackend::new(vec![(root.clone(), index)]);
+        std::fs::remove_file(root.join("gone.pdf")).unwrap();
+        std::fs::copy("test_assets/test.pdf", root.join("new.pdf")).unwrap();
+        let mut options = SearchOptions::new("*.pdf", "subheading");
+        options.paths = vec![root.clone()];
+        let (status, search_matches) = backend.search(&options).unwrap();
+        assert_eq!(status, SearchStatus::Found);
+        let paths: Vec<PathBuf> = search_matches.iter().map(|search_match| search_match.path.clone()).collect();
+        assert_eq!(paths, vec![root.join("a.pdf"), root.join("new.pdf")]);
+    }
+
+    #[test]
+    fn glob_and_no_matches() {
+        // The index only has pdfs, default_backend doesn't use it for other globs.
+        let backend = test_assets_index();
+        let (status, _) = backend.search(&SearchOptions::new("*.md", "test")).unwrap();
+        assert_eq!(status, SearchStatus::NoFilesFound);
+        let (status, search_matches) = backend.search(&SearchOptions::new("test.pdf", "no such words")).unwrap();
+        assert_eq!(status, SearchStatus::NoMatchesFound);
+        assert!(search_matches.is_empty());
+    }
+
+    #[test]
+    fn literal_words_only_for_plain_text() {
+        assert_eq!(literal_words(&SearchOptions::new("*.pdf", "Bolt torque")), Some(vec!["bolt".to_string(), "torque".to_string()]));
+        assert_eq!(literal_words(&SearchOptions::new("*.pdf", "bolt.*")), None);
+        let mut options = SearchOptions::new("*.pdf", "M8 (steel)");
+        options.syntax = Syntax::Fixed;
+        assert_eq!(literal_words(&options), Some(vec!["m8".to_string(), "steel".to_string()]));
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::path::{self, Path, PathBuf};
+use std::{env, fs, io};
+use serde::{Deserialize, Serialize};
+use crate::index::size_and_modified;
+use crate::metadata::DocumentInfo;
+
+const CACHE_FILE: &str = "document_info.json";
+// Bumped when the format changes, a cache with another version is started over.
+const CACHE_VERSION: u32 = 1;
+
+/// What pdfs say about themselves, kept on disk between runs. Reading it means loading the whole pdf,
+/// so it is only read again when the size or modification time of the pdf changes.
+#[derive(Debug, Default)]
+pub struct InfoCache {
+    // None keeps it in memory only.
+    path: Option<PathBuf>,
+    file: CacheFile,
+    changed: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: HashMap<PathBuf, CachedInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedInfo {
+    size: u64,
+    // Milliseconds since the unix epoch, like in the index catalogue.
+    modified: u64,
+    // None when the pdf could not be read, it is not tried again until it changes.
+    info: Option<DocumentInfo>,
+}
+
+impl InfoCache {
+    pub fn in_memory() -> InfoCache {
+        InfoCache::default()
+    }
+
+    /// The cache stored at the path. One that is not there, can't be read or is from another version starts empty.
+    pub fn load(path: PathBuf) -> InfoCache {
+        let file = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<CacheFile>(&json).ok())
+            .filter(|file| file.version == CACHE_VERSION)
+            .unwrap_or(CacheFile { version: CACHE_VERSION, entries: HashMap::new() });
+        InfoCache { path: Some(path), file, changed: false }
+    }
+
+    /// The info of the pdf as it is now, Some(None) when it could not be read. None when it has to be read (again).
+    pub fn get(&self, path: &Path) -> Option<Option<DocumentInfo>> {
+        let (size, modified) = size_and_modified(path).ok()?;
+        self.file
+            .entries
+            .get(&key(path))
+            .filter(|cached| cached.size == size && cached.modified == modified)
+            .map(|cached| cached.info.clone())
+    }
+
+    /// Remembers the info with the size and time the pdf has now. A pdf that is gone is not remembered.
+    pub fn insert(&mut self, path: &Path, info: Option<DocumentInfo>) {
+        if let Ok((size, modified)) = size_and_modified(path) {
+            self.file.entries.insert(key(path), CachedInfo { size, modified, info });
+            self.changed = true;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.file.entries.len()
+    }
+
+    /// Writes the cache when something was added since it was loaded. Pdfs that are gone are left out.
+    pub fn save(&mut self) -> io::Result<()> {
+        let Some(path) = self.path.as_ref().filter(|_| self.changed) else {
+            return Ok(());
+        };
+        self.file.entries.retain(|pdf, _| pdf.is_file());
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string(&self.file).map_err(io::Error::other)?;
+        // Written next to the old cache first, so another search never reads half of one.
+        let temporary = path.with_extension(format!("json.{}.tmp", std::process::id()));
+        fs::write(&temporary, json)?;
+        fs::rename(&temporary, path)?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+// The same pdf searched from another folder is the same entry.
+fn key(path: &Path) -> PathBuf {
+    path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// `$XDG_CACHE_HOME/pdf_search/document_info.json`, or where the cache folder is when it is not set.
+pub fn user_cache_path() -> Option<PathBuf> {
+    let cache_home = env::var_os("XDG_CACHE_HOME")
+        .filter(|folder| !folder.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("LOCALAPPDATA").filter(|_| cfg!(windows)).map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_home.join("pdf_search").join(CACHE_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kept_until_the_pdf_changes() {
+        let folder = env::temp_dir().join(format!("pdf_search_info_cache_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        let pdf = folder.join("a.pdf");
+        fs::copy("test_assets/test.pdf", &pdf).unwrap();
+        let path = folder.join("cache").join(CACHE_FILE);
+        let mut cache = InfoCache::load(path.clone());
+        assert_eq!(cache.get(&pdf), None);
+        let info = DocumentInfo { title: Some("Torque table".to_string()), ..DocumentInfo::default() };
+        cache.insert(&pdf, Some(info.clone()));
+        cache.insert(&folder.join("gone.pdf"), None);
+        cache.save().unwrap();
+        // The next run finds it.
+        let cache = InfoCache::load(path.clone());
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&pdf), Some(Some(info)));
+        fs::write(&pdf, "not a pdf any more").unwrap();
+        assert_eq!(cache.get(&pdf), None);
+        fs::write(&path, "{").unwrap();
+        assert_eq!(InfoCache::load(path).len(), 0);
+    }
+}
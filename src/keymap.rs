@@ -0,0 +1,476 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Everything a key can do in the tui.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Action {
+    Down,
+    Up,
+    PageDown,
+    PageUp,
+    First,
+    Last,
+    NextFile,
+    PreviousFile,
+    // Opens the match in the viewer, or picks it with --print.
+    Open,
+    EditQuery,
+    Filter,
+    // The ones for typing in a field, see is_editing.
+    EndEditing,
+    NextField,
+    PreviousField,
+    DeleteLast,
+    ClearField,
+    Preview,
+    PreviewDown,
+    PreviewUp,
+    NextPage,
+    PreviousPage,
+    Case,
+    Syntax,
+    Order,
+    Help,
+    // Stops a running search, quits when there is none.
+    Cancel,
+    Quit,
+}
+
+impl Action {
+    /// In the order the help shows them.
+    pub const ALL: [Action; 27] = [
+        Action::Down,
+        Action::Up,
+        Action::PageDown,
+        Action::PageUp,
+        Action::First,
+        Action::Last,
+        Action::NextFile,
+        Action::PreviousFile,
+        Action::Open,
+        Action::EditQuery,
+        Action::Filter,
+        Action::EndEditing,
+        Action::NextField,
+        Action::PreviousField,
+        Action::DeleteLast,
+        Action::ClearField,
+        Action::Preview,
+        Action::PreviewDown,
+        Action::PreviewUp,
+        Action::NextPage,
+        Action::PreviousPage,
+        Action::Case,
+        Action::Syntax,
+        Action::Order,
+        Action::Help,
+        Action::Cancel,
+        Action::Quit,
+    ];
+
+    /// The name in the `[keys]` table of the config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Down => "down",
+            Action::Up => "up",
+            Action::PageDown => "page_down",
+            Action::PageUp => "page_up",
+            Action::First => "first",
+            Action::Last => "last",
+            Action::NextFile => "next_file",
+            Action::PreviousFile => "previous_file",
+            Action::Open => "open",
+            Action::EditQuery => "search",
+            Action::Filter => "filter",
+            Action::EndEditing => "done",
+            Action::NextField => "next_field",
+            Action::PreviousField => "previous_field",
+            Action::DeleteLast => "delete_last",
+            Action::ClearField => "clear_field",
+            Action::Preview => "preview",
+            Action::PreviewDown => "preview_down",
+            Action::PreviewUp => "preview_up",
+            Action::NextPage => "next_page",
+            Action::PreviousPage => "previous_page",
+            Action::Case => "case",
+            Action::Syntax => "syntax",
+            Action::Order => "order",
+            Action::Help => "help",
+            Action::Cancel => "cancel",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Down => "next match",
+            Action::Up => "previous match",
+            Action::PageDown => "a screen of matches down",
+            Action::PageUp => "a screen of matches up",
+            Action::First => "first match",
+            Action::Last => "last match",
+            Action::NextFile => "first match in the next pdf",
+            Action::PreviousFile => "first match in the previous pdf",
+            Action::Open => "open the match, or pick it with --print",
+            Action::EditQuery => "edit the search term and glob",
+            Action::Filter => "filter the results",
+            Action::EndEditing => "typing: back to the list",
+            Action::NextField => "typing: next field",
+            Action::PreviousField => "typing: previous field",
+            Action::DeleteLast => "typing: delete the last character",
+            Action::ClearField => "typing: clear the field",
+            Action::Preview => "preview context, page or image",
+            Action::PreviewDown => "scroll the page preview down",
+            Action::PreviewUp => "scroll the page preview up",
+            Action::NextPage => "next page in the preview",
+            Action::PreviousPage => "previous page in the preview",
+            Action::Case => "ignore case, match case or smart case",
+            Action::Syntax => "regex, plain text or query",
+            Action::Order => "relevance, path or page order",
+            Action::Help => "show these keys",
+            Action::Cancel => "stop the search, quit when there is none",
+            Action::Quit => "quit",
+        }
+    }
+
+    /// Only done while typing in a field, so their keys can do something else in the list.
+    pub fn is_editing(&self) -> bool {
+        matches!(self, Action::EndEditing | Action::NextField | Action::PreviousField | Action::DeleteLast | Action::ClearField)
+    }
+
+    /// While typing the keys without ctrl or alt are text, the other keys still do these.
+    pub fn while_typing(&self) -> bool {
+        !matches!(self, Action::Open | Action::EditQuery | Action::Filter | Action::Preview | Action::Cancel | Action::Quit)
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Action::ALL
+            .iter()
+            .find(|action| action.name() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown action '{}' in keys, expected one of {}", s, Action::ALL.map(|action| action.name()).join(", ")))
+    }
+}
+
+/// A key with the modifiers held down, written like `ctrl-d`, `alt-<`, `G` or `pagedown`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+// The names of the keys that are not characters.
+const KEY_NAMES: [(&str, KeyCode); 15] = [
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("space", KeyCode::Char(' ')),
+];
+
+impl Key {
+    // Terminals send G as shift-G and some send shift with ?, the character already says it.
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Key {
+        let modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => Key { code: KeyCode::Char(c.to_ascii_uppercase()), modifiers: modifiers - KeyModifiers::SHIFT },
+            KeyCode::BackTab => Key { code, modifiers: modifiers - KeyModifiers::SHIFT },
+            _ => Key { code, modifiers },
+        }
+    }
+
+    pub fn from_event(event: KeyEvent) -> Key {
+        Key::new(event.code, event.modifiers)
+    }
+}
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unknown = || format!("unknown key '{}', expected something like j, G, ctrl-d, alt-< or pagedown", s);
+        let mut modifiers = KeyModifiers::NONE;
+        let mut rest = s;
+        // A key of its own can be a -, like in ctrl--.
+        while rest.chars().count() > 1 {
+            let prefix = |name: &str| rest.get(..name.len()).is_some_and(|start| start.eq_ignore_ascii_case(name));
+            let (modifier, length) = if prefix("ctrl-") {
+                (KeyModifiers::CONTROL, 5)
+            } else if prefix("alt-") {
+                (KeyModifiers::ALT, 4)
+            } else if prefix("shift-") {
+                (KeyModifiers::SHIFT, 6)
+            } else {
+                break;
+            };
+            modifiers |= modifier;
+            rest = &rest[length..];
+        }
+        let mut chars = rest.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c),
+            _ => {
+                let name = rest.to_lowercase();
+                let function = name.strip_prefix('f').and_then(|number| number.parse().ok()).filter(|number| (1..=12).contains(number));
+                match function {
+                    Some(number) => KeyCode::F(number),
+                    None => KEY_NAMES.iter().find(|(known, _)| *known == name).map(|(_, code)| *code).ok_or_else(unknown)?,
+                }
+            }
+        };
+        Ok(Key::new(code, modifiers))
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (modifier, name) in [(KeyModifiers::CONTROL, "ctrl-"), (KeyModifiers::ALT, "alt-"), (KeyModifiers::SHIFT, "shift-")] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}", name)?;
+            }
+        }
+        match (self.code, KEY_NAMES.iter().find(|(_, code)| *code == self.code)) {
+            (_, Some((name, _))) => write!(f, "{}", name),
+            (KeyCode::F(number), _) => write!(f, "f{}", number),
+            (KeyCode::Char(c), _) => write!(f, "{}", c),
+            (code, _) => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// The bindings to start from, the `[keys]` of the config change single actions.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Preset {
+    Default,
+    Vim,
+    Emacs,
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "default" => Ok(Preset::Default),
+            "vim" => Ok(Preset::Vim),
+            "emacs" => Ok(Preset::Emacs),
+            _ => Err(format!("unknown key preset '{}', expected default, vim or emacs", s)),
+        }
+    }
+}
+
+impl Preset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Default => "default",
+            Preset::Vim => "vim",
+            Preset::Emacs => "emacs",
+        }
+    }
+
+    // The first key of an action is the one the titles mention.
+    fn bindings(&self) -> Vec<(&'static str, Action)> {
+        let own = match self {
+            Preset::Default => vec![
+                ("j", Action::Down),
+                ("k", Action::Up),
+                ("g", Action::First),
+                ("G", Action::Last),
+                ("n", Action::NextFile),
+                ("N", Action::PreviousFile),
+                ("ctrl-d", Action::PreviewDown),
+                ("ctrl-u", Action::PreviewUp),
+            ],
+            Preset::Vim => vec![
+                ("j", Action::Down),
+                ("k", Action::Up),
+                ("ctrl-f", Action::PageDown),
+                ("ctrl-b", Action::PageUp),
+                ("g", Action::First),
+                ("G", Action::Last),
+                ("}", Action::NextFile),
+                ("{", Action::PreviousFile),
+                ("ctrl-d", Action::PreviewDown),
+                ("ctrl-u", Action::PreviewUp),
+                ("l", Action::NextPage),
+                ("h", Action::PreviousPage),
+            ],
+            Preset::Emacs => vec![
+                ("ctrl-n", Action::Down),
+                ("ctrl-p", Action::Up),
+                ("ctrl-v", Action::PageDown),
+                ("alt-v", Action::PageUp),
+                ("alt-<", Action::First),
+                ("alt->", Action::Last),
+                ("alt-}", Action::NextFile),
+                ("alt-{", Action::PreviousFile),
+                ("ctrl-s", Action::EditQuery),
+                ("ctrl-g", Action::Cancel),
+                ("ctrl-g", Action::EndEditing),
+            ],
+        };
+        // Every preset has the arrows and the keys the tui always had.
+        let common = [
+            ("down", Action::Down),
+            ("up", Action::Up),
+            ("pagedown", Action::PageDown),
+            ("pageup", Action::PageUp),
+            ("home", Action::First),
+            ("end", Action::Last),
+            ("enter", Action::Open),
+            ("/", Action::EditQuery),
+            ("f", Action::Filter),
+            ("enter", Action::EndEditing),
+            ("esc", Action::EndEditing),
+            ("tab", Action::NextField),
+            ("backtab", Action::PreviousField),
+            ("backspace", Action::DeleteLast),
+            ("ctrl-u", Action::ClearField),
+            ("p", Action::Preview),
+            ("shift-down", Action::PreviewDown),
+            ("shift-up", Action::PreviewUp),
+            ("right", Action::NextPage),
+            ("left", Action::PreviousPage),
+            ("f2", Action::Case),
+            ("f3", Action::Syntax),
+            ("f4", Action::Order),
+            ("?", Action::Help),
+            ("f1", Action::Help),
+            ("esc", Action::Cancel),
+            ("q", Action::Quit),
+        ];
+        common.into_iter().chain(own).collect()
+    }
+}
+
+/// Which key does what in the tui.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Keymap {
+    bindings: Vec<(Key, Action)>,
+}
+
+impl Keymap {
+    pub fn preset(preset: Preset) -> Keymap {
+        let mut keymap = Keymap { bindings: vec![] };
+        for (key, action) in preset.bindings() {
+            keymap.bindings.push((key.parse().unwrap(), action));
+        }
+        keymap
+    }
+
+    /// The action gets these keys instead of the ones it had, no keys unbinds it. The keys stop doing anything else,
+    /// in the list or while typing, depending on the action.
+    pub fn bind(&mut self, action: Action, keys: &[Key]) {
+        self.bindings.retain(|(key, bound)| *bound != action && !(keys.contains(key) && bound.is_editing() == action.is_editing()));
+        self.bindings.extend(keys.iter().map(|key| (*key, action)));
+    }
+
+    /// What the key does in the list.
+    pub fn action(&self, event: KeyEvent) -> Option<Action> {
+        self.find(event, false)
+    }
+
+    /// What the key does while typing in a field, when it is not text or one of the list's actions.
+    pub fn editing_action(&self, event: KeyEvent) -> Option<Action> {
+        self.find(event, true)
+    }
+
+    fn find(&self, event: KeyEvent, editing: bool) -> Option<Action> {
+        let pressed = Key::from_event(event);
+        self.bindings.iter().find(|(key, action)| *key == pressed && action.is_editing() == editing).map(|(_, action)| *action)
+    }
+
+    pub fn keys(&self, action: Action) -> Vec<Key> {
+        self.bindings.iter().filter(|(_, bound)| *bound == action).map(|(key, _)| *key).collect()
+    }
+
+    /// The first key of the action, for the titles.
+    pub fn hint(&self, action: Action) -> String {
+        self.keys(action).first().map_or_else(|| "unbound".to_string(), Key::to_string)
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::preset(Preset::Default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn keys_by_name() {
+        assert_eq!("ctrl-d".parse(), Ok(Key::new(KeyCode::Char('d'), KeyModifiers::CONTROL)));
+        assert_eq!("Ctrl-Alt-PageDown".parse(), Ok(Key::new(KeyCode::PageDown, KeyModifiers::CONTROL | KeyModifiers::ALT)));
+        assert_eq!("shift-g".parse(), Ok(Key::new(KeyCode::Char('G'), KeyModifiers::NONE)));
+        assert_eq!("ctrl--".parse(), Ok(Key::new(KeyCode::Char('-'), KeyModifiers::CONTROL)));
+        assert_eq!("F12".parse(), Ok(Key::new(KeyCode::F(12), KeyModifiers::NONE)));
+        assert!("f13".parse::<Key>().is_err());
+        assert!("hyper-x".parse::<Key>().is_err());
+        for name in ["ctrl-d", "alt-<", "G", "space", "f2", "shift-up", "-"] {
+            assert_eq!(name.parse::<Key>().unwrap().to_string(), name);
+        }
+    }
+
+    #[test]
+    fn presets() {
+        for preset in [Preset::Default, Preset::Vim, Preset::Emacs] {
+            let keymap = Keymap::preset(preset);
+            // Every action has a key, and no key does two things in the list or while typing.
+            for action in Action::ALL {
+                assert!(!keymap.keys(action).is_empty(), "{:?} in {:?}", action, preset);
+            }
+            for (index, (key, action)) in keymap.bindings.iter().enumerate() {
+                let twice = keymap.bindings[..index].iter().any(|(earlier, other)| earlier == key && other.is_editing() == action.is_editing());
+                assert!(!twice, "{} in {:?}", key, preset);
+            }
+        }
+        let vim = Keymap::preset(Preset::Vim);
+        assert_eq!(vim.action(key(KeyCode::Char('G'), KeyModifiers::SHIFT)), Some(Action::Last));
+        assert_eq!(vim.action(key(KeyCode::Char('}'), KeyModifiers::NONE)), Some(Action::NextFile));
+        let emacs = Keymap::preset(Preset::Emacs);
+        assert_eq!(emacs.action(key(KeyCode::Char('n'), KeyModifiers::CONTROL)), Some(Action::Down));
+        assert_eq!(emacs.action(key(KeyCode::Char('>'), KeyModifiers::ALT | KeyModifiers::SHIFT)), Some(Action::Last));
+        assert_eq!(emacs.action(key(KeyCode::Char('g'), KeyModifiers::CONTROL)), Some(Action::Cancel));
+        assert_eq!(emacs.editing_action(key(KeyCode::Char('g'), KeyModifiers::CONTROL)), Some(Action::EndEditing));
+    }
+
+    #[test]
+    fn rebinding() {
+        let mut keymap = Keymap::default();
+        keymap.bind(Action::Quit, &["ctrl-c".parse().unwrap(), "n".parse().unwrap()]);
+        assert_eq!(keymap.action(key(KeyCode::Char('q'), KeyModifiers::NONE)), None);
+        assert_eq!(keymap.action(key(KeyCode::Char('n'), KeyModifiers::NONE)), Some(Action::Quit));
+        assert_eq!(keymap.hint(Action::NextFile), "unbound");
+        assert_eq!(keymap.hint(Action::PreviousFile), "N");
+        assert_eq!(keymap.hint(Action::Quit), "ctrl-c");
+        // A key for typing leaves the list alone.
+        keymap.bind(Action::ClearField, &["ctrl-d".parse().unwrap()]);
+        assert_eq!(keymap.action(key(KeyCode::Char('d'), KeyModifiers::CONTROL)), Some(Action::PreviewDown));
+        assert_eq!(keymap.editing_action(key(KeyCode::Char('d'), KeyModifiers::CONTROL)), Some(Action::ClearField));
+        assert_eq!(keymap.editing_action(key(KeyCode::Char('u'), KeyModifiers::CONTROL)), None);
+        assert_eq!("next_file".parse(), Ok(Action::NextFile));
+        assert!("jump".parse::<Action>().is_err());
+    }
+}
@@ -1,40 +1,229 @@
 #![allow(unused_imports)]
 #![allow(dead_code)]
 
+mod cli;
+mod config;
 mod search_match;
 mod search_status;
 mod searcher;
 mod fuzzy_finder;
+mod highlight;
+mod index;
+mod index_backend;
+mod info_cache;
+mod keymap;
+mod metadata;
+#[cfg(feature = "powershell")]
 mod powershell;
+mod runner;
 mod tui;
+#[cfg(target_os = "linux")]
+mod watch;
+mod output_format;
+mod page_image;
+mod page_preview;
 mod pdf_opener;
+mod pdf_text;
+mod query;
+mod ranking;
+mod rga_backend;
+mod search_backend;
+mod search_error;
+mod search_options;
+mod terminal_image;
 
+use crate::cli::{CliCommand, IndexCommand, SearchArgs};
+use crate::config::Config;
+use crate::index::Index;
+use crate::output_format::OutputFormat;
+use crate::ranking::SortOrder;
+use crate::tui::TuiMode;
 use crate::searcher::SearchHandler;
 use search_status::SearchStatus;
-use std::{env, fs, path::PathBuf};
+use std::{env, fs, path::{Path, PathBuf}, process};
 use crossterm::terminal::ClearType;
 use crate::fuzzy_finder::FuzzyFinder;
 use crate::pdf_opener::delete_settings_file;
-use crate::powershell::run_powershell_command;
 
 
 fn main() {
-    // tui_example::run();
-    let _args: Vec<String> = env::args().collect();
-    let glob = _args[1].clone();
-    let search_term = _args[2].clone();
-    println!("Press up and down to select, enter to open file, q to exit without opening.");
-
-    let mut search_handler = SearchHandler::new(&glob, &search_term);
-    search_handler.search();
-
-    if let Some(search_matches) = search_handler.search_matches {
-        tui::run(search_matches, &search_term).unwrap();
-        delete_settings_file();
+    // A mistake in a config file doesn't stop --help, --version and config show, they are how it gets fixed.
+    let (config, mistakes) = Config::load();
+    let command = match cli::parse_args(env::args().skip(1), &config) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(2);
+        }
+    };
+    match command {
+        CliCommand::Help => {
+            print!("{}", cli::HELP);
+            for (_, err) in &mistakes {
+                eprintln!("\n{}", err);
+            }
+        }
+        CliCommand::Version => println!("pdf_search {}", env!("CARGO_PKG_VERSION")),
+        CliCommand::ShowConfig => print!("{}", config.show(&mistakes)),
+        _ if !mistakes.is_empty() => {
+            for (_, err) in &mistakes {
+                eprintln!("{}", err);
+            }
+            process::exit(2);
+        }
+        CliCommand::Search(search_args) => run_search(search_args, &config),
+        CliCommand::Index(index_command) => run_index(index_command),
+    }
+}
+
+fn run_index(index_command: IndexCommand) {
+    match index_command {
+        IndexCommand::Build(folder) => {
+            let result = Index::build(&folder, &mut |path| eprintln!("Indexing {}", path.display()))
+                .and_then(|(mut index, changes)| index.save(&folder).map(|_| (index, changes)));
+            match result {
+                Ok((index, changes)) => {
+                    for warning in &changes.failed {
+                        let path = warning.path.as_ref().map(|path| path.display().to_string()).unwrap_or_default();
+                        eprintln!("Could not read {}: {}", path, warning.message);
+                    }
+                    println!("{}", changes.summary());
+                    println!("{}", index.status_string(&folder));
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                }
+            }
+        }
+        IndexCommand::Watch(folder) => watch_index(&folder),
+        IndexCommand::Status(folder) => {
+            if !Index::exists(&folder) {
+                eprintln!("{} has no index, run 'pdf_search index build {}' to make one.", folder.display(), folder.display());
+                process::exit(1);
+            }
+            match Index::load(&folder) {
+                Ok(index) => {
+                    println!("{}", index.status_string(&folder));
+                    let stale = index.stale_files(&folder).unwrap_or_default();
+                    if !stale.is_empty() {
+                        println!("{} pdfs were added, removed or changed since, run 'pdf_search index build {}' to update.", stale.len(), folder.display());
+                    }
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn watch_index(folder: &Path) {
+    println!("Watching {} for pdfs, stop with ctrl-c.", folder.display());
+    let result = watch::watch(
+        folder,
+        &mut |path| eprintln!("Indexing {}", path.display()),
+        &mut |index, changes| {
+            for warning in &changes.failed {
+                let path = warning.path.as_ref().map(|path| path.display().to_string()).unwrap_or_default();
+                eprintln!("Could not read {}: {}", path, warning.message);
+            }
+            println!("{}", changes.summary());
+            println!("{}", index.status_string(folder));
+        },
+    );
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch_index(_folder: &Path) {
+    eprintln!("Watching a folder needs inotify, which is only on Linux. Run 'pdf_search index build' instead.");
+    process::exit(1);
+}
+
+fn run_search(search_args: SearchArgs, config: &Config) {
+    let output_format = search_args.output_format();
+    let print_template = search_args.print_template.clone();
+    let sort_order = search_args.sort;
+    let mut search_handler = if search_args.no_index {
+        let backend = search_backend::extraction_backend(&search_args.options);
+        SearchHandler::with_backend(search_args.options, backend)
     } else {
-        println!("No matches found.");
+        SearchHandler::new(search_args.options)
+    };
+
+    if let Some(format) = output_format {
+        if let Err(err) = search_handler.search() {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        // Printed matches keep the order they were found in unless --sort says otherwise.
+        if let Some(sort_order) = sort_order {
+            search_handler.sort(sort_order);
+        }
+        print!("{}", format.render(&search_handler));
+        // Keep stdout clean for other programs, anything that went wrong goes to stderr.
+        if format != OutputFormat::Plain && !search_handler.search_status.warnings().is_empty() {
+            eprintln!("{}", search_handler.search_status.get_status_string());
+        }
+        return;
+    }
+
+    // The tui starts right away and the matches stream in, a search term that can never work is caught first.
+    if let Err(err) = search_handler.options().regex() {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+    search_handler.start_search();
+
+    if let Some(template) = print_template {
+        pick_and_print(search_handler, &template, sort_order.unwrap_or(SortOrder::Relevance), config);
+        return;
+    }
+
+    println!("Press up and down to select, enter to open file, / to edit the search, q to exit without opening.");
+    let result = tui::run(&mut search_handler, TuiMode::Open, sort_order.unwrap_or(SortOrder::Relevance), config);
+    delete_settings_file();
+    stop_search(&mut search_handler);
+    if let Err(err) = result {
+        eprintln!("Terminal error: {}", err);
+        process::exit(1);
+    }
+    // The tui hides anything printed before it, so the warnings are shown when it is closed.
+    // The search may have been edited in the tui, this is the status of the last one.
+    if search_handler.search_status != SearchStatus::Found {
+        println!("{}", search_handler.search_status.get_status_string());
     }
     println!("Application has shutdown.");
 }
 
+// Like fzf: stdout only gets the picked match, the exit code says whether one was picked.
+fn pick_and_print(mut search_handler: SearchHandler, template: &str, sort_order: SortOrder, config: &Config) {
+    let result = tui::run(&mut search_handler, TuiMode::Pick, sort_order, config);
+    stop_search(&mut search_handler);
+    match result {
+        Ok(Some(search_match)) => println!("{}", output_format::fill_template(template, &search_match)),
+        // Nothing to pick from is an error, quitting without picking is not.
+        Ok(None) if search_handler.search_matches.is_none() && !matches!(search_handler.search_status, SearchStatus::Cancelled(_)) => {
+            eprintln!("{}", search_handler.search_status.get_status_string());
+            process::exit(1);
+        }
+        Ok(None) => process::exit(130),
+        Err(err) => {
+            eprintln!("Terminal error: {}", err);
+            process::exit(1);
+        }
+    }
+}
 
+// Quitting the tui while it still searches cancels the search, the status then says so.
+fn stop_search(search_handler: &mut SearchHandler) {
+    search_handler.cancel();
+    // Whatever went wrong was already shown in the tui.
+    let _ = search_handler.wait();
+}
@@ -0,0 +1,387 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use lopdf::{decode_text_string, Dictionary, Document, Object, ObjectId};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use crate::search_match::SearchMatch;
+
+// How deep the outline and the name trees are followed, a broken pdf can point in circles.
+const MAX_DEPTH: usize = 32;
+
+/// What a pdf says about itself: the document info dictionary, the XMP metadata and the outline.
+/// Most pdfs only fill in some of it.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct DocumentInfo {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+    // As YYYY-MM-DD, or shorter when the pdf leaves out the month or day.
+    pub creation_date: Option<String>,
+    // The values in the XMP packet with the name of the property they belong to, like dc:title.
+    pub xmp: Vec<(String, String)>,
+    pub bookmarks: Vec<Bookmark>,
+}
+
+/// An entry of the outline, what a pdf viewer shows in its bookmarks panel.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Bookmark {
+    // With the titles of the entries it is in, "Installation > Torque values".
+    pub title: String,
+    // The page it jumps to, 1 when that could not be worked out.
+    pub page: usize,
+}
+
+impl DocumentInfo {
+    pub fn read(path: &Path) -> Result<DocumentInfo, lopdf::Error> {
+        Ok(DocumentInfo::from_document(&Document::load(path)?))
+    }
+
+    pub fn from_document(document: &Document) -> DocumentInfo {
+        let dictionary = document
+            .trailer
+            .get(b"Info")
+            .and_then(|info| document.dereference(info))
+            .and_then(|(_, info)| info.as_dict())
+            .ok();
+        let text = |key: &[u8]| {
+            dictionary
+                .and_then(|dictionary| dictionary.get(key).ok())
+                .and_then(|value| decode_text_string(value).ok())
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        DocumentInfo {
+            title: text(b"Title"),
+            author: text(b"Author"),
+            subject: text(b"Subject"),
+            keywords: text(b"Keywords"),
+            creation_date: text(b"CreationDate").and_then(|date| pdf_date(&date)),
+            xmp: xmp_packet(document).map(|xml| xmp_values(&xml)).unwrap_or_default(),
+            bookmarks: bookmarks(document),
+        }
+    }
+
+    // Every searchable line with what it is, like ("Title", "ISO 9001").
+    fn fields(&self) -> Vec<(String, &str)> {
+        let info = [("Title", &self.title), ("Author", &self.author), ("Subject", &self.subject), ("Keywords", &self.keywords)];
+        let mut fields: Vec<(String, &str)> = info
+            .into_iter()
+            .filter_map(|(name, value)| value.as_deref().map(|value| (name.to_string(), value)))
+            .collect();
+        // The XMP often repeats the info dictionary, those would be found twice.
+        for (name, value) in &self.xmp {
+            if !fields.iter().any(|(_, known)| known == value) {
+                fields.push((format!("XMP {}", name), value));
+            }
+        }
+        fields
+    }
+
+    /// Matches in the metadata, for the file at `path`. Bookmarks are on the page they jump to, the rest
+    /// is on page 1. They are not lines of the text, so their line is 0.
+    pub fn search(&self, path: &Path, regex: &Regex) -> Vec<SearchMatch> {
+        let fields = self.fields();
+        let context: Vec<String> = fields.iter().map(|(name, value)| format!("{}: {}", name, value)).collect();
+        let info_matches = fields
+            .iter()
+            .filter_map(|(name, value)| labelled_match(path, 1, name, value, regex, context.join("\n")));
+        let bookmark_matches = self
+            .bookmarks
+            .iter()
+            .filter_map(|bookmark| labelled_match(path, bookmark.page, "Bookmark", &bookmark.title, regex, String::new()));
+        info_matches.chain(bookmark_matches).collect()
+    }
+}
+
+// "Title: ISO 9001" for a title the regex matches. Only the value is searched, not the label.
+fn labelled_match(path: &Path, page: usize, label: &str, value: &str, regex: &Regex, context: String) -> Option<SearchMatch> {
+    let prefix = format!("{}: ", label);
+    let submatches: Vec<_> = regex
+        .find_iter(value)
+        .map(|found| found.start() + prefix.len()..found.end() + prefix.len())
+        .collect();
+    if submatches.is_empty() {
+        return None;
+    }
+    let content = format!("{}{}", prefix, value);
+    let context = if context.is_empty() { content.clone() } else { context };
+    Some(SearchMatch::new(path.to_path_buf(), page, 0, content, context).with_submatches(submatches))
+}
+
+/// Turns a pdf date like D:20200315120000+01'00' into 2020-03-15, the time is left out.
+pub fn pdf_date(date: &str) -> Option<String> {
+    let digits: String = date.trim().trim_start_matches("D:").chars().take_while(|c| c.is_ascii_digit()).collect();
+    match digits.len() {
+        0..=3 => None,
+        4 | 5 => Some(digits[..4].to_string()),
+        6 | 7 => Some(format!("{}-{}", &digits[..4], &digits[4..6])),
+        _ => Some(format!("{}-{}-{}", &digits[..4], &digits[4..6], &digits[6..8])),
+    }
+}
+
+fn xmp_packet(document: &Document) -> Option<String> {
+    let metadata = document.catalog().ok()?.get(b"Metadata").ok()?;
+    let (_, stream) = document.dereference(metadata).ok()?;
+    let content = stream.as_stream().ok()?.get_plain_content().ok()?;
+    Some(String::from_utf8_lossy(&content).into_owned())
+}
+
+/// The text in an XMP packet, with the property it is the value of. Good enough for searching,
+/// it is not a full XML parser. Ids and the edit history are left out.
+pub fn xmp_values(xml: &str) -> Vec<(String, String)> {
+    let attribute = Regex::new(r#"([\w.-]+:[\w.-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+    let mut values = Vec::new();
+    let mut add = |name: &str, value: &str| {
+        let value = unescape(value.trim());
+        let skipped = ["xmlns:", "rdf:", "x:", "xml:", "xmpMM:", "stEvt:", "stRef:"].iter().any(|prefix| name.starts_with(prefix));
+        if !skipped && !value.is_empty() && !values.iter().any(|(known, text)| known == name && *text == value) {
+            values.push((name.to_string(), value));
+        }
+    };
+    let mut elements: Vec<&str> = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        // Text belongs to the closest element that is not just rdf structure, <dc:title><rdf:Alt><rdf:li>.
+        if let Some(name) = elements.iter().rev().find(|name| !name.starts_with("rdf:")) {
+            add(name, &rest[..start]);
+        }
+        let Some(length) = rest[start..].find('>') else { break };
+        let tag = &rest[start + 1..start + length];
+        rest = &rest[start + length + 1..];
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            if let Some(open) = elements.iter().rposition(|open| *open == name.trim()) {
+                elements.truncate(open);
+            }
+            continue;
+        }
+        let name = tag.split_whitespace().next().unwrap_or_default().trim_end_matches('/');
+        // Properties can be attributes as well, <rdf:Description pdf:Producer="...">.
+        for captures in attribute.captures_iter(tag) {
+            add(&captures[1], captures.get(2).or(captures.get(3)).map_or("", |value| value.as_str()));
+        }
+        if !tag.ends_with('/') {
+            elements.push(name);
+        }
+    }
+    values
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// The outline, flattened in the order a viewer shows it.
+pub fn bookmarks(document: &Document) -> Vec<Bookmark> {
+    let page_numbers: HashMap<ObjectId, usize> = document
+        .get_pages()
+        .into_iter()
+        .map(|(number, id)| (id, number as usize))
+        .collect();
+    let mut outline = Outline { document, page_numbers, seen: HashSet::new(), bookmarks: Vec::new() };
+    if let Some(first) = document
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"Outlines").ok())
+        .and_then(|outlines| outline.dictionary(outlines))
+        .and_then(|outlines| outlines.get(b"First").ok())
+    {
+        outline.add_entries(first, "", 0);
+    }
+    outline.bookmarks
+}
+
+struct Outline<'a> {
+    document: &'a Document,
+    page_numbers: HashMap<ObjectId, usize>,
+    seen: HashSet<ObjectId>,
+    bookmarks: Vec<Bookmark>,
+}
+
+impl<'a> Outline<'a> {
+    fn dictionary(&self, object: &'a Object) -> Option<&'a Dictionary> {
+        self.document.dereference(object).ok()?.1.as_dict().ok()
+    }
+
+    // The entry and the ones after it, with their children.
+    fn add_entries(&mut self, first: &'a Object, parents: &str, depth: usize) {
+        let mut next = Some(first);
+        while let Some(entry) = next {
+            let Ok(id) = entry.as_reference() else { return };
+            if depth > MAX_DEPTH || !self.seen.insert(id) {
+                return;
+            }
+            let Some(dictionary) = self.dictionary(entry) else { return };
+            let title = dictionary
+                .get(b"Title")
+                .ok()
+                .and_then(|title| decode_text_string(title).ok())
+                .map(|title| title.split_whitespace().collect::<Vec<_>>().join(" "))
+                .unwrap_or_default();
+            let title = if parents.is_empty() { title } else { format!("{} > {}", parents, title) };
+            self.bookmarks.push(Bookmark { title: title.clone(), page: self.destination_page(dictionary).unwrap_or(1) });
+            if let Ok(child) = dictionary.get(b"First") {
+                self.add_entries(child, &title, depth + 1);
+            }
+            next = dictionary.get(b"Next").ok();
+        }
+    }
+
+    // An entry jumps with /Dest, or with a GoTo action in /A. Either can be a name that is looked up.
+    fn destination_page(&self, entry: &Dictionary) -> Option<usize> {
+        let destination = match entry.get(b"Dest") {
+            Ok(destination) => destination,
+            Err(_) => self.dictionary(entry.get(b"A").ok()?)?.get(b"D").ok()?,
+        };
+        self.page_of(destination, 0)
+    }
+
+    fn page_of(&self, destination: &'a Object, depth: usize) -> Option<usize> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        match self.document.dereference(destination).ok()?.1 {
+            // [page /XYZ left top zoom]
+            Object::Array(array) => self.page_numbers.get(&array.first()?.as_reference().ok()?).copied(),
+            // A named destination can be a dictionary with the array in /D.
+            Object::Dictionary(dictionary) => self.page_of(dictionary.get(b"D").ok()?, depth + 1),
+            Object::Name(name) | Object::String(name, _) => self.page_of(self.named_destination(name)?, depth + 1),
+            _ => None,
+        }
+    }
+
+    // Old pdfs keep the names in the /Dests dictionary of the catalog, newer ones in the /Names tree.
+    fn named_destination(&self, name: &[u8]) -> Option<&'a Object> {
+        let catalog = self.document.catalog().ok()?;
+        if let Some(destination) = catalog.get(b"Dests").ok().and_then(|dests| self.dictionary(dests)).and_then(|dests| dests.get(name).ok()) {
+            return Some(destination);
+        }
+        let tree = self.dictionary(catalog.get(b"Names").ok()?)?.get(b"Dests").ok()?;
+        self.find_in_name_tree(tree, name, 0)
+    }
+
+    fn find_in_name_tree(&self, node: &'a Object, name: &[u8], depth: usize) -> Option<&'a Object> {
+        let node = self.dictionary(node)?;
+        if let Ok(names) = node.get(b"Names").and_then(Object::as_array) {
+            let found = names
+                .chunks(2)
+                .find(|pair| pair.len() == 2 && pair[0].as_str().is_ok_and(|key| key == name))
+                .map(|pair| &pair[1]);
+            if found.is_some() {
+                return found;
+            }
+        }
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        let kids = node.get(b"Kids").and_then(Object::as_array).ok()?;
+        kids.iter().find_map(|kid| self.find_in_name_tree(kid, name, depth + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::{dictionary, StringFormat};
+
+    #[test]
+    fn pdf_dates() {
+        assert_eq!(pdf_date("D:20200315120000+01'00'"), Some("2020-03-15".to_string()));
+        assert_eq!(pdf_date("D:202003"), Some("2020-03".to_string()));
+        assert_eq!(pdf_date("2019"), Some("2019".to_string()));
+        assert_eq!(pdf_date("yesterday"), None);
+    }
+
+    #[test]
+    fn info_dictionary() {
+        let mut document = Document::with_version("1.5");
+        let info = document.add_object(dictionary! {
+            "Title" => Object::String(b"\xFE\xFF\x00I\x00S\x00O\x00 \x009\x000\x000\x001".to_vec(), StringFormat::Hexadecimal),
+            "Author" => Object::string_literal("  "),
+            "CreationDate" => Object::string_literal("D:19991231235959Z"),
+        });
+        document.trailer.set("Info", info);
+        let info = DocumentInfo::from_document(&document);
+        assert_eq!(info.title.as_deref(), Some("ISO 9001"));
+        assert_eq!(info.author, None);
+        assert_eq!(info.creation_date.as_deref(), Some("1999-12-31"));
+        assert_eq!(DocumentInfo::read(Path::new("test_assets/test.pdf")).unwrap().keywords, None);
+    }
+
+    #[test]
+    fn xmp() {
+        let xml = r#"<?xpacket begin="" id="W5M0"?>
+            <x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+            <rdf:Description rdf:about="" pdf:Producer="Writer &amp; co" xmpMM:DocumentID="uuid:1234">
+              <dc:title><rdf:Alt><rdf:li xml:lang="x-default">Torque &lt;specs&gt;</rdf:li></rdf:Alt></dc:title>
+              <dc:creator><rdf:Seq><rdf:li>Ann</rdf:li><rdf:li>Bob</rdf:li></rdf:Seq></dc:creator>
+              <pdf:Keywords/>
+            </rdf:Description></rdf:RDF></x:xmpmeta>"#;
+        let values = xmp_values(xml);
+        let expected = [("pdf:Producer", "Writer & co"), ("dc:title", "Torque <specs>"), ("dc:creator", "Ann"), ("dc:creator", "Bob")];
+        assert_eq!(values, expected.map(|(name, value)| (name.to_string(), value.to_string())));
+    }
+
+    #[test]
+    fn outline() {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let page_ids: Vec<ObjectId> = (0..3).map(|_| document.add_object(dictionary! { "Type" => "Page", "Parent" => pages_id })).collect();
+        let kids: Vec<Object> = page_ids.iter().map(|&id| id.into()).collect();
+        document.objects.insert(pages_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => kids, "Count" => 3 }));
+        let named = document.add_object(dictionary! { "D" => vec![page_ids[2].into(), "Fit".into()] });
+        let names_tree = document.add_object(dictionary! { "Names" => vec![Object::string_literal("torque"), named.into()] });
+        let outlines_id = document.new_object_id();
+        let child = document.add_object(dictionary! {
+            "Title" => Object::string_literal("Torque  values"),
+            "A" => dictionary! { "S" => "GoTo", "D" => Object::string_literal("torque") },
+        });
+        let second = document.add_object(dictionary! { "Title" => Object::string_literal("Index"), "Dest" => "nowhere" });
+        let first = document.add_object(dictionary! {
+            "Title" => Object::string_literal("Installation"),
+            "Dest" => vec![page_ids[1].into(), "XYZ".into()],
+            "First" => child,
+            "Next" => second,
+        });
+        document.objects.insert(outlines_id, Object::Dictionary(dictionary! { "First" => first }));
+        let catalog = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "Outlines" => outlines_id,
+            "Names" => dictionary! { "Dests" => names_tree },
+        });
+        document.trailer.set("Root", catalog);
+
+        let info = DocumentInfo::from_document(&document);
+        let bookmarks: Vec<(&str, usize)> = info.bookmarks.iter().map(|bookmark| (bookmark.title.as_str(), bookmark.page)).collect();
+        assert_eq!(bookmarks, vec![("Installation", 2), ("Installation > Torque values", 3), ("Index", 1)]);
+
+        let search_matches = info.search(Path::new("a.pdf"), &Regex::new("(?i)torque").unwrap());
+        assert_eq!(search_matches.len(), 1);
+        assert_eq!((search_matches[0].page, search_matches[0].line), (3, 0));
+        assert_eq!(search_matches[0].content, "Bookmark: Installation > Torque values");
+        assert_eq!(search_matches[0].submatches, vec![25..31]);
+    }
+
+    #[test]
+    fn only_values_are_searched() {
+        let info = DocumentInfo {
+            title: Some("Torque tables".to_string()),
+            xmp: vec![("dc:title".to_string(), "Torque tables".to_string()), ("dc:creator".to_string(), "Ann".to_string())],
+            ..DocumentInfo::default()
+        };
+        let path = Path::new("a.pdf");
+        assert!(info.search(path, &Regex::new("Title").unwrap()).is_empty());
+        let search_matches = info.search(path, &Regex::new("Torque|Ann").unwrap());
+        let contents: Vec<&str> = search_matches.iter().map(|search_match| search_match.content.as_str()).collect();
+        assert_eq!(contents, vec!["Title: Torque tables", "XMP dc:creator: Ann"]);
+        assert_eq!(search_matches[0].context, "Title: Torque tables\nXMP dc:creator: Ann");
+    }
+}
@@ -0,0 +1,165 @@
+use std::str::FromStr;
+use serde_json::json;
+use crate::search_match::SearchMatch;
+use crate::searcher::SearchHandler;
+
+/// How matches are printed when the tui is not used.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OutputFormat {
+    // Matches grouped under their file, like pretty_formatted.
+    Plain,
+    // One json object per match and line.
+    JsonLines,
+    Csv,
+    // path:page:line:text, for editors and grep like tools.
+    Vimgrep,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "plain" | "text" => Ok(OutputFormat::Plain),
+            "json" | "jsonl" => Ok(OutputFormat::JsonLines),
+            "csv" => Ok(OutputFormat::Csv),
+            "vimgrep" => Ok(OutputFormat::Vimgrep),
+            _ => Err(format!("unknown format '{}', expected plain, jsonl, csv or vimgrep", s)),
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn render(&self, search_handler: &SearchHandler) -> String {
+        let search_matches: &[SearchMatch] = search_handler.search_matches.as_deref().unwrap_or_default();
+        match self {
+            OutputFormat::Plain => search_handler.pretty_formatted(),
+            OutputFormat::JsonLines => search_matches.iter().map(|search_match| format!("{}\n", json_line(search_match))).collect(),
+            OutputFormat::Csv => {
+                let mut csv = "path,page,line,content,context\n".to_string();
+                for search_match in search_matches {
+                    csv += &csv_row(search_match);
+                }
+                csv
+            }
+            OutputFormat::Vimgrep => search_matches.iter().map(vimgrep_line).collect(),
+        }
+    }
+}
+
+pub fn json_line(search_match: &SearchMatch) -> String {
+    json!({
+        "path": search_match.path.display().to_string(),
+        "page": search_match.page,
+        "line": search_match.line,
+        "content": search_match.content,
+        "context": search_match.context,
+    })
+    .to_string()
+}
+
+fn csv_row(search_match: &SearchMatch) -> String {
+    let fields = [
+        search_match.path.display().to_string(),
+        search_match.page.to_string(),
+        search_match.line.to_string(),
+        search_match.content.clone(),
+        search_match.context.clone(),
+    ];
+    let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+    format!("{}\n", fields.join(","))
+}
+
+// Fields with a comma, quote or line break are quoted, and quotes inside are doubled.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub fn vimgrep_line(search_match: &SearchMatch) -> String {
+    format!("{}:{}:{}:{}\n", search_match.path.display(), search_match.page, search_match.line, search_match.content)
+}
+
+/// Fills in {path}, {page}, {line} and {content}.
+/// A literal `\t` and `\n` are turned into a tab and a newline, so they can be typed in a shell.
+pub fn fill_template(template: &str, search_match: &SearchMatch) -> String {
+    template
+        .replace("\\t", "\t")
+        .replace("\\n", "\n")
+        .replace("{path}", &search_match.path.display().to_string())
+        .replace("{page}", &search_match.page.to_string())
+        .replace("{line}", &search_match.line.to_string())
+        .replace("{content}", &search_match.content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::search_backend::{CancelHandle, SearchBackend, SearchedFile};
+    use crate::search_error::SearchError;
+    use crate::search_options::SearchOptions;
+    use crate::search_status::SearchStatus;
+
+    struct FixedBackend;
+
+    impl SearchBackend for FixedBackend {
+        fn search_files(&self, _options: &SearchOptions, _cancel: &CancelHandle, on_file: &mut dyn FnMut(SearchedFile)) -> Result<SearchStatus, SearchError> {
+            let search_match = SearchMatch::new(
+                PathBuf::from("docs/a, b.pdf"),
+                3,
+                12,
+                "torque \"M8\"".to_string(),
+                "before\ntorque \"M8\"".to_string(),
+            );
+            on_file(SearchedFile { path: search_match.path.clone(), search_matches: vec![search_match] });
+            Ok(SearchStatus::Found)
+        }
+    }
+
+    fn searched() -> SearchHandler {
+        let mut search_handler = SearchHandler::with_backend(SearchOptions::new("*.pdf", "torque"), Box::new(FixedBackend));
+        search_handler.search().unwrap();
+        search_handler
+    }
+
+    #[test]
+    fn parse_format() {
+        assert_eq!("JSONL".parse(), Ok(OutputFormat::JsonLines));
+        assert_eq!("vimgrep".parse(), Ok(OutputFormat::Vimgrep));
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn json_lines() {
+        let output = OutputFormat::JsonLines.render(&searched());
+        let value: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(value["path"], "docs/a, b.pdf");
+        assert_eq!(value["page"], 3);
+        assert_eq!(value["line"], 12);
+        assert_eq!(value["context"], "before\ntorque \"M8\"");
+    }
+
+    #[test]
+    fn csv() {
+        let output = OutputFormat::Csv.render(&searched());
+        let expected = "path,page,line,content,context\n\"docs/a, b.pdf\",3,12,\"torque \"\"M8\"\"\",\"before\ntorque \"\"M8\"\"\"\n";
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn template() {
+        let search_handler = searched();
+        let search_match = &search_handler.search_matches.as_ref().unwrap()[0];
+        assert_eq!(fill_template("{path}\\t{page}", search_match), "docs/a, b.pdf\t3");
+        assert_eq!(fill_template("{line}: {content}", search_match), "12: torque \"M8\"");
+    }
+
+    #[test]
+    fn vimgrep() {
+        assert_eq!(OutputFormat::Vimgrep.render(&searched()), "docs/a, b.pdf:3:12:torque \"M8\"\n");
+    }
+}
@@ -0,0 +1,303 @@
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use regex::Regex;
+use crate::highlight::Highlighter;
+use crate::runner::{default_runner, get_stdout};
+use crate::search_error::SearchError;
+
+// The longest side of a rendered page in pixels. Enough for a preview pane on a large screen,
+// the image is scaled down to fit the pane from there.
+const RENDER_SIZE: usize = 1400;
+const OUTLINE_COLOUR: [u8; 3] = [230, 30, 30];
+
+/// Pixels, three bytes each, row by row.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+/// Part of a page, as fractions of its width and height so it fits the image at any size.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Region {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+/// One page rendered, with where the search terms are on it.
+pub struct PageImage {
+    pub path: PathBuf,
+    pub page: usize,
+    pub image: Image,
+    pub regions: Vec<Region>,
+}
+
+impl PageImage {
+    /// Renders the page with pdftoppm. The regions come from pdftotext, without it the page is shown without them.
+    /// `content` is the matched line, on its page only the terms on that line are outlined.
+    pub fn render(path: &Path, page: usize, highlighter: &mut Highlighter, content: &str) -> Result<PageImage, SearchError> {
+        let (first, file) = (page.to_string(), path.display().to_string());
+        // Without a file name for them pdftoppm and pdftotext write to stdout.
+        let ppm = run_poppler("pdftoppm", &["-f", &first, "-l", &first, "-scale-to", &RENDER_SIZE.to_string(), &file])?;
+        let image = Image::from_ppm(&ppm.stdout)?;
+        let regions = run_poppler("pdftotext", &["-f", &first, "-l", &first, "-bbox-layout", &file, "-"])
+            .map(|output| match_regions(&get_stdout(&output), highlighter, content))
+            .unwrap_or_default();
+        Ok(PageImage { path: path.to_path_buf(), page, image, regions })
+    }
+
+    /// The image scaled to fit in `width` by `height` pixels, with the regions outlined.
+    pub fn fitted(&self, width: usize, height: usize) -> Image {
+        let mut image = self.image.fit(width, height);
+        for region in &self.regions {
+            image.outline(region);
+        }
+        image
+    }
+}
+
+fn run_poppler(program: &str, args: &[&str]) -> Result<Output, SearchError> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let output = default_runner().run(program, &args).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => SearchError::BackendMissing(program.to_string()),
+        _ => SearchError::Io(err),
+    })?;
+    if !output.status.success() {
+        return Err(SearchError::BackendFailed { code: output.status.code(), stderr: String::from_utf8_lossy(&output.stderr).to_string() });
+    }
+    Ok(output)
+}
+
+impl Image {
+    /// Reads the binary ppm pdftoppm writes, with 255 as the largest value.
+    pub fn from_ppm(bytes: &[u8]) -> Result<Image, SearchError> {
+        let malformed = || SearchError::MalformedOutput("pdftoppm did not write a ppm image".to_string());
+        // The header is four whitespace separated fields, the pixels start after one more whitespace.
+        let mut fields = Vec::new();
+        let mut position = 0;
+        while fields.len() < 4 {
+            while bytes.get(position).ok_or_else(malformed)?.is_ascii_whitespace() {
+                position += 1;
+            }
+            let start = position;
+            while bytes.get(position).is_some_and(|byte| !byte.is_ascii_whitespace()) {
+                position += 1;
+            }
+            fields.push(String::from_utf8_lossy(&bytes[start..position]).to_string());
+        }
+        let number = |field: &String| field.parse::<usize>().map_err(|_| malformed());
+        let (width, height) = (number(&fields[1])?, number(&fields[2])?);
+        if fields[0] != "P6" || fields[3] != "255" || width == 0 || height == 0 {
+            return Err(malformed());
+        }
+        let data = bytes.get(position + 1..position + 1 + width * height * 3).ok_or_else(malformed)?;
+        let pixels = data.chunks_exact(3).map(|pixel| [pixel[0], pixel[1], pixel[2]]).collect();
+        Ok(Image { width, height, pixels })
+    }
+
+    /// Scaled down to fit in `width` by `height` without changing its shape. Every pixel is the average
+    /// of the pixels it covers, so thin lines of text fade instead of disappearing.
+    pub fn fit(&self, width: usize, height: usize) -> Image {
+        let scale = (width as f32 / self.width as f32).min(height as f32 / self.height as f32).min(1.0);
+        let fitted_width = ((self.width as f32 * scale) as usize).max(1);
+        let fitted_height = ((self.height as f32 * scale) as usize).max(1);
+        let mut pixels = Vec::with_capacity(fitted_width * fitted_height);
+        for y in 0..fitted_height {
+            let rows = covered(y, fitted_height, self.height);
+            for x in 0..fitted_width {
+                let columns = covered(x, fitted_width, self.width);
+                let mut sum = [0usize; 3];
+                for row in rows.clone() {
+                    for pixel in &self.pixels[row * self.width + columns.start..row * self.width + columns.end] {
+                        for channel in 0..3 {
+                            sum[channel] += pixel[channel] as usize;
+                        }
+                    }
+                }
+                let count = rows.len() * columns.len();
+                pixels.push(sum.map(|channel| (channel / count) as u8));
+            }
+        }
+        Image { width: fitted_width, height: fitted_height, pixels }
+    }
+
+    /// Draws a box around the region, just outside it so the text stays readable.
+    pub fn outline(&mut self, region: &Region) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+        let x = |fraction: f32| ((fraction * self.width as f32) as usize).min(self.width - 1);
+        let y = |fraction: f32| ((fraction * self.height as f32) as usize).min(self.height - 1);
+        // The right and bottom edge are the first pixels past the region already.
+        let (left, right) = (x(region.left).saturating_sub(1), x(region.right));
+        let (top, bottom) = (y(region.top).saturating_sub(1), y(region.bottom));
+        for column in left..=right {
+            self.pixels[top * self.width + column] = OUTLINE_COLOUR;
+            self.pixels[bottom * self.width + column] = OUTLINE_COLOUR;
+        }
+        for row in top..=bottom {
+            self.pixels[row * self.width + left] = OUTLINE_COLOUR;
+            self.pixels[row * self.width + right] = OUTLINE_COLOUR;
+        }
+    }
+}
+
+// The pixels of the original that pixel `index` of the fitted image covers, at least one.
+fn covered(index: usize, fitted: usize, original: usize) -> Range<usize> {
+    let start = index * original / fitted;
+    start..((index + 1) * original / fitted).max(start + 1)
+}
+
+// A word in the output of `pdftotext -bbox-layout`, in points from the top left of the page.
+struct Word {
+    text: String,
+    left: f32,
+    top: f32,
+    right: f32,
+    bottom: f32,
+}
+
+/// Where the search terms are in the output of `pdftotext -bbox-layout`. The words of a line are joined
+/// with spaces, so a phrase is found across words. When one of the lines is the matched line, only its terms count.
+pub fn match_regions(bbox_layout: &str, highlighter: &mut Highlighter, content: &str) -> Vec<Region> {
+    let number = r#""([\d.]+)""#;
+    let page = Regex::new(&format!("<page width={} height={}", number, number)).unwrap();
+    let word = Regex::new(&format!("<word xMin={} yMin={} xMax={} yMax={}>([^<]*)</word>", number, number, number, number)).unwrap();
+    let Some(size) = page.captures(bbox_layout) else {
+        return vec![];
+    };
+    let (page_width, page_height) = (size[1].parse::<f32>().unwrap_or(1.0), size[2].parse::<f32>().unwrap_or(1.0));
+    let mut all_regions = Vec::new();
+    let mut line_regions = Vec::new();
+    let content = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    for line in bbox_layout.split("<line ").skip(1) {
+        let words: Vec<Word> = word
+            .captures_iter(line)
+            .map(|found| Word {
+                text: unescape(&found[5]),
+                left: found[1].parse().unwrap_or(0.0),
+                top: found[2].parse().unwrap_or(0.0),
+                right: found[3].parse().unwrap_or(0.0),
+                bottom: found[4].parse().unwrap_or(0.0),
+            })
+            .collect();
+        let mut text = String::new();
+        let mut starts = Vec::new();
+        for word in &words {
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            starts.push(text.len());
+            text.push_str(&word.text);
+        }
+        for (range, _) in highlighter.find(&text) {
+            let covered = words.iter().zip(&starts).filter(|(word, &start)| start < range.end && start + word.text.len() > range.start);
+            let region = covered.fold(None, |region: Option<Region>, (word, _)| {
+                let word_region = Region { left: word.left / page_width, top: word.top / page_height, right: word.right / page_width, bottom: word.bottom / page_height };
+                Some(match region {
+                    Some(region) => Region {
+                        left: region.left.min(word_region.left),
+                        top: region.top.min(word_region.top),
+                        right: region.right.max(word_region.right),
+                        bottom: region.bottom.max(word_region.bottom),
+                    },
+                    None => word_region,
+                })
+            });
+            if let Some(region) = region {
+                if text == content {
+                    line_regions.push(region);
+                }
+                all_regions.push(region);
+            }
+        }
+    }
+    if line_regions.is_empty() {
+        all_regions
+    } else {
+        line_regions
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search_options::SearchOptions;
+
+    const BBOX_LAYOUT: &str = r#"<doc>
+  <page width="200.000000" height="100.000000">
+    <flow>
+      <block xMin="10.000000" yMin="10.000000" xMax="190.000000" yMax="40.000000">
+        <line xMin="10.000000" yMin="10.000000" xMax="190.000000" yMax="20.000000">
+          <word xMin="10.000000" yMin="10.000000" xMax="50.000000" yMax="20.000000">Torque</word>
+          <word xMin="60.000000" yMin="10.000000" xMax="100.000000" yMax="20.000000">M8&amp;M10</word>
+        </line>
+        <line xMin="10.000000" yMin="30.000000" xMax="190.000000" yMax="40.000000">
+          <word xMin="10.000000" yMin="30.000000" xMax="40.000000" yMax="40.000000">M8</word>
+          <word xMin="50.000000" yMin="30.000000" xMax="90.000000" yMax="40.000000">bolt</word>
+        </line>
+      </block>
+    </flow>
+  </page>
+</doc>"#;
+
+    fn image(width: usize, height: usize, pixels: &[u8]) -> Image {
+        Image { width, height, pixels: pixels.iter().map(|&value| [value; 3]).collect() }
+    }
+
+    #[test]
+    fn ppm() {
+        let mut bytes = b"P6\n2 1\n255\n".to_vec();
+        bytes.extend([1, 2, 3, 4, 5, 6]);
+        let image = Image::from_ppm(&bytes).unwrap();
+        assert_eq!(image, Image { width: 2, height: 1, pixels: vec![[1, 2, 3], [4, 5, 6]] });
+        assert!(Image::from_ppm(b"P6\n2 1\n255\n").is_err());
+        assert!(Image::from_ppm(b"P3\n1 1\n255\n1 2 3").is_err());
+        assert!(Image::from_ppm(b"").is_err());
+    }
+
+    #[test]
+    fn fit_keeps_the_shape() {
+        let fitted = image(4, 2, &[0, 100, 200, 200, 0, 100, 200, 200]).fit(2, 10);
+        assert_eq!(fitted, image(2, 1, &[50, 200]));
+        assert_eq!(image(1, 1, &[7]).fit(10, 10), image(1, 1, &[7]));
+    }
+
+    #[test]
+    fn outline_around_a_region() {
+        let mut outlined = image(5, 5, &[0; 25]);
+        outlined.outline(&Region { left: 0.4, top: 0.4, right: 0.6, bottom: 0.6 });
+        let red: Vec<usize> = (0..25).filter(|&index| outlined.pixels[index] == OUTLINE_COLOUR).collect();
+        assert_eq!(red, vec![6, 7, 8, 11, 13, 16, 17, 18]);
+    }
+
+    #[test]
+    fn regions_of_the_matched_line() {
+        let mut highlighter = Highlighter::new(&SearchOptions::new("*.pdf", "m8 bolt|torque"));
+        let regions = match_regions(BBOX_LAYOUT, &mut highlighter, "M8  bolt");
+        assert_eq!(regions, vec![Region { left: 0.05, top: 0.3, right: 0.45, bottom: 0.4 }]);
+        let regions = match_regions(BBOX_LAYOUT, &mut highlighter, "Title: Torque");
+        assert_eq!(regions.len(), 2);
+        let mut highlighter = Highlighter::new(&SearchOptions::new("*.pdf", "M8&M10"));
+        assert_eq!(match_regions(BBOX_LAYOUT, &mut highlighter, "").len(), 1);
+        assert!(match_regions("", &mut highlighter, "").is_empty());
+    }
+
+    #[test]
+    #[ignore = "needs poppler"]
+    fn render_test_pdf() {
+        let mut highlighter = Highlighter::new(&SearchOptions::new("*.pdf", "subheading"));
+        let page_image = PageImage::render(Path::new("test_assets/test.pdf"), 1, &mut highlighter, "This is a subheading - Test").unwrap();
+        assert_eq!(page_image.image.width.max(page_image.image.height), RENDER_SIZE);
+        assert_eq!(page_image.regions.len(), 1);
+    }
+}
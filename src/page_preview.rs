@@ -0,0 +1,162 @@
+use std::path::{Path, PathBuf};
+use crate::search_match::SearchMatch;
+
+/// The whole text of one page of a pdf, scrolled so the match is in the middle.
+/// The pages of the pdf are kept, so stepping to the next or previous page doesn't extract it again.
+pub struct PagePreview {
+    pub path: PathBuf,
+    // The lines of every page, the first page is at index 0.
+    pages: Vec<Vec<String>>,
+    // Counts from 1 like SearchMatch.page.
+    pub page: usize,
+    pub scroll: usize,
+    // The page and the line on it the match is on, when the line can be found.
+    matched: Option<(usize, usize)>,
+    // The match is centred the next time the lines are shown, that needs their height.
+    centre: bool,
+    // How many lines were shown last, PageUp and PageDown move by that much.
+    height: usize,
+}
+
+impl PagePreview {
+    pub fn new(path: &Path, pages: &[String]) -> PagePreview {
+        PagePreview {
+            path: path.to_path_buf(),
+            pages: pages.iter().map(|text| text.lines().map(|line| line.trim().to_string()).collect()).collect(),
+            page: 1,
+            scroll: 0,
+            matched: None,
+            centre: false,
+            height: 0,
+        }
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// Goes to the page of the match. Metadata matches have no line, their page is shown from the top.
+    pub fn show_match(&mut self, search_match: &SearchMatch) {
+        self.page = search_match.page.clamp(1, self.pages.len().max(1));
+        self.matched = self.line_of(search_match).map(|line| (self.page, line));
+        self.scroll = 0;
+        self.centre = self.matched.is_some();
+    }
+
+    /// The line of the match on the page shown, None when the match is on another page.
+    pub fn matched_line(&self) -> Option<usize> {
+        self.matched.filter(|(page, _)| *page == self.page).map(|(_, line)| line)
+    }
+
+    // Line numbers count from the start of the document, the backends that extract the text themselves
+    // count the same lines as here. rga counts the lines pdftotext gives it, so when the line number
+    // doesn't fit the line is looked for by its text.
+    fn line_of(&self, search_match: &SearchMatch) -> Option<usize> {
+        let lines = self.pages.get(self.page - 1)?;
+        let content = search_match.content.trim();
+        if search_match.line > 0 {
+            let before: usize = self.pages[..self.page - 1].iter().map(Vec::len).sum();
+            let line = (search_match.line - 1).checked_sub(before);
+            if line.is_some_and(|line| lines.get(line).is_some_and(|text| text == content)) {
+                return line;
+            }
+        }
+        lines
+            .iter()
+            .position(|text| text == content)
+            .or_else(|| lines.iter().position(|text| !content.is_empty() && text.contains(content)))
+    }
+
+    /// Fits the page in `height` lines, the scroll position can't go past its end.
+    pub fn fit(&mut self, height: usize) {
+        self.height = height;
+        if self.centre {
+            self.centre = false;
+            self.scroll = self.matched_line().unwrap_or(0).saturating_sub(height / 2);
+        }
+        self.scroll = self.scroll.min(self.page_lines().len().saturating_sub(height));
+    }
+
+    /// The lines of the page from the scroll position on.
+    pub fn lines(&self) -> &[String] {
+        &self.page_lines()[self.scroll.min(self.page_lines().len())..]
+    }
+
+    fn page_lines(&self) -> &[String] {
+        self.pages.get(self.page - 1).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Scrolls by whole screens, back when `screens` is negative.
+    pub fn scroll_by(&mut self, screens: isize) {
+        let step = self.height.saturating_sub(1).max(1);
+        self.scroll = self.scroll.saturating_add_signed(screens * step as isize);
+        self.centre = false;
+    }
+
+    /// Steps to another page of the same pdf. Coming back to the page of the match centres it again.
+    pub fn step_page(&mut self, pages: isize) {
+        let page = self.page.saturating_add_signed(pages).clamp(1, self.pages.len().max(1));
+        if page != self.page {
+            self.page = page;
+            self.scroll = 0;
+            self.centre = self.matched_line().is_some();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_preview() -> PagePreview {
+        let pages: Vec<String> = vec![
+            (1..=20).map(|line| format!("first {}\n", line)).collect(),
+            "  bolt torque  \nM8\n".to_string(),
+        ];
+        PagePreview::new(Path::new("a.pdf"), &pages)
+    }
+
+    #[test]
+    fn centres_the_match() {
+        let mut preview = test_preview();
+        preview.show_match(&SearchMatch::new(PathBuf::from("a.pdf"), 1, 15, "first 15".to_string(), String::new()));
+        assert_eq!(preview.matched_line(), Some(14));
+        preview.fit(6);
+        assert_eq!(preview.lines()[0], "first 12");
+        preview.scroll_by(1);
+        preview.fit(6);
+        assert_eq!(preview.lines()[0], "first 15");
+        preview.scroll_by(10);
+        preview.fit(6);
+        assert_eq!(preview.lines().len(), 6);
+        preview.scroll_by(-10);
+        preview.fit(6);
+        assert_eq!(preview.lines()[0], "first 1");
+    }
+
+    #[test]
+    fn lines_by_text_when_the_numbers_differ() {
+        let mut preview = test_preview();
+        preview.show_match(&SearchMatch::new(PathBuf::from("a.pdf"), 2, 3, "bolt torque".to_string(), String::new()));
+        assert_eq!(preview.matched_line(), Some(0));
+        preview.show_match(&SearchMatch::new(PathBuf::from("a.pdf"), 2, 0, "Title: Bolts".to_string(), String::new()));
+        assert_eq!(preview.matched_line(), None);
+        preview.fit(6);
+        assert_eq!(preview.lines(), ["bolt torque", "M8"]);
+    }
+
+    #[test]
+    fn steps_through_the_pages() {
+        let mut preview = test_preview();
+        preview.show_match(&SearchMatch::new(PathBuf::from("a.pdf"), 2, 22, "M8".to_string(), String::new()));
+        assert_eq!(preview.matched_line(), Some(1));
+        preview.step_page(-1);
+        assert_eq!((preview.page, preview.matched_line()), (1, None));
+        preview.fit(6);
+        assert_eq!(preview.lines()[0], "first 1");
+        preview.step_page(-1);
+        assert_eq!(preview.page, 1);
+        preview.step_page(5);
+        assert_eq!((preview.page, preview.matched_line()), (2, Some(1)));
+    }
+}
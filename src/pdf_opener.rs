@@ -0,0 +1,169 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::{env, fs, io};
+use crate::runner::find_program;
+use crate::search_match::SearchMatch;
+
+// Set this to the name of a profile, e.g. "zathura", or to a command template like "myviewer --page {page} {file}".
+pub const VIEWER_ENV_VAR: &str = "PDF_SEARCH_VIEWER";
+
+/// Describes how a pdf viewer is started so that it opens a file at a given page.
+/// `{file}`, `{page}` and `{settings_dir}` in the arguments are replaced before the viewer is started.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ViewerProfile {
+    pub name: String,
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl ViewerProfile {
+    pub fn new(name: &str, program: &str, args: &[&str]) -> ViewerProfile {
+        ViewerProfile {
+            name: name.to_string(),
+            program: program.to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    pub fn builtin() -> Vec<ViewerProfile> {
+        vec![
+            ViewerProfile::new("zathura", "zathura", &["--page={page}", "{file}"]),
+            ViewerProfile::new("evince", "evince", &["--page-index={page}", "{file}"]),
+            ViewerProfile::new("okular", "okular", &["-p", "{page}", "{file}"]),
+            ViewerProfile::new("mupdf", "mupdf", &["{file}", "{page}"]),
+            // SumatraPDF gets its own settings folder, otherwise it restores its last session on top of the file we open.
+            ViewerProfile::new("sumatrapdf", "SumatraPDF", &["-appdata", "{settings_dir}", "-reuse-instance", "-page", "{page}", "{file}"]),
+            // Can't jump to a page, so it is the last resort.
+            ViewerProfile::new("xdg-open", "xdg-open", &["{file}"]),
+        ]
+    }
+
+    // A template is split on whitespace, the first word is the program. Quotes keep a word with spaces
+    // together, like "C:\Program Files\SumatraPDF\SumatraPDF.exe" -page {page} {file}.
+    pub fn from_template(template: &str) -> Option<ViewerProfile> {
+        let words = split_template(template);
+        let (program, args) = words.split_first()?;
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        Some(ViewerProfile::new("custom", program, &args))
+    }
+
+    pub fn args_for(&self, file: &Path, page: usize) -> Vec<String> {
+        let settings_dir = settings_dir();
+        self.args
+            .iter()
+            .map(|arg| {
+                arg.replace("{file}", &file.display().to_string())
+                    .replace("{page}", &page.to_string())
+                    .replace("{settings_dir}", &settings_dir.display().to_string())
+            })
+            .collect()
+    }
+
+    pub fn command_for(&self, search_match: &SearchMatch) -> Command {
+        let program = find_program(&self.program).unwrap_or_else(|| PathBuf::from(&self.program));
+        let mut command = Command::new(program);
+        command.args(self.args_for(&search_match.path, search_match.page));
+        command
+    }
+}
+
+/// The viewer the config or `PDF_SEARCH_VIEWER` chose, otherwise the first built in viewer that is installed.
+pub fn select_viewer(choice: Option<&str>) -> Option<ViewerProfile> {
+    match choice {
+        Some(choice) if !choice.trim().is_empty() => viewer_from_choice(choice),
+        _ => detect_viewer(),
+    }
+}
+
+fn viewer_from_choice(choice: &str) -> Option<ViewerProfile> {
+    ViewerProfile::builtin()
+        .into_iter()
+        .find(|profile| profile.name.eq_ignore_ascii_case(choice.trim()))
+        .or_else(|| ViewerProfile::from_template(choice))
+}
+
+// Backslashes are left alone, they are in every Windows path. A quote that is not closed runs to the end.
+fn split_template(template: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for c in template.chars() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                // "" is an empty word.
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+fn detect_viewer() -> Option<ViewerProfile> {
+    ViewerProfile::builtin()
+        .into_iter()
+        .find(|profile| find_program(&profile.program).is_some())
+}
+
+pub fn open_pdf(search_match: &SearchMatch, viewer: Option<&str>) -> io::Result<()> {
+    let viewer = select_viewer(viewer).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("No pdf viewer found, set viewer in the config or {} to choose one.", VIEWER_ENV_VAR))
+    })?;
+    // The viewer keeps running after we exit, and must not draw over the tui.
+    viewer
+        .command_for(search_match)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+fn settings_dir() -> PathBuf {
+    env::temp_dir().join("pdf_search")
+}
+
+/// Removes the settings the viewers wrote while we were running.
+pub fn delete_settings_file() {
+    // It is fine if it was never created.
+    let _ = fs::remove_dir_all(settings_dir());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_and_file_are_filled_in() {
+        let zathura = viewer_from_choice("Zathura").unwrap();
+        let args = zathura.args_for(Path::new("docs/a b.pdf"), 7);
+        assert_eq!(args, vec!["--page=7".to_string(), "docs/a b.pdf".to_string()]);
+    }
+
+    #[test]
+    fn custom_template() {
+        let viewer = viewer_from_choice("myviewer -p {page} {file}").unwrap();
+        assert_eq!(viewer.program, "myviewer");
+        assert_eq!(viewer.args_for(Path::new("x.pdf"), 3), vec!["-p", "3", "x.pdf"]);
+    }
+
+    #[test]
+    fn quoted_template() {
+        let viewer = viewer_from_choice(r#""C:\Program Files\SumatraPDF\SumatraPDF.exe" -page {page} "{file}""#).unwrap();
+        assert_eq!(viewer.program, r"C:\Program Files\SumatraPDF\SumatraPDF.exe");
+        assert_eq!(viewer.args_for(Path::new("a b.pdf"), 3), vec!["-page", "3", "a b.pdf"]);
+        let viewer = viewer_from_choice("'/opt/My Viewer/view' --title='' {file}").unwrap();
+        assert_eq!(viewer.program, "/opt/My Viewer/view");
+        assert_eq!(viewer.args_for(Path::new("x.pdf"), 1), vec!["--title=", "x.pdf"]);
+    }
+
+    #[test]
+    fn empty_template() {
+        assert_eq!(ViewerProfile::from_template("   "), None);
+    }
+}
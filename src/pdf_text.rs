@@ -0,0 +1,169 @@
+use std::path::{Path, PathBuf};
+use lopdf::Document;
+use regex::Regex;
+use crate::metadata::DocumentInfo;
+use crate::search_backend::{find_files, CancelHandle, SearchBackend, SearchedFile};
+use crate::search_error::SearchError;
+use crate::search_match::SearchMatch;
+use crate::search_options::SearchOptions;
+use crate::search_status::{FileWarning, SearchStatus};
+
+/// The text of every page in a pdf, the first page is at index 0.
+pub fn extract_pages(path: &Path) -> Result<Vec<String>, lopdf::Error> {
+    extract(path).map(|(pages, _)| pages)
+}
+
+/// The pages and the document info, the pdf is only loaded once.
+pub fn extract(path: &Path) -> Result<(Vec<String>, DocumentInfo), lopdf::Error> {
+    let document = Document::load(path)?;
+    let pages = document
+        .get_pages()
+        .keys()
+        .map(|&page_number| document.extract_text(&[page_number]))
+        .collect::<Result<_, _>>()?;
+    Ok((pages, DocumentInfo::from_document(&document)))
+}
+
+/// Searches pdfs without any external programs, the text is extracted in process.
+pub struct PdfTextBackend {}
+
+impl PdfTextBackend {
+    pub fn new() -> PdfTextBackend {
+        PdfTextBackend {}
+    }
+}
+
+impl SearchBackend for PdfTextBackend {
+    fn search_files(&self, options: &SearchOptions, cancel: &CancelHandle, on_file: &mut dyn FnMut(SearchedFile)) -> Result<SearchStatus, SearchError> {
+        let regex = options.regex()?;
+        let files: Vec<PathBuf> = find_files(&options.glob, &options.roots())?
+            .into_iter()
+            .filter(|path| is_pdf(path))
+            .collect();
+        if files.is_empty() {
+            return Ok(SearchStatus::NoFilesFound);
+        }
+        let mut found = false;
+        let mut warnings = Vec::new();
+        for path in files {
+            // A single pdf is not interrupted, the next one is just never started.
+            if cancel.is_cancelled() {
+                return Ok(SearchStatus::Cancelled(vec![]));
+            }
+            // Pdfs we can't read are skipped, like rga does, but reported.
+            let search_matches = match search_pdf(&path, &regex, options.context) {
+                Ok(search_matches) => search_matches,
+                Err(err) => {
+                    warnings.push(FileWarning::new(Some(path.clone()), &err.to_string()));
+                    vec![]
+                }
+            };
+            found |= !search_matches.is_empty();
+            on_file(SearchedFile { path, search_matches });
+        }
+        Ok(SearchStatus::from_results(found, warnings))
+    }
+}
+
+/// Extracts one pdf and searches its text and metadata.
+pub fn search_pdf(path: &Path, regex: &Regex, context: usize) -> Result<Vec<SearchMatch>, lopdf::Error> {
+    let (pages, info) = extract(path)?;
+    Ok(with_metadata(info.search(path, regex), search_pages(path, &pages, regex, context)))
+}
+
+/// The matches in the metadata and in the text together, in page order. The metadata comes first on its page.
+pub fn with_metadata(mut metadata_matches: Vec<SearchMatch>, text_matches: Vec<SearchMatch>) -> Vec<SearchMatch> {
+    metadata_matches.extend(text_matches);
+    metadata_matches.sort_by_key(|search_match| (search_match.page, search_match.line));
+    metadata_matches
+}
+
+pub fn is_pdf(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"))
+}
+
+/// Every line of the document with the page it is on. Line numbers count from the start of the document,
+/// like they do in rga's output, so line N is at index N - 1.
+pub fn page_lines(pages: &[String]) -> Vec<(usize, &str)> {
+    pages
+        .iter()
+        .enumerate()
+        .flat_map(|(index, text)| text.lines().map(move |line| (index + 1, line.trim())))
+        .collect()
+}
+
+pub fn search_pages(path: &Path, pages: &[String], regex: &Regex, context: usize) -> Vec<SearchMatch> {
+    let lines = page_lines(pages);
+    (0..lines.len())
+        .filter(|&index| regex.is_match(lines[index].1))
+        .map(|index| line_match(path, &lines, index, regex, context))
+        .collect()
+}
+
+/// The SearchMatch for the line at `index`, with the lines around it as context.
+pub fn line_match(path: &Path, lines: &[(usize, &str)], index: usize, regex: &Regex, context: usize) -> SearchMatch {
+    let (page, line) = lines[index];
+    let start = index.saturating_sub(context);
+    let end = (index + context + 1).min(lines.len());
+    let context: Vec<&str> = lines[start..end]
+        .iter()
+        .map(|(_, line)| *line)
+        .filter(|line| SearchMatch::string_contains_ascii_letters(line))
+        .collect();
+    let submatches = regex.find_iter(line).map(|found| found.range()).collect();
+    SearchMatch::new(path.to_path_buf(), page, index + 1, line.to_string(), context.join("\n"))
+        .with_submatches(submatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::RegexBuilder;
+
+    #[test]
+    fn extract_test_pdf() {
+        let pages = extract_pages(Path::new("test_assets/test.pdf")).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].starts_with("This is a heading - Testing\nThis is a subheading - Test\n"));
+    }
+
+    #[test]
+    fn search_pages_counts_lines_across_pages() {
+        let pages = vec!["first\nbolt".to_string(), "●\ntorque and bolt".to_string()];
+        let regex = RegexBuilder::new("BOLT").case_insensitive(true).build().unwrap();
+        let matches = search_pages(Path::new("a.pdf"), &pages, &regex, 8);
+        assert_eq!(matches.len(), 2);
+        assert_eq!((matches[1].page, matches[1].line), (2, 4));
+        assert_eq!(matches[1].content, "torque and bolt");
+        assert_eq!(matches[1].context, "first\nbolt\ntorque and bolt");
+        assert_eq!(matches[1].submatches, vec![11..15]);
+    }
+
+    #[test]
+    fn search_test_pdf() {
+        let (status, matches) = PdfTextBackend::new().search(&SearchOptions::new("test_assets/test.*", "subheading")).unwrap();
+        assert_eq!(status, SearchStatus::Found);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].path, PathBuf::from("test_assets/test.pdf"));
+        assert_eq!((matches[0].page, matches[0].line), (1, 2));
+        assert_eq!(matches[0].content, "This is a subheading - Test");
+    }
+
+    #[test]
+    fn search_invalid_regex() {
+        let result = PdfTextBackend::new().search(&SearchOptions::new("test_assets/test.*", "(unclosed"));
+        assert!(matches!(result, Err(SearchError::InvalidSearchTerm(_))));
+    }
+
+    #[test]
+    fn cancelled_search() {
+        let cancel = CancelHandle::new();
+        cancel.cancel();
+        let mut searched_files = 0;
+        let status = PdfTextBackend::new()
+            .search_files(&SearchOptions::new("test_assets/test.*", "test"), &cancel, &mut |_| searched_files += 1)
+            .unwrap();
+        assert_eq!(status, SearchStatus::Cancelled(vec![]));
+        assert_eq!(searched_files, 0);
+    }
+}
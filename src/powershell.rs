@@ -1,32 +1,36 @@
 use std::process::{Command, Output};
 
-// pub fn run_powershell_command(command: &str) -> String {
-//     let mut powershell = Command::new("powershell.exe");
-//     let command = powershell.args(["-Command", command]);
-//     let output = command.output().unwrap();
-//     let result = output.stdout;
-//     let string = String::from_utf8_lossy(&result).to_string();
-//     string.trim().to_string()
-// }
+pub fn powershell_command(cmd_string: &str) -> Command {
+    let mut powershell = Command::new("powershell.exe");
+    powershell.args(["-Command", cmd_string]);
+    powershell
+}
 
 pub fn run_powershell_command(cmd_string: &str) -> std::io::Result<Output> {
-    let mut powershell = Command::new("powershell.exe");
-    let command = powershell.args(["-Command", cmd_string]);
-    command.output()
+    powershell_command(cmd_string).output()
 }
 
-pub fn get_stdout(output: &Output) -> String {
-    String::from_utf8_lossy(&output.stdout).trim().to_string()
+// Single quoted strings are taken literally by powershell, only the quote itself has to be doubled.
+pub fn quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "''"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn quote_escapes_single_quotes() {
+        assert_eq!("'it''s'", quote("it's"));
+        assert_eq!("'*.pdf'", quote("*.pdf"));
+    }
+
+    #[cfg(windows)]
     #[test]
     fn test_run_powershell_command() {
+        use crate::runner::get_stdout;
         let output = run_powershell_command("echo 'aaa\nbbb'").expect("Could not run command.");
         let result_string = get_stdout(&output);
         assert_eq!("aaa\nbbb", result_string);
     }
-}
\ No newline at end of file
+}
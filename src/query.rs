@@ -0,0 +1,626 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use regex::{Regex, RegexBuilder};
+use crate::index::tokenize;
+use crate::metadata::DocumentInfo;
+use crate::search_backend::{CancelHandle, FileGlob, SearchBackend, SearchedFile};
+use crate::search_error::SearchError;
+use crate::search_match::SearchMatch;
+use crate::search_options::{CaseMode, SearchOptions};
+use crate::search_status::SearchStatus;
+
+// How many words NEAR allows between its words when no /n is given.
+pub const DEFAULT_NEAR: usize = 10;
+
+/// A word or a quoted phrase. The words of a phrase can be on consecutive lines.
+#[derive(Debug)]
+pub struct Term {
+    pub text: String,
+    // What the backend looks for: any of the words, as it only sees one line at a time.
+    // The phrase is checked over the lines of the page, see matching_pages.
+    pattern: String,
+    regex: Regex,
+}
+
+impl Term {
+    fn new(text: &str, ignore_case: bool) -> Result<Term, SearchError> {
+        let words: Vec<String> = text.split_whitespace().map(regex::escape).collect();
+        if words.is_empty() {
+            return Err(SearchError::InvalidSearchTerm("a phrase is empty".to_string()));
+        }
+        let regex = RegexBuilder::new(&words.join(r"\s+")).case_insensitive(ignore_case).build()?;
+        Ok(Term { text: text.to_string(), pattern: words.join("|"), regex })
+    }
+
+    pub fn regex(&self) -> &Regex {
+        &self.regex
+    }
+}
+
+/// A condition on the page itself instead of its text, like `file:specs/`, `page:1-20` or `title:ISO`.
+#[derive(Debug)]
+pub enum Field {
+    // Part of the path.
+    File(String),
+    // file: with a * or ? in it.
+    FileGlob(FileGlob),
+    // Both ends included.
+    Page(usize, usize),
+    // Part of a field of the document info, lower case.
+    Info(InfoField, String),
+    // YYYY, YYYY-MM or YYYY-MM-DD, a date matches when it is in the range at that precision.
+    CreationDate(Option<String>, Option<String>),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum InfoField {
+    Title,
+    Author,
+    Subject,
+    Keywords,
+}
+
+impl InfoField {
+    fn value(self, info: &DocumentInfo) -> Option<&str> {
+        match self {
+            InfoField::Title => info.title.as_deref(),
+            InfoField::Author => info.author.as_deref(),
+            InfoField::Subject => info.subject.as_deref(),
+            InfoField::Keywords => info.keywords.as_deref(),
+        }
+    }
+}
+
+const FIELDS: [&str; 9] = ["file", "path", "page", "title", "author", "subject", "keywords", "creationdate", "created"];
+
+impl Field {
+    fn parse(name: &str, value: &str) -> Result<Field, SearchError> {
+        let info = |field| Ok(Field::Info(field, value.to_lowercase()));
+        match name {
+            "file" | "path" if value.contains(['*', '?']) => Ok(Field::FileGlob(FileGlob::new(value)?)),
+            "file" | "path" => Ok(Field::File(value.to_lowercase().replace('\\', "/"))),
+            "page" => {
+                let number = |number: Option<&str>, default| match number {
+                    Some(number) => number.parse().map_err(|_| invalid(format!("page:{} is not a page or a range like 1-20", value))),
+                    None => Ok(default),
+                };
+                let (from, to) = range(value, true);
+                Ok(Field::Page(number(from, 1)?, number(to, usize::MAX)?))
+            }
+            "title" => info(InfoField::Title),
+            "author" => info(InfoField::Author),
+            "subject" => info(InfoField::Subject),
+            "keywords" => info(InfoField::Keywords),
+            _ => {
+                let (from, to) = range(value, false);
+                let is_date = |date: &str| [4, 7, 10].contains(&date.len()) && date.chars().all(|c| c.is_ascii_digit() || c == '-');
+                if !from.into_iter().chain(to).all(is_date) {
+                    return Err(invalid(format!("{}:{} is not a date like 2020, 2020-03 or 2020-03-15, or a range like 2019..2021", name, value)));
+                }
+                Ok(Field::CreationDate(from.map(str::to_string), to.map(str::to_string)))
+            }
+        }
+    }
+
+    fn is_match(&self, page: &Page) -> bool {
+        match self {
+            Field::File(part) => page.path.to_string_lossy().to_lowercase().replace('\\', "/").contains(part.as_str()),
+            Field::FileGlob(glob) => glob.is_match(page.path),
+            Field::Page(from, to) => (*from..=*to).contains(&page.number),
+            Field::Info(field, part) => page
+                .info
+                .and_then(|info| field.value(info))
+                .is_some_and(|value| value.to_lowercase().contains(part.as_str())),
+            Field::CreationDate(from, to) => page.info.and_then(|info| info.creation_date.as_deref()).is_some_and(|date| {
+                from.as_ref().is_none_or(|from| date >= from.as_str())
+                    && to.as_ref().is_none_or(|to| date[..date.len().min(to.len())] <= *to.as_str())
+            }),
+        }
+    }
+}
+
+// 5 is 5 to 5, 5.. and ..20 are open. Pages can use a dash as well, dates have dashes in them.
+fn range(value: &str, dash: bool) -> (Option<&str>, Option<&str>) {
+    let split = value.split_once("..").or_else(|| dash.then(|| value.split_once('-')).flatten());
+    match split {
+        Some((from, to)) => ((!from.is_empty()).then_some(from), (!to.is_empty()).then_some(to)),
+        None => (Some(value), Some(value)),
+    }
+}
+
+// What a page is checked against.
+struct Page<'a> {
+    path: &'a Path,
+    number: usize,
+    info: Option<&'a DocumentInfo>,
+    // The text of consecutive lines with any of the words, a phrase or NEAR does not reach over a gap.
+    runs: Vec<String>,
+}
+
+/// A search term like `torque AND (bolt OR nut) NOT "lock washer"` or `torque NEAR/5 bolt page:1-20`.
+/// Operators are upper case, words next to each other have to be on the same page, like AND.
+/// Fields are written as `name:value`, with quotes around a value with spaces: `title:"ISO 9001"`.
+/// It is answered page by page: a page matches when the query is true for the text of the lines
+/// that have any of its words. Pages without any of the words are never seen, so a query that only
+/// excludes words is refused.
+#[derive(Debug)]
+pub enum Query {
+    Term(Term),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+    // At most this many words between the two terms.
+    Near(Term, Term, usize),
+    Field(Field),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Word(String),
+    Phrase(String),
+    And,
+    Or,
+    Not,
+    Near(usize),
+    Field(String, String),
+    Open,
+    Close,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => format!("'{}'", word),
+            Token::Phrase(phrase) => format!("\"{}\"", phrase),
+            Token::And => "AND".to_string(),
+            Token::Or => "OR".to_string(),
+            Token::Not => "NOT".to_string(),
+            Token::Near(distance) => format!("NEAR/{}", distance),
+            Token::Field(name, value) => format!("{}:{}", name, value),
+            Token::Open => "(".to_string(),
+            Token::Close => ")".to_string(),
+        }
+    }
+}
+
+fn invalid(message: String) -> SearchError {
+    SearchError::InvalidSearchTerm(message)
+}
+
+fn tokens(text: &str) -> Result<Vec<Token>, SearchError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '"' => {
+                let mut phrase = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => phrase.push(c),
+                        None => return Err(invalid("a quote is not closed".to_string())),
+                    }
+                }
+                tokens.push(Token::Phrase(phrase));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "()\"".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                if let Some((name, value)) = word.split_once(':') {
+                    let name = name.to_lowercase();
+                    if FIELDS.contains(&name.as_str()) {
+                        // title:"ISO 9001"
+                        let value = match chars.peek() {
+                            Some('"') if value.is_empty() => {
+                                chars.next();
+                                let value: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                                value
+                            }
+                            _ => value.to_string(),
+                        };
+                        if value.trim().is_empty() {
+                            return Err(invalid(format!("{}: needs a value", name)));
+                        }
+                        tokens.push(Token::Field(name, value));
+                        continue;
+                    }
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    "NEAR" => Token::Near(DEFAULT_NEAR),
+                    _ => match word.strip_prefix("NEAR/") {
+                        Some(distance) => Token::Near(
+                            distance.parse().map_err(|_| invalid(format!("{} needs a number of words, like NEAR/5", word)))?,
+                        ),
+                        None => Token::Word(word),
+                    },
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    ignore_case: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Query, SearchError> {
+        let mut query = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<Query, SearchError> {
+        let mut query = self.near()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.next();
+                }
+                Some(Token::Word(_) | Token::Phrase(_) | Token::Field(..) | Token::Not | Token::Open) => {}
+                _ => return Ok(query),
+            }
+            query = Query::And(Box::new(query), Box::new(self.near()?));
+        }
+    }
+
+    fn near(&mut self) -> Result<Query, SearchError> {
+        let mut query = self.not()?;
+        while let Some(&Token::Near(distance)) = self.peek() {
+            self.next();
+            query = match (query, self.not()?) {
+                (Query::Term(left), Query::Term(right)) => Query::Near(left, right, distance),
+                _ => return Err(invalid("NEAR only works between two words or phrases".to_string())),
+            };
+        }
+        Ok(query)
+    }
+
+    fn not(&mut self) -> Result<Query, SearchError> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Query::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Query, SearchError> {
+        match self.next() {
+            Some(Token::Word(text) | Token::Phrase(text)) => Ok(Query::Term(Term::new(&text, self.ignore_case)?)),
+            Some(Token::Field(name, value)) => Ok(Query::Field(Field::parse(&name, &value)?)),
+            Some(Token::Open) => {
+                let query = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(query),
+                    _ => Err(invalid("a parenthesis is not closed".to_string())),
+                }
+            }
+            Some(token) => Err(invalid(format!("did not expect {} there", token.describe()))),
+            None => Err(invalid("the query ends where a word was expected".to_string())),
+        }
+    }
+}
+
+impl Query {
+    pub fn parse(text: &str, case: CaseMode) -> Result<Query, SearchError> {
+        let mut parser = Parser {
+            tokens: tokens(text)?,
+            position: 0,
+            ignore_case: case.ignores_case(&Query::words(text)),
+        };
+        let query = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(invalid(format!("did not expect {} there", token.describe())));
+        }
+        if query.without_words().0 {
+            return Err(invalid("the query needs a word that is not excluded with NOT, fields alone are not enough".to_string()));
+        }
+        Ok(query)
+    }
+
+    /// The words and phrases without the operators, smart case looks at these.
+    pub fn words(text: &str) -> String {
+        match tokens(text) {
+            Ok(tokens) => tokens
+                .into_iter()
+                .filter_map(|token| match token {
+                    Token::Word(text) | Token::Phrase(text) => Some(text),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+            Err(_) => text.to_string(),
+        }
+    }
+
+    /// A regex for the lines with any word of the terms, also the excluded ones, so a page that has them is known to.
+    pub fn pattern(&self) -> String {
+        let mut terms = Vec::new();
+        self.terms(false, &mut terms);
+        terms.iter().map(|(term, _)| format!("(?:{})", term.pattern)).collect::<Vec<_>>().join("|")
+    }
+
+    /// The terms a page should have, the ones behind NOT are left out.
+    pub fn wanted_terms(&self) -> Vec<&Term> {
+        let mut terms = Vec::new();
+        self.terms(false, &mut terms);
+        terms.into_iter().filter(|(_, negated)| !negated).map(|(term, _)| term).collect()
+    }
+
+    fn terms<'a>(&'a self, negated: bool, terms: &mut Vec<(&'a Term, bool)>) {
+        match self {
+            Query::Term(term) => terms.push((term, negated)),
+            Query::And(left, right) | Query::Or(left, right) => {
+                left.terms(negated, terms);
+                right.terms(negated, terms);
+            }
+            Query::Not(query) => query.terms(!negated, terms),
+            Query::Near(left, right, _) => {
+                terms.push((left, negated));
+                terms.push((right, negated));
+            }
+            Query::Field(_) => {}
+        }
+    }
+
+    // Whether the query can be true, and whether it can be false, for a page without any of the words.
+    // Such pages are never seen, so a query that can be true for them is refused.
+    fn without_words(&self) -> (bool, bool) {
+        match self {
+            Query::Term(_) | Query::Near(..) => (false, true),
+            Query::Field(_) => (true, true),
+            Query::And(left, right) => {
+                let ((left_true, left_false), (right_true, right_false)) = (left.without_words(), right.without_words());
+                (left_true && right_true, left_false || right_false)
+            }
+            Query::Or(left, right) => {
+                let ((left_true, left_false), (right_true, right_false)) = (left.without_words(), right.without_words());
+                (left_true || right_true, left_false && right_false)
+            }
+            Query::Not(query) => {
+                let (can_be_true, can_be_false) = query.without_words();
+                (can_be_false, can_be_true)
+            }
+        }
+    }
+
+    /// Whether the query looks at the document info, which then has to be read.
+    pub fn needs_info(&self) -> bool {
+        match self {
+            Query::Field(Field::Info(..) | Field::CreationDate(..)) => true,
+            Query::And(left, right) | Query::Or(left, right) => left.needs_info() || right.needs_info(),
+            Query::Not(query) => query.needs_info(),
+            _ => false,
+        }
+    }
+
+    fn is_match(&self, page: &Page) -> bool {
+        match self {
+            Query::Term(term) => page.runs.iter().any(|run| term.regex.is_match(run)),
+            Query::And(left, right) => left.is_match(page) && right.is_match(page),
+            Query::Or(left, right) => left.is_match(page) || right.is_match(page),
+            Query::Not(query) => !query.is_match(page),
+            Query::Near(left, right, distance) => page.runs.iter().any(|run| is_near(run, left, right, *distance)),
+            Query::Field(field) => field.is_match(page),
+        }
+    }
+
+    /// One match for every page the query is true for, out of the matched lines of a file.
+    /// It is the first line of the page, with all of the page's lines as context.
+    pub fn matching_pages(&self, search_matches: Vec<SearchMatch>, info: Option<&DocumentInfo>) -> Vec<SearchMatch> {
+        let mut pages: BTreeMap<usize, Vec<SearchMatch>> = BTreeMap::new();
+        for search_match in search_matches {
+            pages.entry(search_match.page).or_default().push(search_match);
+        }
+        pages
+            .into_values()
+            .filter_map(|mut lines| {
+                lines.sort_by_key(|search_match| search_match.line);
+                lines.dedup_by_key(|search_match| search_match.line);
+                let page = Page { path: &lines[0].path, number: lines[0].page, info, runs: runs(&lines) };
+                if !self.is_match(&page) {
+                    return None;
+                }
+                let context = lines.iter().map(|search_match| search_match.content.as_str()).collect::<Vec<_>>().join("\n");
+                let first = lines.swap_remove(0);
+                Some(SearchMatch { context, ..first })
+            })
+            .collect()
+    }
+}
+
+fn runs(lines: &[SearchMatch]) -> Vec<String> {
+    let mut runs: Vec<String> = Vec::new();
+    let mut previous_line = None;
+    for search_match in lines {
+        match runs.last_mut() {
+            // Metadata is on line 0, it does not run on into the text.
+            Some(run) if previous_line.is_some_and(|line: usize| line > 0 && line + 1 == search_match.line) => {
+                run.push('\n');
+                run.push_str(&search_match.content);
+            }
+            _ => runs.push(search_match.content.clone()),
+        }
+        previous_line = Some(search_match.line);
+    }
+    runs
+}
+
+fn is_near(text: &str, left: &Term, right: &Term, distance: usize) -> bool {
+    left.regex.find_iter(text).any(|left_match| {
+        right.regex.find_iter(text).any(|right_match| {
+            let between = if left_match.end() <= right_match.start() {
+                &text[left_match.end()..right_match.start()]
+            } else if right_match.end() <= left_match.start() {
+                &text[right_match.end()..left_match.start()]
+            } else {
+                ""
+            };
+            tokenize(between).count() <= distance
+        })
+    })
+}
+
+/// Answers a query with another backend: it finds the lines with any of the words, which are then
+/// grouped by page and checked against the query.
+pub struct QueryBackend<'a> {
+    backend: &'a dyn SearchBackend,
+}
+
+impl<'a> QueryBackend<'a> {
+    pub fn new(backend: &'a dyn SearchBackend) -> QueryBackend<'a> {
+        QueryBackend { backend }
+    }
+}
+
+impl SearchBackend for QueryBackend<'_> {
+    fn search_files(&self, options: &SearchOptions, cancel: &CancelHandle, on_file: &mut dyn FnMut(SearchedFile)) -> Result<SearchStatus, SearchError> {
+        let query = Query::parse(&options.search_term, options.case)?;
+        let mut found = false;
+        let status = self.backend.search_files(options, cancel, &mut |searched_file| {
+            let info = (query.needs_info() && !searched_file.search_matches.is_empty())
+                .then(|| self.backend.document_info(&searched_file.path))
+                .flatten();
+            let search_matches = query.matching_pages(searched_file.search_matches, info.as_ref());
+            found |= !search_matches.is_empty();
+            on_file(SearchedFile { path: searched_file.path, search_matches });
+        })?;
+        // The backend found lines, that does not mean a page matched.
+        Ok(match status {
+            SearchStatus::Found => SearchStatus::from_results(found, vec![]),
+            SearchStatus::PartiallyFound(warnings) => SearchStatus::from_results(found, warnings),
+            status => status,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::pdf_text::PdfTextBackend;
+
+    fn page_matches(query: &str, lines: &[(usize, usize, &str)]) -> Vec<(usize, usize)> {
+        let query = Query::parse(query, CaseMode::Insensitive).unwrap();
+        let search_matches = lines
+            .iter()
+            .map(|&(page, line, content)| SearchMatch::new(PathBuf::from("a.pdf"), page, line, content.to_string(), String::new()))
+            .collect();
+        query.matching_pages(search_matches, None).iter().map(|search_match| (search_match.page, search_match.line)).collect()
+    }
+
+    #[test]
+    fn pages_not_lines() {
+        let lines = [(1, 1, "torque"), (1, 5, "bolt"), (2, 9, "torque only"), (3, 12, "bolt and torque"), (3, 13, "lock washer")];
+        assert_eq!(page_matches("torque AND bolt", &lines), vec![(1, 1), (3, 12)]);
+        assert_eq!(page_matches("torque bolt", &lines), vec![(1, 1), (3, 12)]);
+        assert_eq!(page_matches("torque NOT bolt", &lines), vec![(2, 9)]);
+        assert_eq!(page_matches("bolt AND NOT \"lock washer\"", &lines), vec![(1, 1)]);
+        assert_eq!(page_matches("(washer OR only) AND torque", &lines), vec![(2, 9), (3, 12)]);
+    }
+
+    #[test]
+    fn phrases_and_near() {
+        let lines = [(1, 1, "the torque of"), (1, 2, "the bolt"), (2, 7, "torque"), (2, 9, "bolt"), (3, 20, "torque a b c d e f bolt")];
+        assert_eq!(page_matches("\"of the bolt\"", &lines), vec![(1, 1)]);
+        assert_eq!(page_matches("torque NEAR/2 bolt", &lines), vec![(1, 1)]);
+        assert_eq!(page_matches("bolt NEAR/6 torque", &lines), vec![(1, 1), (3, 20)]);
+        assert_eq!(page_matches("torque NEAR bolt", &lines), vec![(1, 1), (3, 20)]);
+    }
+
+    #[test]
+    fn phrase_over_two_lines_of_a_pdf() {
+        // test.pdf has "This is a heading - Testing" and "This is a subheading - Test" on lines 1 and 2.
+        let mut options = SearchOptions::new("test_assets/test.pdf", "\"testing this is a subheading\"");
+        options.syntax = crate::search_options::Syntax::Query;
+        let (status, search_matches) = QueryBackend::new(&PdfTextBackend::new()).search(&options).unwrap();
+        assert_eq!(status, SearchStatus::Found);
+        assert_eq!(search_matches.len(), 1);
+        assert_eq!((search_matches[0].page, search_matches[0].line), (1, 1));
+        options.search_term = "\"testing this is a heading\"".to_string();
+        assert_eq!(QueryBackend::new(&PdfTextBackend::new()).search(&options).unwrap().0, SearchStatus::NoMatchesFound);
+    }
+
+    #[test]
+    fn context_is_the_page() {
+        let query = Query::parse("torque bolt", CaseMode::Insensitive).unwrap();
+        let search_matches = vec![
+            SearchMatch::new(PathBuf::from("a.pdf"), 4, 30, "bolt".to_string(), "around bolt".to_string()),
+            SearchMatch::new(PathBuf::from("a.pdf"), 4, 2, "Torque and torque".to_string(), String::new()).with_submatches(vec![0..6, 11..17]),
+        ];
+        let pages = query.matching_pages(search_matches, None);
+        assert_eq!(pages.len(), 1);
+        assert_eq!((pages[0].line, pages[0].content.as_str(), pages[0].context.as_str()), (2, "Torque and torque", "Torque and torque\nbolt"));
+        assert_eq!(pages[0].submatches, vec![0..6, 11..17]);
+    }
+
+    #[test]
+    fn fields() {
+        let info = DocumentInfo {
+            title: Some("ISO 9001 checklist".to_string()),
+            creation_date: Some("2020-03-15".to_string()),
+            ..DocumentInfo::default()
+        };
+        let page = |number| Page { path: Path::new("docs/specs/a.pdf"), number, info: Some(&info), runs: vec!["torque".to_string()] };
+        let is_match = |query: &str, number| Query::parse(query, CaseMode::Insensitive).unwrap().is_match(&page(number));
+        assert!(is_match("torque file:specs/", 1));
+        assert!(!is_match("torque file:manuals/", 1));
+        assert!(is_match("torque file:*.pdf", 1));
+        assert!(is_match("torque file:docs/*/a.pdf", 1));
+        assert!(is_match("torque page:1-20", 20));
+        assert!(!is_match("torque page:1-20", 21));
+        assert!(is_match("torque page:5", 5) && is_match("torque page:5..", 50) && is_match("torque page:-5", 1));
+        assert!(is_match("torque title:iso", 1));
+        assert!(is_match("torque title:\"ISO 9001\"", 1));
+        assert!(!is_match("torque author:smith", 1));
+        assert!(is_match("torque NOT author:smith", 1));
+        assert!(is_match("torque creationdate:2020", 1));
+        assert!(is_match("torque created:2019..2020-03", 1));
+        assert!(!is_match("torque created:2020-04..", 1));
+        assert!(!Query::parse("torque title:iso", CaseMode::Insensitive).unwrap().is_match(&Page { info: None, ..page(1) }));
+        assert!(Query::parse("torque title:iso", CaseMode::Insensitive).unwrap().needs_info());
+        assert!(!Query::parse("torque page:2", CaseMode::Insensitive).unwrap().needs_info());
+        assert_eq!(Query::words("Torque title:ISO"), "Torque");
+    }
+
+    #[test]
+    fn invalid_queries() {
+        for query in ["", "torque AND", "(torque", "torque)", "NOT torque", "torque OR NOT bolt", "a NEAR/x b", "(a b) NEAR c", "\"open", "title:ISO", "torque OR page:1", "page:x", "created:march", "title:"] {
+            assert!(matches!(Query::parse(query, CaseMode::Insensitive), Err(SearchError::InvalidSearchTerm(_))), "{}", query);
+        }
+        let query = Query::parse("Torque and \"M8 bolt\" NOT nut", CaseMode::Smart).unwrap();
+        let wanted: Vec<&str> = query.wanted_terms().iter().map(|term| term.text.as_str()).collect();
+        assert_eq!(wanted, vec!["Torque", "and", "M8 bolt"]);
+        let page = Page { path: Path::new("a.pdf"), number: 1, info: None, runs: vec!["torque".to_string()] };
+        assert!(!Query::parse("Torque", CaseMode::Smart).unwrap().is_match(&page));
+        assert!(Query::parse("torque OR", CaseMode::Insensitive).is_err());
+    }
+}
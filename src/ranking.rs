@@ -0,0 +1,314 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use crate::index::tokenize;
+use crate::query::Query;
+use crate::search_match::SearchMatch;
+use crate::search_options::{SearchOptions, Syntax};
+
+// The usual BM25 constants. K1 is how fast more hits of a term stop adding up, B how much long passages are punished.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+/// The order matches are shown in.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SortOrder {
+    // Best BM25 score first.
+    Relevance,
+    // By file, then page and line, the way rga prints them.
+    Path,
+    // First pages first, over all files.
+    Page,
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "relevance" | "score" => Ok(SortOrder::Relevance),
+            "path" | "file" => Ok(SortOrder::Path),
+            "page" => Ok(SortOrder::Page),
+            _ => Err(format!("unknown sort order '{}', expected relevance, path or page", s)),
+        }
+    }
+}
+
+impl SortOrder {
+    pub fn next(self) -> SortOrder {
+        match self {
+            SortOrder::Relevance => SortOrder::Path,
+            SortOrder::Path => SortOrder::Page,
+            SortOrder::Page => SortOrder::Relevance,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortOrder::Relevance => "relevance",
+            SortOrder::Path => "path",
+            SortOrder::Page => "page",
+        }
+    }
+}
+
+/// What BM25 knows about the text that was searched besides the matches, the index when there is one.
+/// A line of text is a document here.
+pub trait Corpus {
+    fn lines(&self) -> usize;
+    fn words_per_line(&self) -> f64;
+    // How many lines have the term.
+    fn lines_with(&self, term: &str) -> usize;
+}
+
+/// Scores matches with BM25, every match and the lines around it is a passage. How rare a term is and how long
+/// passages usually are comes from the corpus when there is one. Without it the matches found so far are all there is
+/// to go by, so a term that is on every matched line counts for little even when it is rare in the pdfs.
+#[derive(Debug, Default)]
+pub struct Ranker {
+    terms: Vec<String>,
+    // For a regex the terms are what it matched, every match can add one.
+    terms_from_matches: bool,
+    passages: usize,
+    passage_words: usize,
+    passage_lines: usize,
+    // For every term, how many passages have it.
+    containing: HashMap<String, usize>,
+    // Asked from the corpus once per term.
+    corpus_lines_with: HashMap<String, usize>,
+}
+
+impl Ranker {
+    pub fn new(options: &SearchOptions) -> Ranker {
+        let terms = literal_terms(options);
+        Ranker { terms_from_matches: terms.is_none(), terms: terms.unwrap_or_default(), ..Ranker::default() }
+    }
+
+    /// The scores of the matches. They count towards how the matches after them are scored, so scoring
+    /// the matches batch by batch gives about the same as scoring them all at once.
+    pub fn score(&mut self, search_matches: &[SearchMatch], corpus: Option<&dyn Corpus>) -> Vec<f64> {
+        if self.terms_from_matches {
+            for text in matched_texts(search_matches) {
+                if !self.terms.contains(&text) {
+                    self.terms.push(text);
+                }
+            }
+        }
+        let passages: Vec<String> = search_matches.iter().map(passage).collect();
+        let lengths: Vec<f64> = passages.iter().map(|passage| tokenize(passage).count().max(1) as f64).collect();
+        self.passages += passages.len();
+        self.passage_words += lengths.iter().sum::<f64>() as usize;
+        self.passage_lines += passages.iter().map(|passage| passage.lines().count().max(1)).sum::<usize>();
+        for term in &self.terms {
+            *self.containing.entry(term.clone()).or_default() += passages.iter().filter(|passage| passage.contains(term.as_str())).count();
+        }
+        let corpus = corpus.filter(|corpus| corpus.lines() > 0);
+        let passages_seen = self.passages.max(1) as f64;
+        let average_length = match corpus {
+            Some(corpus) => corpus.words_per_line() * self.passage_lines as f64 / passages_seen,
+            None => self.passage_words as f64 / passages_seen,
+        }
+        .max(1.0);
+        let inverse_frequencies: Vec<f64> = self
+            .terms
+            .iter()
+            .map(|term| {
+                let (count, containing) = match corpus {
+                    Some(corpus) => {
+                        let containing = *self.corpus_lines_with.entry(term.clone()).or_insert_with(|| corpus.lines_with(term));
+                        (corpus.lines() as f64, containing as f64)
+                    }
+                    None => (passages_seen, self.containing[term] as f64),
+                };
+                ((count - containing + 0.5) / (containing + 0.5) + 1.0).ln()
+            })
+            .collect();
+        passages
+            .iter()
+            .zip(&lengths)
+            .map(|(passage, length)| {
+                self.terms
+                    .iter()
+                    .zip(&inverse_frequencies)
+                    .map(|(term, inverse_frequency)| {
+                        let frequency = passage.matches(term.as_str()).count() as f64;
+                        inverse_frequency * frequency * (K1 + 1.0) / (frequency + K1 * (1.0 - B + B * length / average_length))
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+/// Sorts the matches, ties keep the order they came in. In relevance order the scores are sorted with their
+/// matches, in the other orders they are not used. Sorting matches that were sorted before and then had
+/// more added only has to merge the two.
+pub fn sort_scored(search_matches: &mut Vec<SearchMatch>, scores: &mut Vec<f64>, order: SortOrder) {
+    match order {
+        SortOrder::Relevance => {
+            let mut scored: Vec<(f64, SearchMatch)> = std::mem::take(scores).into_iter().zip(std::mem::take(search_matches)).collect();
+            scored.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+            (*scores, *search_matches) = scored.into_iter().unzip();
+        }
+        SortOrder::Path => search_matches.sort_by(|a, b| (&a.path, a.page, a.line).cmp(&(&b.path, b.page, b.line))),
+        SortOrder::Page => search_matches.sort_by(|a, b| (a.page, &a.path, a.line).cmp(&(b.page, &b.path, b.line))),
+    }
+}
+
+// The words of a plain search term or a query. None for a regex, its terms are what it matched,
+// "torque|bolt" ranks by torque and bolt.
+fn literal_terms(options: &SearchOptions) -> Option<Vec<String>> {
+    let term = &options.search_term;
+    if options.syntax == Syntax::Query {
+        let query = Query::parse(term, options.case);
+        return Some(query.map(|query| query.wanted_terms().iter().map(|term| term.text.to_lowercase()).collect()).unwrap_or_default());
+    }
+    if options.syntax == Syntax::Fixed || regex::escape(term) == *term {
+        let words: Vec<String> = tokenize(term).collect();
+        if !words.is_empty() {
+            return Some(words);
+        }
+    }
+    None
+}
+
+fn matched_texts(search_matches: &[SearchMatch]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    search_matches
+        .iter()
+        .flat_map(|search_match| search_match.submatches.iter().filter_map(|range| search_match.content.get(range.clone())))
+        .map(|text| text.to_lowercase())
+        .filter(|text| !text.trim().is_empty() && seen.insert(text.clone()))
+        .collect()
+}
+
+// The context has the matched line in it, when it is empty the line is all there is.
+fn passage(search_match: &SearchMatch) -> String {
+    let text = if search_match.context.is_empty() { &search_match.content } else { &search_match.context };
+    text.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn search_match(path: &str, page: usize, context: &str) -> SearchMatch {
+        SearchMatch::new(PathBuf::from(path), page, page * 10, context.lines().next().unwrap().to_string(), context.to_string())
+    }
+
+    fn scores(search_matches: &[SearchMatch], term: &str) -> Vec<f64> {
+        Ranker::new(&SearchOptions::new("*.pdf", term)).score(search_matches, None)
+    }
+
+    fn sort_matches(search_matches: &mut Vec<SearchMatch>, order: SortOrder, options: &SearchOptions) {
+        let mut scores = Ranker::new(options).score(search_matches, None);
+        sort_scored(search_matches, &mut scores, order);
+    }
+
+    // Bolt is on one line in ten thousand, torque on every other one.
+    struct Manuals;
+
+    impl Corpus for Manuals {
+        fn lines(&self) -> usize {
+            10_000
+        }
+        fn words_per_line(&self) -> f64 {
+            2.0
+        }
+        fn lines_with(&self, term: &str) -> usize {
+            if term == "bolt" { 1 } else { 5_000 }
+        }
+    }
+
+    #[test]
+    fn term_frequency_and_length() {
+        let search_matches = vec![
+            search_match("a.pdf", 1, "torque\nsome other words about nothing in particular at all"),
+            search_match("b.pdf", 1, "torque torque"),
+            search_match("c.pdf", 1, "unrelated"),
+        ];
+        let scores = scores(&search_matches, "torque");
+        assert!(scores[1] > scores[0]);
+        assert!(scores[0] > scores[2]);
+        assert_eq!(scores[2], 0.0);
+    }
+
+    #[test]
+    fn rare_terms_count_more() {
+        let search_matches = vec![
+            search_match("a.pdf", 1, "bolt torque"),
+            search_match("b.pdf", 1, "bolt washer"),
+            search_match("c.pdf", 1, "bolt nut"),
+        ];
+        let scores = scores(&search_matches, "bolt torque");
+        assert!(scores[0] > scores[1]);
+        assert_eq!(scores[1], scores[2]);
+    }
+
+    #[test]
+    fn rare_in_the_corpus_counts_more() {
+        let search_matches = vec![search_match("a.pdf", 1, "bolt"), search_match("b.pdf", 1, "torque")];
+        // In the matches alone both are as rare.
+        let alone = scores(&search_matches, "bolt torque");
+        assert_eq!(alone[0], alone[1]);
+        let scores = Ranker::new(&SearchOptions::new("*.pdf", "bolt torque")).score(&search_matches, Some(&Manuals));
+        assert!(scores[0] > 2.0 * scores[1]);
+    }
+
+    #[test]
+    fn scored_in_batches() {
+        let search_matches = vec![
+            search_match("a.pdf", 1, "torque"),
+            search_match("b.pdf", 1, "torque torque"),
+            search_match("c.pdf", 1, "bolt"),
+        ];
+        let mut ranker = Ranker::new(&SearchOptions::new("*.pdf", "torque"));
+        let mut scores = ranker.score(&search_matches[..1], None);
+        scores.extend(ranker.score(&search_matches[1..], None));
+        let mut search_matches = search_matches;
+        sort_scored(&mut search_matches, &mut scores, SortOrder::Relevance);
+        assert_eq!(search_matches[0].path, PathBuf::from("b.pdf"));
+        assert_eq!(scores.len(), 3);
+        assert_eq!(scores[2], 0.0);
+    }
+
+    #[test]
+    fn sort_orders() {
+        let mut search_matches = vec![
+            search_match("b.pdf", 2, "torque"),
+            search_match("a.pdf", 3, "torque torque"),
+            search_match("b.pdf", 1, "nothing"),
+        ];
+        let options = SearchOptions::new("*.pdf", "torque");
+        let order = |search_matches: &[SearchMatch]| -> Vec<(String, usize)> {
+            search_matches.iter().map(|m| (m.path.display().to_string(), m.page)).collect()
+        };
+        sort_matches(&mut search_matches, SortOrder::Path, &options);
+        assert_eq!(order(&search_matches), vec![("a.pdf".to_string(), 3), ("b.pdf".to_string(), 1), ("b.pdf".to_string(), 2)]);
+        sort_matches(&mut search_matches, SortOrder::Page, &options);
+        assert_eq!(order(&search_matches), vec![("b.pdf".to_string(), 1), ("b.pdf".to_string(), 2), ("a.pdf".to_string(), 3)]);
+        sort_matches(&mut search_matches, SortOrder::Relevance, &options);
+        assert_eq!(order(&search_matches), vec![("a.pdf".to_string(), 3), ("b.pdf".to_string(), 2), ("b.pdf".to_string(), 1)]);
+    }
+
+    #[test]
+    fn regex_terms_come_from_submatches() {
+        let search_matches = vec![
+            SearchMatch::new(PathBuf::from("a.pdf"), 1, 1, "Torque and bolt".to_string(), String::new()).with_submatches(vec![0..6, 11..15]),
+            SearchMatch::new(PathBuf::from("a.pdf"), 1, 2, "torque torque".to_string(), String::new()).with_submatches(vec![0..6, 7..13]),
+        ];
+        let mut ranker = Ranker::new(&SearchOptions::new("*.pdf", "torque|bolt"));
+        ranker.score(&search_matches, None);
+        assert_eq!(ranker.terms, vec!["torque".to_string(), "bolt".to_string()]);
+        let ranker = Ranker::new(&SearchOptions::new("*.pdf", "Bolt torque"));
+        assert_eq!(ranker.terms, vec!["bolt".to_string(), "torque".to_string()]);
+    }
+
+    #[test]
+    fn parse_sort_order() {
+        assert_eq!("Relevance".parse(), Ok(SortOrder::Relevance));
+        assert_eq!(SortOrder::Page.next(), SortOrder::Relevance);
+        assert!("size".parse::<SortOrder>().is_err());
+    }
+}
@@ -0,0 +1,702 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::io::{self, BufRead, BufReader, Read};
+use std::process::{Child, Output, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use base64::Engine;
+use regex::Regex;
+use serde::Deserialize;
+use crate::info_cache::InfoCache;
+use crate::metadata::DocumentInfo;
+use crate::runner::ProcessRunner;
+use crate::pdf_text::{is_pdf, with_metadata};
+use crate::search_backend::{find_files, CancelHandle, SearchBackend, SearchedFile};
+use crate::search_error::SearchError;
+use crate::search_match::SearchMatch;
+use crate::search_options::{SearchOptions, Syntax};
+use crate::search_status::SearchStatus;
+
+// Why rga was killed before it finished.
+enum Stopped {
+    TimedOut,
+    Cancelled,
+}
+
+/// Searches by running ripgrep-all and reading its json output.
+pub struct RgaBackend {
+    runner: Box<dyn ProcessRunner>,
+    timeout: Option<Duration>,
+    // What every pdf says about itself, rga doesn't search that.
+    info_cache: Mutex<InfoCache>,
+}
+
+impl RgaBackend {
+    pub fn new(runner: Box<dyn ProcessRunner>) -> RgaBackend {
+        RgaBackend { runner, timeout: None, info_cache: Mutex::new(InfoCache::in_memory()) }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> RgaBackend {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_info_cache(mut self, info_cache: InfoCache) -> RgaBackend {
+        self.info_cache = Mutex::new(info_cache);
+        self
+    }
+
+    // Why the search has to stop now, if it has to.
+    fn stopped(&self, cancel: &CancelHandle, started: Instant) -> Option<Stopped> {
+        if cancel.is_cancelled() {
+            Some(Stopped::Cancelled)
+        } else if self.timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+            Some(Stopped::TimedOut)
+        } else {
+            None
+        }
+    }
+
+    // The info from the cache, or read on a thread of its own so a big pdf doesn't hold up a cancel or the timeout.
+    // Err when the search has to stop first, the pdf is then left to be read another time.
+    fn info_until_stopped(&self, path: &Path, cancel: &CancelHandle, started: Instant) -> Result<Option<DocumentInfo>, Stopped> {
+        if let Some(info) = self.info_cache.lock().unwrap_or_else(PoisonError::into_inner).get(path) {
+            return Ok(info);
+        }
+        let (sender, receiver) = mpsc::channel();
+        let pdf = path.to_path_buf();
+        thread::spawn(move || {
+            // Nobody waits any more when the search was stopped meanwhile.
+            let _ = sender.send(DocumentInfo::read(&pdf).ok());
+        });
+        loop {
+            if let Some(stopped) = self.stopped(cancel, started) {
+                return Err(stopped);
+            }
+            match receiver.recv_timeout(Duration::from_millis(10)) {
+                Ok(info) => {
+                    self.info_cache.lock().unwrap_or_else(PoisonError::into_inner).insert(path, info.clone());
+                    return Ok(info);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(None),
+            }
+        }
+    }
+
+    // Hands every line rga prints to on_line as soon as it is printed.
+    // Returns the output, without stdout, and why rga was killed if it had to be.
+    fn execute_rga(
+        &self,
+        options: &SearchOptions,
+        cancel: &CancelHandle,
+        started: Instant,
+        on_line: &mut dyn FnMut(&str) -> Result<(), SearchError>,
+    ) -> Result<(Output, Option<Stopped>), SearchError> {
+        let mut command = self.runner.command("rga", &rga_args(options)?);
+        // rga runs rg and the adapters as children of its own, they go in a group with it so they can be killed together.
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::NotFound => SearchError::BackendMissing("rga".to_string()),
+                _ => SearchError::Io(err),
+            })?;
+        // Both pipes are read on their own threads, so rga never blocks on a full pipe while we wait.
+        let lines = read_lines_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+        let status = loop {
+            // Waiting for the next line doubles as the poll interval.
+            match lines.recv_timeout(Duration::from_millis(10)) {
+                Ok(line) => {
+                    if let Err(err) = on_line(&line) {
+                        let _ = kill(&mut child);
+                        let _ = child.wait();
+                        return Err(err);
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => thread::sleep(Duration::from_millis(10)),
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            let stopped = self.stopped(cancel, started);
+            if stopped.is_some() {
+                kill(&mut child)?;
+                let status = child.wait()?;
+                // What rga printed after it was told to stop is not wanted, and a child that escaped
+                // the kill could keep the pipes open for as long as it runs. The reader threads end on their own.
+                return Ok((Output { status, stdout: vec![], stderr: vec![] }, stopped));
+            }
+        };
+        // The lines rga printed right before it exited.
+        for line in lines.iter() {
+            on_line(&line)?;
+        }
+        let output = Output {
+            status,
+            stdout: vec![],
+            stderr: stderr.join().unwrap_or_default(),
+        };
+        Ok((output, None))
+    }
+}
+
+// Kills rga with its whole process group, killing just rga leaves rg running with the pipes open.
+fn kill(child: &mut Child) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        // SAFETY: kill only sends a signal. The group has rga's pid, see process_group above.
+        if unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) } == 0 {
+            return Ok(());
+        }
+    }
+    child.kill()
+}
+
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        buffer
+    })
+}
+
+// A killed rga can stop in the middle of a line, only the complete ones are sent.
+fn read_lines_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        if let Some(pipe) = pipe {
+            let mut reader = BufReader::new(pipe);
+            let mut line = Vec::new();
+            while reader.read_until(b'\n', &mut line).is_ok_and(|read| read > 0) && line.ends_with(b"\n") {
+                if sender.send(String::from_utf8_lossy(&line).to_string()).is_err() {
+                    break;
+                }
+                line.clear();
+            }
+        }
+    });
+    receiver
+}
+
+impl SearchBackend for RgaBackend {
+    fn search_files(&self, options: &SearchOptions, cancel: &CancelHandle, on_file: &mut dyn FnMut(SearchedFile)) -> Result<SearchStatus, SearchError> {
+        let started = Instant::now();
+        let result = self.search_with_metadata(options, cancel, started, on_file);
+        // What was read is kept for the next run, even when the search did not finish.
+        let _ = self.info_cache.lock().unwrap_or_else(PoisonError::into_inner).save();
+        result
+    }
+
+    fn document_info(&self, path: &Path) -> Option<DocumentInfo> {
+        self.info_until_stopped(path, &CancelHandle::new(), Instant::now()).ok().flatten()
+    }
+}
+
+impl RgaBackend {
+    fn search_with_metadata(
+        &self,
+        options: &SearchOptions,
+        cancel: &CancelHandle,
+        started: Instant,
+        on_file: &mut dyn FnMut(SearchedFile),
+    ) -> Result<SearchStatus, SearchError> {
+        let mut parser = JsonParser::new(options.context);
+        let mut found = false;
+        // rga only sees the text, the titles and bookmarks of a pdf go in with its text matches.
+        // When the search has to stop while a pdf is read, its file goes without them.
+        let regex = options.regex().ok();
+        let metadata_matches = |path: &Path| match &regex {
+            Some(regex) if is_pdf(path) => self.info_until_stopped(path, cancel, started).ok().flatten().map(|info| info.search(path, regex)).unwrap_or_default(),
+            _ => vec![],
+        };
+        let mut reported = HashSet::new();
+        let (output, stopped) = self.execute_rga(options, cancel, started, &mut |line| {
+            if let Some(mut searched_file) = parser.parse_line(line)? {
+                searched_file.search_matches = with_metadata(metadata_matches(&searched_file.path), searched_file.search_matches);
+                found |= !searched_file.search_matches.is_empty();
+                reported.insert(searched_file.path.clone());
+                on_file(searched_file);
+            }
+            Ok(())
+        })?;
+        // rga can also be killed by someone else, then there is no exit code.
+        if stopped.is_some() || output.status.code().is_none() {
+            let unfinished_file = parser.unfinished_file();
+            let unfinished_files = unfinished_file.iter().map(|searched_file| searched_file.path.clone()).collect();
+            if let Some(searched_file) = unfinished_file {
+                on_file(searched_file);
+            }
+            return Ok(match stopped {
+                Some(Stopped::TimedOut) => SearchStatus::TimedOut(unfinished_files),
+                Some(Stopped::Cancelled) => SearchStatus::Cancelled(unfinished_files),
+                None => SearchStatus::Aborted(unfinished_files),
+            });
+        }
+        // The pdfs without any match in the text can still have one in their metadata.
+        if regex.is_some() {
+            for path in find_files(&options.glob, &options.roots())?.into_iter().filter(|path| is_pdf(path) && !reported.contains(path)) {
+                match self.stopped(cancel, started) {
+                    Some(Stopped::Cancelled) => return Ok(SearchStatus::Cancelled(vec![])),
+                    Some(Stopped::TimedOut) => return Ok(SearchStatus::TimedOut(vec![])),
+                    None => {}
+                }
+                let search_matches = metadata_matches(&path);
+                if !search_matches.is_empty() {
+                    found = true;
+                    on_file(SearchedFile { path, search_matches });
+                }
+            }
+        }
+        SearchStatus::from_output(&output, found).map_err(|err| match err {
+            SearchError::BackendFailed { stderr, .. } if stderr.contains("error parsing glob") => SearchError::InvalidGlob(stderr),
+            err => err,
+        })
+    }
+}
+
+fn rga_args(options: &SearchOptions) -> Result<Vec<String>, SearchError> {
+    let context = options.context.to_string();
+    // rga's smart case would see the operators of a query, so it gets told what the query needs.
+    let case_flag = match options.syntax {
+        Syntax::Query if options.ignores_case() => "--ignore-case",
+        Syntax::Query => "--case-sensitive",
+        _ => options.case.rga_flag(),
+    };
+    let fixed_arguments = ["--json", "--path-separator", "/", case_flag, "-C", &context];
+    let mut args: Vec<String> = fixed_arguments.iter().map(|s| s.to_string()).collect();
+    if options.syntax == Syntax::Fixed {
+        args.push("--fixed-strings".to_string());
+    }
+    args.push("--glob".to_string());
+    args.push(options.glob.clone());
+    // Everything after -- is a positional argument, so a search term starting with - is not read as a flag.
+    args.push("--".to_string());
+    args.push(match options.syntax {
+        Syntax::Query => options.pattern()?,
+        _ => options.search_term.clone(),
+    });
+    args.extend(options.paths.iter().map(|path| path.display().to_string()));
+    Ok(args)
+}
+
+// One line of `rga --json`, see the json printer docs in ripgrep. The data depends on the type.
+#[derive(Deserialize)]
+struct RgaMessage {
+    #[serde(rename = "type")]
+    kind: String,
+    data: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct RgaFile {
+    path: RgaData,
+}
+
+// Both match and context messages look like this, context just has no submatches.
+#[derive(Deserialize)]
+struct RgaLine {
+    lines: RgaData,
+    line_number: Option<usize>,
+    #[serde(default)]
+    submatches: Vec<RgaSubmatch>,
+}
+
+#[derive(Deserialize)]
+struct RgaSubmatch {
+    start: usize,
+    end: usize,
+}
+
+// Text that is not valid utf-8 is sent base64 encoded.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RgaData {
+    Text { text: String },
+    Bytes { bytes: String },
+}
+
+impl RgaData {
+    fn into_string(self) -> String {
+        match self {
+            RgaData::Text { text } => text,
+            RgaData::Bytes { bytes } => {
+                let decoded = base64::engine::general_purpose::STANDARD.decode(bytes).unwrap_or_default();
+                String::from_utf8_lossy(&decoded).to_string()
+            }
+        }
+    }
+}
+
+struct ParsedLine {
+    number: usize,
+    page: usize,
+    text: String,
+    submatches: Vec<Range<usize>>,
+    is_match: bool,
+}
+
+impl ParsedLine {
+    // rga's pdf adapter puts "Page N: " in front of every line, the page number is taken from there.
+    // Files without pages, like markdown, count as a single page.
+    fn new(line: RgaLine, is_match: bool, page_prefix: &Regex) -> ParsedLine {
+        // The offsets are in the bytes rga sent, after a lossy decode they can point anywhere. The highlighter finds the terms itself then.
+        let exact = matches!(line.lines, RgaData::Text { .. });
+        let raw = line.lines.into_string();
+        let (page, prefix_len) = match page_prefix.captures(&raw) {
+            Some(captures) => (captures[1].parse().unwrap_or(1), captures[0].len()),
+            None => (1, 0),
+        };
+        let rest = &raw[prefix_len..];
+        let text = rest.trim();
+        let offset = prefix_len + (rest.len() - rest.trim_start().len());
+        let submatches = line
+            .submatches
+            .iter()
+            .filter(|_| exact)
+            .map(|submatch| {
+                let start = submatch.start.saturating_sub(offset).min(text.len());
+                let end = submatch.end.saturating_sub(offset).min(text.len());
+                start..end
+            })
+            .filter(|range| !range.is_empty())
+            .collect();
+        ParsedLine {
+            number: line.line_number.unwrap_or(0),
+            page,
+            text: text.to_string(),
+            submatches,
+            is_match,
+        }
+    }
+}
+
+// Every match gets its own SearchMatch, with the lines around it in the same file as context.
+fn file_matches(path: &Path, lines: &[ParsedLine], context: usize) -> Vec<SearchMatch> {
+    lines
+        .iter()
+        .filter(|line| line.is_match)
+        .map(|matched| {
+            let context: Vec<&str> = lines
+                .iter()
+                .filter(|line| line.number.abs_diff(matched.number) <= context)
+                .map(|line| line.text.as_str())
+                .filter(|text| SearchMatch::string_contains_ascii_letters(text))
+                .collect();
+            SearchMatch::new(path.to_path_buf(), matched.page, matched.number, matched.text.clone(), context.join("\n"))
+                .with_submatches(matched.submatches.clone())
+        })
+        .collect()
+}
+
+/// Turns the begin, match, context and end messages into SearchMatches, one file at a time.
+struct JsonParser {
+    context: usize,
+    page_prefix: Regex,
+    current_file: Option<PathBuf>,
+    lines: Vec<ParsedLine>,
+}
+
+impl JsonParser {
+    fn new(context: usize) -> JsonParser {
+        JsonParser {
+            context,
+            page_prefix: Regex::new(r"^Page (\d+):").unwrap(),
+            current_file: None,
+            lines: Vec::new(),
+        }
+    }
+
+    // Returns the file once its end message has been read.
+    fn parse_line(&mut self, output_line: &str) -> Result<Option<SearchedFile>, SearchError> {
+        let output_line = output_line.trim_end();
+        if output_line.trim().is_empty() {
+            return Ok(None);
+        }
+        let malformed = |_| SearchError::MalformedOutput(output_line.to_string());
+        let message: RgaMessage = serde_json::from_str(output_line).map_err(malformed)?;
+        match message.kind.as_str() {
+            "begin" => {
+                let file: RgaFile = serde_json::from_value(message.data).map_err(malformed)?;
+                self.current_file = Some(PathBuf::from(file.path.into_string()));
+                self.lines.clear();
+            }
+            "match" | "context" => {
+                let line: RgaLine = serde_json::from_value(message.data).map_err(malformed)?;
+                self.lines.push(ParsedLine::new(line, message.kind == "match", &self.page_prefix));
+            }
+            "end" => {
+                let searched_file = self.unfinished_file();
+                self.lines.clear();
+                return Ok(searched_file);
+            }
+            _ => {}
+        }
+        Ok(None)
+    }
+
+    // What was found in a file before rga stopped is still worth showing.
+    fn unfinished_file(&mut self) -> Option<SearchedFile> {
+        let path = self.current_file.take()?;
+        let search_matches = file_matches(&path, &self.lines, self.context);
+        Some(SearchedFile { path, search_matches })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::NativeRunner;
+    use crate::search_options::CaseMode;
+
+    fn rga() -> RgaBackend {
+        RgaBackend::new(Box::new(NativeRunner))
+    }
+
+    const JSON_OUTPUT: &str = r#"{"type":"begin","data":{"path":{"text":"C:/docs/context.pdf"}}}
+{"type":"context","data":{"path":{"text":"C:/docs/context.pdf"},"lines":{"text":"Page 1: c\n"},"line_number":1,"absolute_offset":0,"submatches":[]}}
+{"type":"match","data":{"path":{"text":"C:/docs/context.pdf"},"lines":{"text":"Page 12: Test\n"},"line_number":2,"absolute_offset":10,"submatches":[{"match":{"text":"Test"},"start":9,"end":13}]}}
+{"type":"context","data":{"path":{"text":"C:/docs/context.pdf"},"lines":{"text":"Page 12:\n"},"line_number":3,"absolute_offset":24,"submatches":[]}}
+{"type":"end","data":{"path":{"text":"C:/docs/context.pdf"},"binary_offset":null,"stats":{}}}
+{"type":"begin","data":{"path":{"text":"notes.md"}}}
+{"type":"match","data":{"path":{"text":"notes.md"},"lines":{"bytes":"dGVzdCBh/w=="},"line_number":7,"absolute_offset":0,"submatches":[{"match":{"text":"test"},"start":0,"end":4}]}}
+{"type":"end","data":{"path":{"text":"notes.md"},"binary_offset":null,"stats":{}}}
+{"data":{"elapsed_total":{"human":"0.01s","nanos":1,"secs":0},"stats":{}},"type":"summary"}"#;
+
+    // The files in the order their end message came.
+    fn parse(output: &str) -> Result<Vec<SearchedFile>, SearchError> {
+        let mut parser = JsonParser::new(8);
+        let mut searched_files = Vec::new();
+        for output_line in output.lines() {
+            searched_files.extend(parser.parse_line(output_line)?);
+        }
+        Ok(searched_files)
+    }
+
+    #[test]
+    fn parse_json() {
+        let searched_files = parse(JSON_OUTPUT).unwrap();
+        assert_eq!(searched_files.len(), 2);
+        let search_matches: Vec<SearchMatch> = searched_files.into_iter().flat_map(|searched_file| searched_file.search_matches).collect();
+        assert_eq!(search_matches.len(), 2);
+        let expected_sm = SearchMatch::new(
+            PathBuf::from("C:/docs/context.pdf"),
+            12,
+            2,
+            "Test".to_string(),
+            "c\nTest".to_string(),
+        );
+        assert_eq!(search_matches[0].submatches, vec![0..4]);
+        assert_eq!(search_matches[0].clone().with_submatches(vec![]), expected_sm);
+        assert_eq!(search_matches[1].path, PathBuf::from("notes.md"));
+        assert_eq!((search_matches[1].page, search_matches[1].line), (1, 7));
+        assert_eq!(search_matches[1].content, "test a\u{FFFD}");
+        assert!(search_matches[1].submatches.is_empty());
+    }
+
+    #[test]
+    fn parse_unfinished_file() {
+        let mut parser = JsonParser::new(8);
+        for output_line in JSON_OUTPUT.lines().take(3) {
+            assert!(parser.parse_line(output_line).unwrap().is_none());
+        }
+        let searched_file = parser.unfinished_file().unwrap();
+        assert_eq!(searched_file.path, PathBuf::from("C:/docs/context.pdf"));
+        assert_eq!(searched_file.search_matches.len(), 1);
+        assert!(parser.unfinished_file().is_none());
+    }
+
+    #[test]
+    fn parse_garbage() {
+        assert!(matches!(parse("not json"), Err(SearchError::MalformedOutput(line)) if line == "not json"));
+        assert!(matches!(parse("{\"type\":\"match\",\"data\":{}}"), Err(SearchError::MalformedOutput(_))));
+    }
+
+    #[test]
+    fn missing_rga() {
+        struct MissingRunner;
+        impl ProcessRunner for MissingRunner {
+            fn command(&self, _program: &str, args: &[String]) -> std::process::Command {
+                NativeRunner.command("pdf_search_no_such_program", args)
+            }
+        }
+        let result = RgaBackend::new(Box::new(MissingRunner)).search(&SearchOptions::new("*.pdf", "test"));
+        assert!(matches!(result, Err(SearchError::BackendMissing(program)) if program == "rga"));
+    }
+
+    // Pretends to be rga by printing the start of JSON_OUTPUT and then hanging in the given command.
+    #[cfg(unix)]
+    struct HangingRunner(&'static str);
+
+    #[cfg(unix)]
+    impl ProcessRunner for HangingRunner {
+        fn command(&self, _program: &str, _args: &[String]) -> std::process::Command {
+            let printed: String = JSON_OUTPUT.lines().take(3).map(|line| format!("{}\n", line)).collect();
+            let script = format!("printf '%s' '{}'; printf '{{\"type\":'; {}", printed, self.0);
+            NativeRunner.command("sh", &["-c".to_string(), script])
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timed_out_search_keeps_partial_results() {
+        let backend = RgaBackend::new(Box::new(HangingRunner("exec sleep 5"))).with_timeout(Duration::from_millis(200));
+        let (status, search_matches) = backend.search(&SearchOptions::new("*.pdf", "test")).unwrap();
+        assert_eq!(status, SearchStatus::TimedOut(vec![PathBuf::from("C:/docs/context.pdf")]));
+        assert_eq!(search_matches.len(), 1);
+        assert_eq!(search_matches[0].content, "Test");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cancelled_search_keeps_partial_results() {
+        let cancel = CancelHandle::new();
+        let canceller = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            canceller.cancel();
+        });
+        let mut search_matches = Vec::new();
+        let status = RgaBackend::new(Box::new(HangingRunner("exec sleep 5")))
+            .search_files(&SearchOptions::new("*.pdf", "test"), &cancel, &mut |searched_file| search_matches.extend(searched_file.search_matches))
+            .unwrap();
+        assert_eq!(status, SearchStatus::Cancelled(vec![PathBuf::from("C:/docs/context.pdf")]));
+        assert_eq!(search_matches.len(), 1);
+    }
+
+    // Like rga running rg, the sleep is a child of the shell and holds stdout as well.
+    #[cfg(unix)]
+    #[test]
+    fn cancel_kills_the_children_too() {
+        let cancel = CancelHandle::new();
+        let canceller = cancel.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            canceller.cancel();
+        });
+        let started = Instant::now();
+        let status = RgaBackend::new(Box::new(HangingRunner("sleep 5; true")))
+            .search_files(&SearchOptions::new("*.pdf", "test"), &cancel, &mut |_| {})
+            .unwrap();
+        assert!(matches!(status, SearchStatus::Cancelled(_)));
+        assert!(started.elapsed() < Duration::from_secs(3), "{:?}", started.elapsed());
+    }
+
+    // Prints a match in a.pdf, like rga would when only a.pdf has the term in its text.
+    #[cfg(unix)]
+    struct PrintingRunner(PathBuf);
+
+    #[cfg(unix)]
+    impl ProcessRunner for PrintingRunner {
+        fn command(&self, _program: &str, _args: &[String]) -> std::process::Command {
+            let path = self.0.join("a.pdf").display().to_string();
+            let json = [
+                format!(r#"{{"type":"begin","data":{{"path":{{"text":"{}"}}}}}}"#, path),
+                format!(r#"{{"type":"match","data":{{"path":{{"text":"{}"}},"lines":{{"text":"Page 1: torque\n"}},"line_number":1,"submatches":[]}}}}"#, path),
+                format!(r#"{{"type":"end","data":{{"path":{{"text":"{}"}}}}}}"#, path),
+            ];
+            NativeRunner.command("printf", &["%s\n".to_string(), json.join("\n")])
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn metadata_goes_with_the_text() {
+        use lopdf::{dictionary, Document, Object};
+        let folder = std::env::temp_dir().join(format!("pdf_search_rga_metadata_{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        for name in ["a.pdf", "b.pdf"] {
+            let mut document = Document::with_version("1.5");
+            let info = document.add_object(dictionary! { "Title" => Object::string_literal("Torque table") });
+            document.trailer.set("Info", info);
+            document.save(folder.join(name)).unwrap();
+        }
+        let backend = RgaBackend::new(Box::new(PrintingRunner(folder.clone())));
+        let mut options = SearchOptions::new("*.pdf", "torque");
+        options.paths = vec![folder.clone()];
+        for _ in 0..2 {
+            let mut searched_files = Vec::new();
+            let status = backend.search_files(&options, &CancelHandle::new(), &mut |searched_file| searched_files.push(searched_file)).unwrap();
+            assert_eq!(status, SearchStatus::Found);
+            let contents: Vec<(PathBuf, Vec<&str>)> = searched_files
+                .iter()
+                .map(|searched_file| (searched_file.path.clone(), searched_file.search_matches.iter().map(|search_match| search_match.content.as_str()).collect()))
+                .collect();
+            assert_eq!(contents, vec![(folder.join("a.pdf"), vec!["Title: Torque table", "torque"]), (folder.join("b.pdf"), vec!["Title: Torque table"])]);
+        }
+        assert_eq!(backend.info_cache.lock().unwrap().len(), 2);
+        // Reading the metadata is stopped by the timeout like rga is.
+        let backend = RgaBackend::new(Box::new(PrintingRunner(folder.clone()))).with_timeout(Duration::ZERO);
+        let status = backend.search_files(&options, &CancelHandle::new(), &mut |_| {}).unwrap();
+        assert!(matches!(status, SearchStatus::TimedOut(_)), "{:?}", status);
+    }
+
+    #[test]
+    fn rga_args_keep_glob_and_term_unquoted() {
+        let args = rga_args(&SearchOptions::new("my docs/*.pdf", "-it's")).unwrap();
+        let glob_index = args.iter().position(|arg| arg == "--glob").unwrap();
+        assert_eq!(args[glob_index + 1], "my docs/*.pdf");
+        assert_eq!(args[args.len() - 2..], ["--".to_string(), "-it's".to_string()]);
+    }
+
+    #[test]
+    fn rga_args_from_options() {
+        let mut options = SearchOptions::new("*.pdf", "Bolt");
+        options.paths = vec![PathBuf::from("specs"), PathBuf::from("manuals")];
+        options.context = 2;
+        options.case = CaseMode::Smart;
+        options.syntax = Syntax::Fixed;
+        let args = rga_args(&options).unwrap();
+        assert!(args.contains(&"--smart-case".to_string()));
+        assert!(args.contains(&"--fixed-strings".to_string()));
+        assert!(args.windows(2).any(|pair| pair == ["-C", "2"]));
+        assert_eq!(args[args.len() - 3..], ["Bolt".to_string(), "specs".to_string(), "manuals".to_string()]);
+    }
+
+    #[test]
+    #[ignore = "requires rga on PATH"]
+    fn test_execute_rga_md() {
+        let search_matches = rga().search(&SearchOptions::new("test_assets/test.md", "beautiful")).unwrap().1;
+        assert_eq!(search_matches.len(), 1);
+        assert_eq!(search_matches[0].path, PathBuf::from("test_assets/test.md"));
+        assert_eq!(search_matches[0].line, 5);
+        assert_eq!(search_matches[0].content, "This is beautiful text.");
+    }
+
+    #[test]
+    #[ignore = "requires rga on PATH"]
+    fn test_execute_rga_pdf() {
+        let search_matches = rga().search(&SearchOptions::new("test_assets/test.pdf", "subheading")).unwrap().1;
+        assert_eq!(search_matches.len(), 1);
+        assert_eq!((search_matches[0].page, search_matches[0].line), (1, 2));
+        assert_eq!(search_matches[0].content, "This is a subheading - Test");
+        assert_eq!(search_matches[0].submatches, vec![10..20]);
+    }
+
+    #[test]
+    #[ignore = "requires rga on PATH"]
+    fn no_such_file() {
+        let (status, search_matches) = rga().search(&SearchOptions::new("assets/file_does_not_exist.pdf", "subheading")).unwrap();
+        assert_eq!(status, SearchStatus::NoFilesFound);
+        assert!(search_matches.is_empty());
+    }
+
+    #[test]
+    #[ignore = "requires rga on PATH"]
+    fn no_such_match() {
+        let (status, search_matches) = rga().search(&SearchOptions::new("test_assets/*.pdf", "phrase that doesnt exist in test files")).unwrap();
+        assert_eq!(status, SearchStatus::NoMatchesFound);
+        assert!(search_matches.is_empty());
+    }
+}
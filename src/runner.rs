@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+use std::{env, io};
+use std::process::{Command, Output};
+
+#[cfg(feature = "powershell")]
+use crate::powershell;
+
+/// Knows how to turn a program and its arguments into a `Command` that can be run.
+// Send and Sync so a backend holding a runner can search on another thread.
+pub trait ProcessRunner: Send + Sync {
+    fn command(&self, program: &str, args: &[String]) -> Command;
+
+    fn run(&self, program: &str, args: &[String]) -> io::Result<Output> {
+        self.command(program, args).output()
+    }
+}
+
+/// Starts the program directly. Every argument is handed over as is, so globs and search terms never need quoting.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NativeRunner;
+
+impl ProcessRunner for NativeRunner {
+    fn command(&self, program: &str, args: &[String]) -> Command {
+        let mut command = Command::new(program);
+        command.args(args);
+        command
+    }
+}
+
+/// Runs the program through `powershell.exe -Command`, like the old script did.
+#[cfg(feature = "powershell")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PowerShellRunner;
+
+#[cfg(feature = "powershell")]
+impl ProcessRunner for PowerShellRunner {
+    fn command(&self, program: &str, args: &[String]) -> Command {
+        let mut cmd_string = program.to_string();
+        for arg in args {
+            cmd_string.push(' ');
+            cmd_string.push_str(&powershell::quote(arg));
+        }
+        powershell::powershell_command(&cmd_string)
+    }
+}
+
+pub fn default_runner() -> Box<dyn ProcessRunner> {
+    #[cfg(all(windows, feature = "powershell"))]
+    {
+        Box::new(PowerShellRunner)
+    }
+    #[cfg(not(all(windows, feature = "powershell")))]
+    {
+        Box::new(NativeRunner)
+    }
+}
+
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    if path.components().count() > 1 {
+        return path.is_file().then(|| path.to_path_buf());
+    }
+    let mut candidates = vec![program.to_string()];
+    if cfg!(windows) {
+        candidates.push(format!("{}.exe", program));
+    }
+    let mut dirs: Vec<PathBuf> = env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).collect())
+        .unwrap_or_default();
+    // SumatraPDF installs per user and does not add itself to PATH.
+    if let Some(local_app_data) = env::var_os("LOCALAPPDATA") {
+        dirs.push(PathBuf::from(local_app_data).join("SumatraPDF"));
+    }
+    dirs.iter()
+        .flat_map(|dir| candidates.iter().map(move |candidate| dir.join(candidate)))
+        .find(|candidate| candidate.is_file())
+}
+
+pub fn get_stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn native_runner_does_not_interpret_arguments() {
+        let args = vec!["'*.pdf'".to_string(), "$HOME; echo no".to_string()];
+        let output = NativeRunner.run("echo", &args).expect("Could not run command.");
+        assert_eq!("'*.pdf' $HOME; echo no", get_stdout(&output));
+    }
+}
@@ -0,0 +1,189 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use globset::{Glob, GlobMatcher};
+use walkdir::{DirEntry, WalkDir};
+use crate::index_backend::IndexBackend;
+use crate::info_cache::{user_cache_path, InfoCache};
+use crate::metadata::DocumentInfo;
+use crate::pdf_text::{extract_pages, PdfTextBackend};
+use crate::ranking::Corpus;
+use crate::rga_backend::RgaBackend;
+use crate::runner::{default_runner, find_program};
+use crate::search_error::SearchError;
+use crate::search_match::SearchMatch;
+use crate::search_options::SearchOptions;
+use crate::search_status::SearchStatus;
+
+/// The matches in one file, handed over as soon as that file has been searched.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SearchedFile {
+    pub path: PathBuf,
+    pub search_matches: Vec<SearchMatch>,
+}
+
+/// Tells a running search to stop. Clones share the flag, so one can be kept while the other is searching.
+#[derive(Debug, Default, Clone)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn new() -> CancelHandle {
+        CancelHandle::default()
+    }
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Something that can find the lines matching a search term in the files matching a glob.
+pub trait SearchBackend: Send + Sync {
+    /// Calls `on_file` for every file as soon as it has been searched, and returns the status when all are done.
+    /// rga only tells us about files with matches, so with it the files without any are never reported.
+    /// When `cancel` is cancelled the backend stops as soon as it can, keeps what it found and returns `SearchStatus::Cancelled`.
+    fn search_files(&self, options: &SearchOptions, cancel: &CancelHandle, on_file: &mut dyn FnMut(SearchedFile)) -> Result<SearchStatus, SearchError>;
+
+    fn search(&self, options: &SearchOptions) -> Result<(SearchStatus, Vec<SearchMatch>), SearchError> {
+        let mut search_matches = Vec::new();
+        let status = self.search_files(options, &CancelHandle::new(), &mut |searched_file| search_matches.extend(searched_file.search_matches))?;
+        Ok((status, search_matches))
+    }
+
+    /// The info dictionary of a file this backend found matches in, None when it can not be read.
+    fn document_info(&self, path: &Path) -> Option<DocumentInfo> {
+        DocumentInfo::read(path).ok()
+    }
+
+    /// The text of every page of a file, for previewing whole pages.
+    fn pages(&self, path: &Path) -> Result<Vec<String>, SearchError> {
+        Ok(extract_pages(path)?)
+    }
+
+    /// What ranking can know about all the text, not only the matches. None when the backend only sees the matches.
+    fn corpus(&self) -> Option<&dyn Corpus> {
+        None
+    }
+}
+
+/// The index when every folder searched has one, otherwise the pdfs are extracted.
+/// An index that can not be read is skipped as well, the search still works, just slower.
+/// The index only has pdfs, a glob that can find other files is left to rga.
+pub fn default_backend(options: &SearchOptions) -> Box<dyn SearchBackend> {
+    if !only_pdfs(&options.glob) {
+        return extraction_backend(options);
+    }
+    match IndexBackend::open(&options.roots()) {
+        Ok(Some(index_backend)) => Box::new(index_backend),
+        _ => extraction_backend(options),
+    }
+}
+
+// Whether every file the glob can match is a pdf, like *.pdf or manuals/**/*.PDF.
+fn only_pdfs(glob: &str) -> bool {
+    glob.rsplit('/').next().is_some_and(|name| name.to_lowercase().ends_with(".pdf"))
+}
+
+/// rga if it is installed, otherwise the built in pdf text extraction.
+pub fn extraction_backend(options: &SearchOptions) -> Box<dyn SearchBackend> {
+    if find_program("rga").is_some() {
+        let info_cache = user_cache_path().map_or_else(InfoCache::in_memory, InfoCache::load);
+        let backend = RgaBackend::new(default_runner()).with_info_cache(info_cache);
+        match options.timeout {
+            Some(timeout) => Box::new(backend.with_timeout(timeout)),
+            None => Box::new(backend),
+        }
+    } else {
+        Box::new(PdfTextBackend::new())
+    }
+}
+
+/// A glob as rga applies it. A glob without a `/` is matched against the file name, otherwise against the path relative to its root.
+#[derive(Debug)]
+pub struct FileGlob {
+    matcher: GlobMatcher,
+    match_file_name: bool,
+}
+
+impl FileGlob {
+    pub fn new(glob: &str) -> Result<FileGlob, SearchError> {
+        Ok(FileGlob {
+            matcher: Glob::new(glob)?.compile_matcher(),
+            match_file_name: !glob.contains('/'),
+        })
+    }
+
+    pub fn is_match(&self, relative_to_root: &Path) -> bool {
+        if self.match_file_name {
+            relative_to_root.file_name().is_some_and(|name| self.matcher.is_match(name))
+        } else {
+            self.matcher.is_match(relative_to_root)
+        }
+    }
+}
+
+/// Walks the roots like rga does and returns the files matching the glob.
+pub fn find_files(glob: &str, roots: &[PathBuf]) -> Result<Vec<PathBuf>, SearchError> {
+    let file_glob = FileGlob::new(glob)?;
+    let mut files = Vec::new();
+    for root in roots {
+        let root_files = WalkDir::new(root)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|entry| entry.depth() == 0 || !is_hidden(entry))
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .filter(|entry| file_glob.is_match(entry.path().strip_prefix(root).unwrap_or(entry.path())))
+            .map(|entry| relative_path(entry.path()));
+        files.extend(root_files);
+    }
+    Ok(files)
+}
+
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry.file_name().to_str().is_some_and(|name| name.starts_with('.'))
+}
+
+// rga prints paths without the leading ./
+pub fn relative_path(path: &Path) -> PathBuf {
+    path.strip_prefix(".").unwrap_or(path).to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_files_by_path() {
+        let files = find_files("test_assets/test.*", &[PathBuf::from(".")]).unwrap();
+        assert_eq!(files, vec![PathBuf::from("test_assets/test.md"), PathBuf::from("test_assets/test.pdf")]);
+    }
+
+    #[test]
+    fn find_files_by_name() {
+        let files = find_files("*.pdf", &[PathBuf::from(".")]).unwrap();
+        assert!(files.contains(&PathBuf::from("test_assets/test.pdf")));
+        let files = find_files("*.pdf", &[PathBuf::from("test_assets")]).unwrap();
+        assert_eq!(files, vec![PathBuf::from("test_assets/test.pdf")]);
+    }
+
+    #[test]
+    fn index_only_for_pdfs() {
+        assert!(only_pdfs("*.pdf"));
+        assert!(only_pdfs("manuals/**/*.PDF"));
+        assert!(!only_pdfs("*.md"));
+        assert!(!only_pdfs("*"));
+        assert!(!only_pdfs("pdfs/*"));
+    }
+
+    #[test]
+    fn find_files_invalid_glob() {
+        assert!(matches!(find_files("test_assets/[.pdf", &[PathBuf::from(".")]), Err(SearchError::InvalidGlob(_))));
+    }
+
+    #[test]
+    fn find_no_files() {
+        assert!(find_files("assets/file_does_not_exist.pdf", &[PathBuf::from(".")]).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+
+/// Everything that can stop a search from giving an answer.
+#[derive(Debug)]
+pub enum SearchError {
+    BackendMissing(String),
+    BackendFailed { code: Option<i32>, stderr: String },
+    MalformedOutput(String),
+    InvalidGlob(String),
+    InvalidSearchTerm(String),
+    // The index on disk could not be read or written.
+    CorruptIndex(String),
+    // A pdf could not be read for its text.
+    UnreadablePdf(String),
+    Io(io::Error),
+}
+
+impl Display for SearchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchError::BackendMissing(program) => {
+                write!(f, "Could not find {}, make sure it is installed and on your PATH.", program)
+            }
+            SearchError::BackendFailed { code: Some(code), stderr } => {
+                write!(f, "The search failed with exit code {}: {}", code, stderr.trim())
+            }
+            SearchError::BackendFailed { code: None, stderr } => {
+                write!(f, "The search was stopped before it finished: {}", stderr.trim())
+            }
+            SearchError::MalformedOutput(line) => {
+                write!(f, "Could not understand this line of search output: {}", line)
+            }
+            SearchError::InvalidGlob(message) => {
+                write!(f, "Invalid glob pattern: {}", message.trim())
+            }
+            SearchError::InvalidSearchTerm(message) => {
+                write!(f, "Invalid search term: {}", message.trim())
+            }
+            SearchError::CorruptIndex(message) => {
+                write!(f, "The index is not usable: {}", message.trim())
+            }
+            SearchError::UnreadablePdf(message) => {
+                write!(f, "Could not read the pdf: {}", message.trim())
+            }
+            SearchError::Io(err) => {
+                write!(f, "{}", err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+impl From<io::Error> for SearchError {
+    fn from(err: io::Error) -> Self {
+        SearchError::Io(err)
+    }
+}
+
+impl From<globset::Error> for SearchError {
+    fn from(err: globset::Error) -> Self {
+        SearchError::InvalidGlob(err.to_string())
+    }
+}
+
+impl From<regex::Error> for SearchError {
+    fn from(err: regex::Error) -> Self {
+        SearchError::InvalidSearchTerm(err.to_string())
+    }
+}
+
+impl From<lopdf::Error> for SearchError {
+    fn from(err: lopdf::Error) -> Self {
+        SearchError::UnreadablePdf(err.to_string())
+    }
+}
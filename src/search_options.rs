@@ -0,0 +1,185 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use regex::{Regex, RegexBuilder};
+use crate::query::Query;
+use crate::search_error::SearchError;
+
+pub const DEFAULT_GLOB: &str = "*.pdf";
+pub const DEFAULT_CONTEXT: usize = 8;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CaseMode {
+    Insensitive,
+    Sensitive,
+    // Insensitive unless the search term has an uppercase letter, like ripgrep's --smart-case.
+    Smart,
+}
+
+impl FromStr for CaseMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "insensitive" | "ignore" => Ok(CaseMode::Insensitive),
+            "sensitive" => Ok(CaseMode::Sensitive),
+            "smart" => Ok(CaseMode::Smart),
+            _ => Err(format!("unknown case mode '{}', expected insensitive, sensitive or smart", s)),
+        }
+    }
+}
+
+impl CaseMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CaseMode::Insensitive => "insensitive",
+            CaseMode::Sensitive => "sensitive",
+            CaseMode::Smart => "smart",
+        }
+    }
+
+    pub fn rga_flag(&self) -> &'static str {
+        match self {
+            CaseMode::Insensitive => "--ignore-case",
+            CaseMode::Sensitive => "--case-sensitive",
+            CaseMode::Smart => "--smart-case",
+        }
+    }
+
+    pub fn ignores_case(&self, search_term: &str) -> bool {
+        match self {
+            CaseMode::Insensitive => true,
+            CaseMode::Sensitive => false,
+            CaseMode::Smart => !search_term.chars().any(|c| c.is_uppercase()),
+        }
+    }
+}
+
+/// How the search term is read.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Syntax {
+    Regex,
+    // Plain text, like rga's --fixed-strings.
+    Fixed,
+    // Words and phrases with AND, OR, NOT and NEAR, matched per page. See query.
+    Query,
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "regex" => Ok(Syntax::Regex),
+            "fixed" => Ok(Syntax::Fixed),
+            "query" => Ok(Syntax::Query),
+            _ => Err(format!("unknown syntax '{}', expected regex, fixed or query", s)),
+        }
+    }
+}
+
+impl Syntax {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Syntax::Regex => "regex",
+            Syntax::Fixed => "fixed",
+            Syntax::Query => "query",
+        }
+    }
+}
+
+/// Everything that decides what a search looks for and where.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SearchOptions {
+    pub glob: String,
+    // Folders to search in, the current folder when empty.
+    pub paths: Vec<PathBuf>,
+    pub search_term: String,
+    pub context: usize,
+    pub case: CaseMode,
+    pub syntax: Syntax,
+    pub max_results: Option<usize>,
+    // rga is stopped when it takes longer, the built in extraction has no timeout.
+    pub timeout: Option<Duration>,
+}
+
+impl SearchOptions {
+    pub fn new(glob: &str, search_term: &str) -> SearchOptions {
+        SearchOptions {
+            glob: glob.to_string(),
+            paths: vec![],
+            search_term: search_term.to_string(),
+            context: DEFAULT_CONTEXT,
+            case: CaseMode::Insensitive,
+            syntax: Syntax::Regex,
+            max_results: None,
+            timeout: None,
+        }
+    }
+
+    pub fn roots(&self) -> Vec<PathBuf> {
+        if self.paths.is_empty() {
+            vec![PathBuf::from(".")]
+        } else {
+            self.paths.clone()
+        }
+    }
+
+    /// The regex rga is given. A query becomes a regex for the lines that have any of its words.
+    pub fn pattern(&self) -> Result<String, SearchError> {
+        Ok(match self.syntax {
+            Syntax::Regex => self.search_term.clone(),
+            Syntax::Fixed => regex::escape(&self.search_term),
+            Syntax::Query => Query::parse(&self.search_term, self.case)?.pattern(),
+        })
+    }
+
+    /// Smart case looks at the words of a query, not at its operators.
+    pub fn ignores_case(&self) -> bool {
+        match self.syntax {
+            Syntax::Query => self.case.ignores_case(&Query::words(&self.search_term)),
+            _ => self.case.ignores_case(&self.search_term),
+        }
+    }
+
+    /// The search term as a regex that matches the same lines rga would.
+    pub fn regex(&self) -> Result<Regex, SearchError> {
+        let regex = RegexBuilder::new(&self.pattern()?)
+            .case_insensitive(self.ignores_case())
+            .build()?;
+        Ok(regex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn smart_case() {
+        assert!(CaseMode::Smart.ignores_case("torque"));
+        assert!(!CaseMode::Smart.ignores_case("Torque"));
+    }
+
+    #[test]
+    fn fixed_strings_regex() {
+        let mut options = SearchOptions::new(DEFAULT_GLOB, "a.b(");
+        assert!(options.regex().is_err());
+        options.syntax = Syntax::Fixed;
+        let regex = options.regex().unwrap();
+        assert!(regex.is_match("x A.B( y"));
+        assert!(!regex.is_match("axb("));
+    }
+
+    #[test]
+    fn query_regex_finds_every_word() {
+        let mut options = SearchOptions::new(DEFAULT_GLOB, "torque AND NOT \"m8 bolt\"");
+        options.syntax = Syntax::Query;
+        options.case = CaseMode::Smart;
+        assert!(options.ignores_case());
+        let regex = options.regex().unwrap();
+        assert!(regex.is_match("Torque"));
+        assert!(regex.is_match("an M8  bolt"));
+        assert!(!regex.is_match("and not"));
+    }
+}
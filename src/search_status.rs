@@ -1,11 +1,42 @@
+use std::path::{Path, PathBuf};
 use std::process::Output;
+use crate::search_error::SearchError;
+
+/// A file that could not be searched, and what the backend said about it.
+/// Not every message names a file, then `path` is None.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FileWarning {
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
+
+impl FileWarning {
+    pub fn new(path: Option<PathBuf>, message: &str) -> FileWarning {
+        FileWarning {
+            path,
+            message: message.trim().to_string(),
+        }
+    }
+}
 
 #[non_exhaustive]
 #[derive(Debug, PartialEq, Clone)]
 pub enum SearchStatus {
     Found,
+    // Matches were found, but some files could not be read.
+    PartiallyFound(Vec<FileWarning>),
     NoFilesFound,
     NoMatchesFound,
+    // Nothing matched in the files that could be read, but some could not be.
+    NoMatchesInReadableFiles(Vec<FileWarning>),
+    BackendNotFound(String),
+    // The backend was stopped before it finished. Holds the files that were being searched at that moment.
+    Aborted(Vec<PathBuf>),
+    TimedOut(Vec<PathBuf>),
+    // Stopped by the user, for example by editing the search while it ran.
+    Cancelled(Vec<PathBuf>),
+    // The search could not run, holds what went wrong.
+    Failed(String),
     NotSearched,
 }
 
@@ -15,17 +46,72 @@ impl SearchStatus {
             SearchStatus::Found => {
                 "Found matches:".to_string()
             }
+            SearchStatus::PartiallyFound(warnings) => {
+                format!("Found matches, but some files could not be read:{}", warning_lines(warnings))
+            }
             SearchStatus::NoFilesFound => {
                 "No files found, make sure your glob pattern is correct.".to_string()
             }
             SearchStatus::NoMatchesFound => {
                 "No matches for your search term has been found".to_string()
             }
+            SearchStatus::NoMatchesInReadableFiles(warnings) => {
+                format!("No matches found, and some files could not be read:{}", warning_lines(warnings))
+            }
+            SearchStatus::BackendNotFound(program) => {
+                format!("Could not find {}, make sure it is installed and on your PATH.", program)
+            }
+            SearchStatus::Aborted(files) => {
+                format!("The search was aborted, results are incomplete.{}", file_lines(files))
+            }
+            SearchStatus::TimedOut(files) => {
+                format!("The search timed out, results are incomplete.{}", file_lines(files))
+            }
+            SearchStatus::Cancelled(files) => {
+                format!("The search was cancelled, results are incomplete.{}", file_lines(files))
+            }
+            SearchStatus::Failed(message) => {
+                message.clone()
+            }
             SearchStatus::NotSearched => {
                 "The search has not been run".to_string()
             }
         }
     }
+
+    /// Found or NoMatchesFound, or their partial versions when there are warnings.
+    pub fn from_results(found: bool, warnings: Vec<FileWarning>) -> SearchStatus {
+        match (found, warnings.is_empty()) {
+            (true, true) => SearchStatus::Found,
+            (true, false) => SearchStatus::PartiallyFound(warnings),
+            (false, true) => SearchStatus::NoMatchesFound,
+            (false, false) => SearchStatus::NoMatchesInReadableFiles(warnings),
+        }
+    }
+
+    pub fn warnings(&self) -> &[FileWarning] {
+        match self {
+            SearchStatus::PartiallyFound(warnings) | SearchStatus::NoMatchesInReadableFiles(warnings) => warnings,
+            _ => &[],
+        }
+    }
+
+    // rga exits with 0 when something matched, 1 when nothing did and 2 when there was an error.
+    // When no files were searched it still exits with 1, but complains on stderr.
+    // An error in a single file also gives 2, that is only a warning as long as every message is about a file.
+    pub fn from_output(output: &Output, found: bool) -> Result<SearchStatus, SearchError> {
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        let warnings = parse_warnings(&stderr);
+        match output.status.code() {
+            Some(0) => Ok(SearchStatus::from_results(true, warnings)),
+            Some(1) if stderr.contains("No files were searched") => Ok(SearchStatus::NoFilesFound),
+            Some(1) => Ok(SearchStatus::from_results(false, warnings)),
+            Some(2) if !warnings.is_empty() && warnings.iter().all(|warning| warning.path.is_some()) => {
+                Ok(SearchStatus::from_results(found, warnings))
+            }
+            code => Err(SearchError::BackendFailed { code, stderr }),
+        }
+    }
 }
 
 
@@ -35,24 +121,56 @@ impl SearchStatus {
     }
 }
 
-impl From<&Output> for SearchStatus {
-    fn from(output: &Output) -> Self {
-        if output.status.success() {
-            if output.stderr.is_empty() {
-                if output.stdout.is_empty() {
-                    SearchStatus::NoMatchesFound
-                } else {
-                    SearchStatus::Found
-                }
-            } else {
-                SearchStatus::NoFilesFound
-            }
-        } else {
-            panic!("Error in conversion to SearchStatus.");
+impl From<&SearchError> for SearchStatus {
+    fn from(err: &SearchError) -> Self {
+        match err {
+            SearchError::BackendMissing(program) => SearchStatus::BackendNotFound(program.clone()),
+            SearchError::BackendFailed { code: None, .. } => SearchStatus::Aborted(vec![]),
+            err => SearchStatus::Failed(err.to_string()),
         }
     }
 }
 
+/// Every line rga printed on stderr, with the file it is about when there is one.
+/// The lines look like `rga: path/to/file.pdf: what went wrong`.
+pub fn parse_warnings(stderr: &str) -> Vec<FileWarning> {
+    stderr
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.contains("No files were searched"))
+        .map(|line| {
+            let line = line.strip_prefix("rga: ").or_else(|| line.strip_prefix("rg: ")).unwrap_or(line);
+            match line.split_once(": ") {
+                Some((path, message)) if looks_like_file(path) => FileWarning::new(Some(PathBuf::from(path)), message),
+                _ => FileWarning::new(None, line),
+            }
+        })
+        .collect()
+}
+
+fn looks_like_file(path: &str) -> bool {
+    let path = Path::new(path);
+    path.extension().is_some() || path.exists()
+}
+
+fn warning_lines(warnings: &[FileWarning]) -> String {
+    warnings
+        .iter()
+        .map(|warning| match &warning.path {
+            Some(path) => format!("\n{}: {}", path.display(), warning.message),
+            None => format!("\n{}", warning.message),
+        })
+        .collect()
+}
+
+fn file_lines(files: &[PathBuf]) -> String {
+    if files.is_empty() {
+        return String::new();
+    }
+    let files: String = files.iter().map(|file| format!("\n{}", file.display())).collect();
+    format!(" It stopped while searching:{}", files)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -62,4 +180,50 @@ mod tests {
         let status = SearchStatus::new();
         assert_eq!(SearchStatus::NotSearched, status)
     }
-}
\ No newline at end of file
+
+    #[cfg(unix)]
+    fn output(code: i32, stderr: &str) -> Output {
+        use std::os::unix::process::ExitStatusExt;
+        Output {
+            status: std::process::ExitStatus::from_raw(code << 8),
+            stdout: vec![],
+            stderr: stderr.as_bytes().to_vec(),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_exit_code() {
+        assert_eq!(SearchStatus::from_output(&output(0, ""), true).unwrap(), SearchStatus::Found);
+        assert_eq!(SearchStatus::from_output(&output(1, ""), false).unwrap(), SearchStatus::NoMatchesFound);
+        let no_files = "rg: No files were searched, which means ripgrep probably applied a filter you didn't expect.";
+        assert_eq!(SearchStatus::from_output(&output(1, no_files), false).unwrap(), SearchStatus::NoFilesFound);
+        let err = SearchStatus::from_output(&output(2, "regex parse error"), false).unwrap_err();
+        assert!(matches!(err, SearchError::BackendFailed { code: Some(2), .. }));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_files_are_warnings() {
+        let stderr = "rga: docs/locked.pdf: Permission denied (os error 13)\ndocs/broken.pdf: preprocessing failed\n";
+        let status = SearchStatus::from_output(&output(2, stderr), true).unwrap();
+        let expected = vec![
+            FileWarning::new(Some(PathBuf::from("docs/locked.pdf")), "Permission denied (os error 13)"),
+            FileWarning::new(Some(PathBuf::from("docs/broken.pdf")), "preprocessing failed"),
+        ];
+        assert_eq!(status, SearchStatus::PartiallyFound(expected.clone()));
+        assert_eq!(status.warnings(), expected.as_slice());
+        let status = SearchStatus::from_output(&output(2, stderr), false).unwrap();
+        assert_eq!(status, SearchStatus::NoMatchesInReadableFiles(expected));
+        assert!(status.get_status_string().contains("docs/locked.pdf: Permission denied"));
+    }
+
+    #[test]
+    fn missing_backend() {
+        let status = SearchStatus::from(&SearchError::BackendMissing("rga".to_string()));
+        assert_eq!(status, SearchStatus::BackendNotFound("rga".to_string()));
+        let status = SearchStatus::from(&SearchError::InvalidGlob("a{".to_string()));
+        assert_eq!(status, SearchStatus::Failed("Invalid glob pattern: a{".to_string()));
+        assert_eq!(status.get_status_string(), "Invalid glob pattern: a{");
+    }
+}
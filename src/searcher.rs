@@ -1,134 +1,448 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use crate::query::QueryBackend;
+use crate::ranking::{sort_scored, Ranker, SortOrder};
 use crate::search_match::SearchMatch;
 use crate::search_status::SearchStatus;
-use std::process::{Command, Output};
-use crossterm::terminal;
-use crate::powershell::run_powershell_command;
+use crate::search_backend::{default_backend, CancelHandle, SearchBackend, SearchedFile};
+use crate::search_error::SearchError;
+use crate::search_options::{SearchOptions, Syntax};
+
+// What the worker thread of a streamed search sends back.
+enum SearchEvent {
+    File(SearchedFile),
+    Finished(Result<SearchStatus, SearchError>),
+}
+
+// A streamed search on its worker thread.
+struct RunningSearch {
+    events: Receiver<SearchEvent>,
+    cancel: CancelHandle,
+}
 
 pub struct SearchHandler {
     pub search_status: SearchStatus,
     pub search_matches: Option<Vec<SearchMatch>>,
-    glob: String,
-    search_term: String,
+    // How many files a streamed search has found matches in so far, rga does not tell about the others.
+    pub files_with_matches: usize,
+    options: SearchOptions,
+    backend: Arc<dyn SearchBackend>,
+    // Set while a streamed search is running.
+    running: Option<RunningSearch>,
+    // Once set, matches are sorted as they come in. In relevance order `scores` has the score of every match.
+    sort_order: Option<SortOrder>,
+    scores: Vec<f64>,
+    ranker: Ranker,
 }
 
 
 impl SearchHandler {
-    pub fn new(glob: &str, search_term: &str) -> SearchHandler {
+    pub fn new(options: SearchOptions) -> SearchHandler {
+        let backend = default_backend(&options);
+        SearchHandler::with_backend(options, backend)
+    }
+    pub fn with_backend(options: SearchOptions, backend: Box<dyn SearchBackend>) -> SearchHandler {
         SearchHandler {
             search_status: SearchStatus::new(),
             search_matches: None,
-            glob: glob.to_string(),
-            search_term: search_term.to_string(),
+            files_with_matches: 0,
+            backend: Arc::from(backend),
+            running: None,
+            sort_order: None,
+            scores: Vec::new(),
+            ranker: Ranker::new(&options),
+            options,
         }
     }
-    pub fn search(&mut self) -> SearchStatus {
-        let search_hits = self.execute_rga();
-        if let Some(search_hits_string) = search_hits {
-            self.handle_search_hits(search_hits_string);
+    pub fn options(&self) -> &SearchOptions {
+        &self.options
+    }
+    // The old results are dropped, they do not belong to the new options. A search that is still running is cancelled.
+    pub fn set_options(&mut self, options: SearchOptions) {
+        if let Some(running) = self.running.take() {
+            running.cancel.cancel();
         }
-        self.search_status.clone()
-    }
-    fn execute_rga(&mut self) -> Option<String> {
-        // let fixed_arguments = ["rga", "--no-heading", "--line-number", "--path-separator", "/", "--ignore-case", "--type", " pdf", "-C", "8"];
-        // let mut powershell = Command::new("powershell.exe");
-        // let command = powershell.args(fixed_arguments)
-        //     .arg("--glob")
-        //     .arg(&self.glob)
-        //     .arg(&self.search_term);
-        let command = format!("rga --no-heading --line-number --path-separator / --ignore-case --glob '{}' -C 8 '{}'", self.glob, self.search_term);
-        let output = run_powershell_command(&command).unwrap();
-        self.set_search_status(&output);
-        let result = output.stdout;
-        let string = String::from_utf8_lossy(&result).to_string();
-        if string.is_empty() {
-            None
-        } else {
-            Some(string)
+        self.options = options;
+        self.search_status = SearchStatus::new();
+        self.search_matches = None;
+        self.files_with_matches = 0;
+        self.scores.clear();
+        self.ranker = Ranker::new(&self.options);
+    }
+    pub fn search(&mut self) -> Result<SearchStatus, SearchError> {
+        self.set_options(self.options.clone());
+        let (search_status, search_matches) = match search_with(self.backend.as_ref(), &self.options) {
+            Ok(result) => result,
+            Err(err) => {
+                self.search_status = SearchStatus::from(&err);
+                return Err(err);
+            }
+        };
+        self.search_status = search_status;
+        if !search_matches.is_empty() {
+            self.search_matches = Some(search_matches);
+        }
+        Ok(self.search_status.clone())
+    }
+    /// Runs the search on a worker thread. What it finds comes in through `receive_results`.
+    /// A search that is still running is cancelled first.
+    pub fn start_search(&mut self) {
+        self.set_options(self.options.clone());
+        let (sender, receiver) = mpsc::channel();
+        let cancel = CancelHandle::new();
+        let backend = Arc::clone(&self.backend);
+        let options = self.options.clone();
+        let worker_cancel = cancel.clone();
+        thread::spawn(move || {
+            let result = search_files_with(backend.as_ref(), &options, &worker_cancel, &mut |searched_file| {
+                let _ = sender.send(SearchEvent::File(searched_file));
+            });
+            let _ = sender.send(SearchEvent::Finished(result));
+        });
+        self.running = Some(RunningSearch { events: receiver, cancel });
+    }
+    pub fn is_searching(&self) -> bool {
+        self.running.is_some()
+    }
+    /// A handle that stops the running search, None when nothing is running.
+    pub fn cancel_handle(&self) -> Option<CancelHandle> {
+        self.running.as_ref().map(|running| running.cancel.clone())
+    }
+    /// Asks the running search to stop. It keeps what it found so far and finishes with `SearchStatus::Cancelled`,
+    /// which comes in through `receive_results` or `wait` like any other result.
+    pub fn cancel(&self) {
+        if let Some(cancel) = self.cancel_handle() {
+            cancel.cancel();
         }
     }
-    fn handle_search_hits(&mut self, result: String) {
-        let search_matches: Vec<SearchMatch> = result
-            .split("\n--\n")
-            .map(|s| s.trim().to_string())
-            .map(SearchMatch::from)
-            .collect();
-        self.search_matches = Some(search_matches);
+    /// Adds the matches the worker has sent since the last call, without waiting for more.
+    /// Returns whether anything changed, or the error the search ended with.
+    pub fn receive_results(&mut self) -> Result<bool, SearchError> {
+        self.receive(false)
+    }
+    /// Blocks until the running search has finished.
+    pub fn wait(&mut self) -> Result<(), SearchError> {
+        self.receive(true).map(|_| ())
+    }
+    fn receive(&mut self, block: bool) -> Result<bool, SearchError> {
+        let mut changed = false;
+        while let Some(running) = &self.running {
+            let received = if block {
+                running.events.recv().map_err(|_| TryRecvError::Disconnected)
+            } else {
+                running.events.try_recv()
+            };
+            let event = match received {
+                Ok(event) => event,
+                Err(TryRecvError::Empty) => break,
+                // The worker panicked, what it sent is all there is.
+                Err(TryRecvError::Disconnected) => SearchEvent::Finished(Ok(SearchStatus::Aborted(vec![]))),
+            };
+            changed = true;
+            match event {
+                SearchEvent::File(searched_file) => {
+                    let mut new_matches = searched_file.search_matches;
+                    if new_matches.is_empty() {
+                        continue;
+                    }
+                    self.files_with_matches += 1;
+                    let found = self.search_matches.as_ref().map_or(0, Vec::len);
+                    // Enough is found, the rest is not searched.
+                    if let Some(max_results) = self.options.max_results.filter(|&max_results| found + new_matches.len() >= max_results) {
+                        new_matches.truncate(max_results - found);
+                        running.cancel.cancel();
+                    }
+                    self.add_matches(new_matches);
+                }
+                SearchEvent::Finished(result) => {
+                    self.running = None;
+                    // The matches were scored as they came in, with what was known about the others then.
+                    if self.sort_order == Some(SortOrder::Relevance) {
+                        self.sort(SortOrder::Relevance);
+                    }
+                    let found = self.search_matches.as_ref().map_or(0, Vec::len);
+                    match result {
+                        Ok(search_status) => self.search_status = stopped_at_max(search_status, found, &self.options),
+                        Err(err) => {
+                            self.search_status = SearchStatus::from(&err);
+                            return Err(err);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(changed)
+    }
+
+    /// The text of every page of a file, from the index when the search uses one.
+    pub fn pages(&self, path: &Path) -> Result<Vec<String>, SearchError> {
+        self.backend.pages(path)
     }
 
-    fn set_search_status(&mut self, output: &Output) {
-        self.search_status = output.into();
+    /// Like `pages`, on a thread of its own so the tui keeps going while a big pdf is read.
+    pub fn pages_in_background(&self, path: &Path) -> Receiver<Result<Vec<String>, SearchError>> {
+        let (sender, receiver) = mpsc::channel();
+        let backend = Arc::clone(&self.backend);
+        let path = path.to_path_buf();
+        thread::spawn(move || {
+            let _ = sender.send(backend.pages(&path));
+        });
+        receiver
     }
+
+    /// Reorders the matches found so far, see ranking. The ones that come in after are sorted in as well.
+    pub fn sort(&mut self, order: SortOrder) {
+        self.sort_order = Some(order);
+        self.ranker = Ranker::new(&self.options);
+        self.scores.clear();
+        let search_matches = std::mem::take(&mut self.search_matches).unwrap_or_default();
+        self.add_matches(search_matches);
+    }
+
+    // Only the new matches are scored, the ones before are already sorted.
+    fn add_matches(&mut self, new_matches: Vec<SearchMatch>) {
+        if let Some(SortOrder::Relevance) = self.sort_order {
+            self.scores.extend(self.ranker.score(&new_matches, self.backend.corpus()));
+        }
+        let search_matches = self.search_matches.get_or_insert_with(Vec::new);
+        search_matches.extend(new_matches);
+        if let Some(order) = self.sort_order {
+            sort_scored(search_matches, &mut self.scores, order);
+        }
+        if search_matches.is_empty() {
+            self.search_matches = None;
+        }
+    }
+
     pub fn pretty_formatted(&self) -> String {
+        // A search that failed or never ran only has its status to show.
         let mut string = String::from(&self.search_status.get_status_string());
-        match self.search_status {
-            SearchStatus::Found => {
-                let search_matches = self.search_matches.as_ref().unwrap();
-                let mut current_file: PathBuf = PathBuf::new();
-                for search_match in search_matches {
-                    if current_file != search_match.path {
-                        current_file = search_match.path.clone();
-                        string += &*format!("\n{}:\n", current_file.display());
-                    }
-
-                    string += format!("{}\n", search_match).as_str();
+        // Partial, aborted and timed out searches can have matches as well.
+        if let Some(search_matches) = &self.search_matches {
+            let mut current_file: PathBuf = PathBuf::new();
+            for search_match in search_matches {
+                if current_file != search_match.path {
+                    current_file = search_match.path.clone();
+                    string += &*format!("\n{}:\n", current_file.display());
                 }
+
+                string += format!("{}\n", search_match).as_str();
             }
-            SearchStatus::NotSearched => {
-                panic!("Search status should not be NotSearched: {}", string)
-            }
-            SearchStatus::NoMatchesFound | SearchStatus::NoFilesFound => {}
         }
         string
     }
 }
 
+// A query is answered by the backend through QueryBackend, which knows about pages.
+fn search_files_with(
+    backend: &dyn SearchBackend,
+    options: &SearchOptions,
+    cancel: &CancelHandle,
+    on_file: &mut dyn FnMut(SearchedFile),
+) -> Result<SearchStatus, SearchError> {
+    match options.syntax {
+        Syntax::Query => QueryBackend::new(backend).search_files(options, cancel, on_file),
+        _ => backend.search_files(options, cancel, on_file),
+    }
+}
+
+// Stops the search once max_results matches are found.
+fn search_with(backend: &dyn SearchBackend, options: &SearchOptions) -> Result<(SearchStatus, Vec<SearchMatch>), SearchError> {
+    let cancel = CancelHandle::new();
+    let mut search_matches = Vec::new();
+    let search_status = search_files_with(backend, options, &cancel, &mut |searched_file| {
+        search_matches.extend(searched_file.search_matches);
+        if let Some(max_results) = options.max_results.filter(|&max_results| search_matches.len() >= max_results) {
+            search_matches.truncate(max_results);
+            cancel.cancel();
+        }
+    })?;
+    let search_status = stopped_at_max(search_status, search_matches.len(), options);
+    Ok((search_status, search_matches))
+}
+
+// A search that was cancelled because it found max_results matches found what it was asked for.
+fn stopped_at_max(search_status: SearchStatus, found: usize, options: &SearchOptions) -> SearchStatus {
+    match search_status {
+        SearchStatus::Cancelled(_) if options.max_results.is_some_and(|max_results| found >= max_results) => SearchStatus::Found,
+        search_status => search_status,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pdf_text::PdfTextBackend;
+    use crate::rga_backend::RgaBackend;
+    use crate::runner::NativeRunner;
 
     #[test]
-    fn test_execute_rga_md() {
-        let mut sh = SearchHandler::new("test_assets/test.md", "beautiful");
-        let result = sh.execute_rga();
-        let expected_result = "test_assets/test.md:5:This is beautiful text.\r\n".to_string();
-        assert!(result.is_some());
-        assert_eq!(result.unwrap(), expected_result);
+    #[ignore = "requires rga on PATH"]
+    fn search_test() {
+        let mut sh = SearchHandler::with_backend(SearchOptions::new("test_assets/test.*", "subheading"), Box::new(RgaBackend::new(Box::new(NativeRunner))));
+        let result = sh.search().unwrap();
+        let expected_result = SearchStatus::Found;
+        assert_eq!(result, expected_result);
+        assert_eq!(sh.search_status, SearchStatus::Found);
+        assert_eq!(sh.search_matches.unwrap().len(), 2);
     }
 
     #[test]
-    fn test_execute_rga_pdf() {
-        let mut sh = SearchHandler::new("test_assets/test.pdf", "subheading");
-        let result = sh.execute_rga();
-        let expected_result = "test_assets/test.pdf:2:Page 1: This is a subheading - Test\n".to_string();
-        assert!(result.is_some());
-        assert_eq!(result.unwrap(), expected_result);
+    fn search_without_rga() {
+        let mut sh = SearchHandler::with_backend(SearchOptions::new("test_assets/test.*", "subheading"), Box::new(PdfTextBackend::new()));
+        assert_eq!(sh.search().unwrap(), SearchStatus::Found);
+        assert_eq!(sh.search_matches.unwrap().len(), 1);
+        let no_files = SearchHandler::with_backend(SearchOptions::new("assets/*.pdf", "subheading"), Box::new(PdfTextBackend::new())).search().unwrap();
+        assert_eq!(no_files, SearchStatus::NoFilesFound);
     }
 
     #[test]
-    fn no_such_file() {
-        let mut sh = SearchHandler::new("assets/file_does_not_exist.pdf", "subheading");
-        let result = sh.execute_rga();
-        let expected_result: Option<String> = None;
-        assert_eq!(result, expected_result);
+    fn query_matches_pages() {
+        let mut options = SearchOptions::new("test_assets/test.pdf", "heading AND subheading");
+        options.syntax = Syntax::Query;
+        let mut sh = SearchHandler::with_backend(options.clone(), Box::new(PdfTextBackend::new()));
+        assert_eq!(sh.search().unwrap(), SearchStatus::Found);
+        let search_matches = sh.search_matches.take().unwrap();
+        assert_eq!(search_matches.len(), 1);
+        assert_eq!(search_matches[0].content, "This is a heading - Testing");
+        options.search_term = "heading NOT subheading".to_string();
+        sh.set_options(options);
+        sh.start_search();
+        sh.wait().unwrap();
+        assert_eq!(sh.search_status, SearchStatus::NoMatchesFound);
+        assert_eq!(sh.search_matches, None);
+    }
+
+    // Finds two matches in one more file until it is cancelled.
+    struct MatchingBackend;
+
+    impl SearchBackend for MatchingBackend {
+        fn search_files(&self, _options: &SearchOptions, cancel: &CancelHandle, on_file: &mut dyn FnMut(SearchedFile)) -> Result<SearchStatus, SearchError> {
+            for number in 0.. {
+                if cancel.is_cancelled() {
+                    break;
+                }
+                let path = PathBuf::from(format!("{}.pdf", number));
+                let search_matches = vec![SearchMatch::new(path.clone(), 1, 1, "test".to_string(), String::new()); 2];
+                on_file(SearchedFile { path, search_matches });
+                thread::sleep(std::time::Duration::from_millis(1));
+            }
+            Ok(SearchStatus::Cancelled(vec![]))
+        }
     }
 
     #[test]
-    fn no_such_match() {
-        let mut sh = SearchHandler::new("assets/*.pdf", "phrase that doesnt exist in test files");
-        let result = sh.execute_rga();
-        let expected_result: Option<String> = None;
-        assert_eq!(result, expected_result);
+    fn max_results() {
+        let mut options = SearchOptions::new("test_assets/test.pdf", "test");
+        options.max_results = Some(2);
+        let mut sh = SearchHandler::with_backend(options.clone(), Box::new(PdfTextBackend::new()));
+        sh.search().unwrap();
+        assert_eq!(sh.search_matches.unwrap().len(), 2);
+        // The search stops once enough is found, also when it would go on forever.
+        options.max_results = Some(3);
+        let mut sh = SearchHandler::with_backend(options, Box::new(MatchingBackend));
+        assert_eq!(sh.search().unwrap(), SearchStatus::Found);
+        assert_eq!(sh.search_matches.as_ref().unwrap().len(), 3);
+        sh.start_search();
+        sh.wait().unwrap();
+        assert_eq!(sh.search_status, SearchStatus::Found);
+        assert_eq!(sh.search_matches.unwrap().len(), 3);
     }
 
+    fn finish(sh: &mut SearchHandler) -> Result<(), SearchError> {
+        while sh.is_searching() {
+            sh.receive_results()?;
+            thread::sleep(std::time::Duration::from_millis(5));
+        }
+        Ok(())
+    }
 
     #[test]
-    fn search_test() {
-        let mut sh = SearchHandler::new("test_assets/test.*", "subheading");
-        let result = sh.search();
-        let expected_result = SearchStatus::Found;
-        assert_eq!(result, expected_result);
+    fn streamed_search() {
+        let mut sh = SearchHandler::with_backend(SearchOptions::new("test_assets/*", "test"), Box::new(PdfTextBackend::new()));
+        sh.start_search();
+        assert!(sh.is_searching());
+        finish(&mut sh).unwrap();
         assert_eq!(sh.search_status, SearchStatus::Found);
-        assert_eq!(sh.search_matches.unwrap().len(), 2);
+        assert_eq!(sh.files_with_matches, 1);
+        let streamed = sh.search_matches.take();
+        sh.search().unwrap();
+        assert_eq!(streamed, sh.search_matches);
+
+        sh.set_options(SearchOptions::new("test_assets/*", "("));
+        sh.start_search();
+        assert!(matches!(finish(&mut sh), Err(SearchError::InvalidSearchTerm(_))));
+        assert!(!sh.is_searching());
+    }
+
+    #[test]
+    fn sorted_as_they_come_in() {
+        let mut sh = SearchHandler::with_backend(SearchOptions::new("test_assets/*", "test"), Box::new(PdfTextBackend::new()));
+        sh.sort(SortOrder::Page);
+        sh.start_search();
+        finish(&mut sh).unwrap();
+        let search_matches = sh.search_matches.clone().unwrap();
+        assert!(search_matches.windows(2).all(|pair| (pair[0].page, &pair[0].path, pair[0].line) <= (pair[1].page, &pair[1].path, pair[1].line)));
+        // Scored once more at the end, the same as scoring them all at once.
+        sh.sort(SortOrder::Relevance);
+        sh.start_search();
+        finish(&mut sh).unwrap();
+        assert_eq!(sh.scores.len(), search_matches.len());
+        let streamed = sh.search_matches.clone();
+        sh.sort(SortOrder::Relevance);
+        assert_eq!(sh.search_matches, streamed);
+        assert!(sh.scores.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+
+    // Keeps reporting empty files until it is cancelled.
+    struct EndlessBackend;
+
+    impl SearchBackend for EndlessBackend {
+        fn search_files(&self, _options: &SearchOptions, cancel: &CancelHandle, on_file: &mut dyn FnMut(SearchedFile)) -> Result<SearchStatus, SearchError> {
+            while !cancel.is_cancelled() {
+                on_file(SearchedFile { path: PathBuf::from("endless.pdf"), search_matches: vec![] });
+                thread::sleep(std::time::Duration::from_millis(1));
+            }
+            Ok(SearchStatus::Cancelled(vec![]))
+        }
+    }
+
+    #[test]
+    fn cancel_streamed_search() {
+        let mut sh = SearchHandler::with_backend(SearchOptions::new("*.pdf", "test"), Box::new(EndlessBackend));
+        sh.start_search();
+        let first = sh.cancel_handle().unwrap();
+        sh.start_search();
+        // Starting again stopped the first search.
+        assert!(first.is_cancelled());
+        sh.cancel();
+        sh.wait().unwrap();
+        assert!(!sh.is_searching());
+        assert!(sh.cancel_handle().is_none());
+        assert_eq!(sh.search_status, SearchStatus::Cancelled(vec![]));
+        // Files without matches are not counted.
+        assert_eq!(sh.files_with_matches, 0);
+    }
+
+    #[test]
+    fn set_options() {
+        let mut sh = SearchHandler::with_backend(SearchOptions::new("test_assets/test.pdf", "subheading"), Box::new(PdfTextBackend::new()));
+        sh.search().unwrap();
+        sh.set_options(SearchOptions::new("test_assets/test.pdf", "no such words"));
+        assert_eq!(sh.search_status, SearchStatus::NotSearched);
+        assert_eq!(sh.search_matches, None);
+        assert_eq!(sh.search().unwrap(), SearchStatus::NoMatchesFound);
+        assert_eq!(sh.search_matches, None);
+    }
+
+    #[test]
+    fn failed_search_is_printed() {
+        let mut sh = SearchHandler::with_backend(SearchOptions::new("test_assets/test.pdf", "("), Box::new(PdfTextBackend::new()));
+        assert_eq!(sh.pretty_formatted(), "The search has not been run");
+        assert!(matches!(sh.search(), Err(SearchError::InvalidSearchTerm(_))));
+        assert!(matches!(sh.search_status, SearchStatus::Failed(_)));
+        assert!(sh.pretty_formatted().starts_with("Invalid search term"));
     }
 }
@@ -0,0 +1,231 @@
+use std::env;
+use std::str::FromStr;
+use base64::Engine;
+use tui::style::{Color, Style};
+use tui::text::{Span, Spans};
+use crate::page_image::Image;
+
+// Set this to kitty, sixel or blocks when the terminal is not recognised.
+pub const IMAGES_ENV_VAR: &str = "PDF_SEARCH_IMAGES";
+// Kitty takes the image in pieces of at most this many base64 bytes.
+const KITTY_CHUNK: usize = 4096;
+// Removes every image kitty shows, q=2 keeps it from answering.
+pub const KITTY_DELETE: &str = "\x1b_Ga=d,q=2\x1b\\";
+// The size of a cell in pixels when the terminal doesn't tell.
+const DEFAULT_CELL_SIZE: (usize, usize) = (10, 20);
+
+/// How an image gets onto the terminal.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Protocol {
+    Kitty,
+    Sixel,
+    // Two pixels per cell with ▀, works in every terminal with true colour.
+    HalfBlocks,
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "kitty" => Ok(Protocol::Kitty),
+            "sixel" => Ok(Protocol::Sixel),
+            "blocks" | "halfblocks" => Ok(Protocol::HalfBlocks),
+            _ => Err(format!("unknown image protocol '{}', expected kitty, sixel or blocks", s)),
+        }
+    }
+}
+
+impl Protocol {
+    /// Guessed from the terminal's environment, for when the config and `PDF_SEARCH_IMAGES` don't say.
+    /// Asking the terminal means reading its answer from stdin, which the tui is already reading keys from.
+    pub fn detect() -> Protocol {
+        let term = env::var("TERM").unwrap_or_default();
+        let term_program = env::var("TERM_PROGRAM").unwrap_or_default();
+        Protocol::guess(&term, &term_program, env::var_os("KITTY_WINDOW_ID").is_some())
+    }
+
+    fn guess(term: &str, term_program: &str, in_kitty: bool) -> Protocol {
+        if in_kitty || term.contains("kitty") || ["WezTerm", "ghostty"].contains(&term_program) {
+            Protocol::Kitty
+        } else if ["foot", "mlterm", "contour", "yaft"].iter().any(|name| term.starts_with(name)) {
+            Protocol::Sixel
+        } else {
+            Protocol::HalfBlocks
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Protocol::Kitty => "kitty",
+            Protocol::Sixel => "sixel",
+            Protocol::HalfBlocks => "blocks",
+        }
+    }
+}
+
+/// The size of a cell in pixels, to know how many pixels fit in the preview.
+#[cfg(unix)]
+pub fn cell_size() -> (usize, usize) {
+    // SAFETY: TIOCGWINSZ only writes the winsize it is given.
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    // The tui is drawn on stderr, stdout can be a pipe.
+    let found = unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) } == 0;
+    if !found || size.ws_col == 0 || size.ws_row == 0 || size.ws_xpixel == 0 || size.ws_ypixel == 0 {
+        return DEFAULT_CELL_SIZE;
+    }
+    ((size.ws_xpixel / size.ws_col) as usize, (size.ws_ypixel / size.ws_row) as usize)
+}
+
+#[cfg(not(unix))]
+pub fn cell_size() -> (usize, usize) {
+    DEFAULT_CELL_SIZE
+}
+
+/// Shows the image at the cursor, scaled to `columns` by `rows` cells. The cursor stays where it is.
+pub fn kitty(image: &Image, columns: usize, rows: usize) -> String {
+    let bytes: Vec<u8> = image.pixels.iter().flatten().copied().collect();
+    let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+    let chunks: Vec<&str> = encoded.as_bytes().chunks(KITTY_CHUNK).map(|chunk| std::str::from_utf8(chunk).unwrap_or_default()).collect();
+    let mut escape = String::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        // The first piece says what the image is, m=1 means more pieces follow.
+        if index == 0 {
+            escape.push_str(&format!("\x1b_Ga=T,f=24,s={},v={},c={},r={},C=1,q=2,", image.width, image.height, columns, rows));
+        } else {
+            escape.push_str("\x1b_G");
+        }
+        escape.push_str(&format!("m={};{}\x1b\\", u8::from(index + 1 < chunks.len()), chunk));
+    }
+    escape
+}
+
+/// Shows the image at the cursor as sixels, in the 216 colours of a 6x6x6 cube. Pages are mostly
+/// black and white, so that is plenty.
+pub fn sixel(image: &Image) -> String {
+    let mut escape = format!("\x1bPq\"1;1;{};{}", image.width, image.height);
+    for colour in 0..216 {
+        let percent = |level: usize| level * 100 / 5;
+        escape.push_str(&format!("#{};2;{};{};{}", colour, percent(colour / 36), percent(colour / 6 % 6), percent(colour % 6)));
+    }
+    let colours: Vec<usize> = image
+        .pixels
+        .iter()
+        .map(|pixel| pixel.iter().fold(0, |colour, &channel| colour * 6 + (channel as usize * 5 + 127) / 255))
+        .collect();
+    // Every band is six rows of pixels, drawn once for every colour in it.
+    for band in (0..image.height).step_by(6) {
+        let rows = band..(band + 6).min(image.height);
+        let mut used: Vec<usize> = rows.clone().flat_map(|row| colours[row * image.width..(row + 1) * image.width].iter().copied()).collect();
+        used.sort_unstable();
+        used.dedup();
+        for colour in used {
+            let sixels = (0..image.width).map(|column| {
+                let bits = rows.clone().filter(|&row| colours[row * image.width + column] == colour).fold(0, |bits, row| bits | 1 << (row - band));
+                (b'?' + bits) as char
+            });
+            escape.push_str(&format!("#{}{}$", colour, run_length_encoded(sixels)));
+        }
+        escape.push('-');
+    }
+    escape.push_str("\x1b\\");
+    escape
+}
+
+// Four or more of the same sixel in a row are written as !<count><sixel>.
+fn run_length_encoded(sixels: impl Iterator<Item = char>) -> String {
+    let mut encoded = String::new();
+    let mut run: Option<(char, usize)> = None;
+    let flush = |encoded: &mut String, (sixel, count): (char, usize)| {
+        if count >= 4 {
+            encoded.push_str(&format!("!{}{}", count, sixel));
+        } else {
+            encoded.extend(std::iter::repeat_n(sixel, count));
+        }
+    };
+    for sixel in sixels {
+        run = match run {
+            Some((current, count)) if current == sixel => Some((current, count + 1)),
+            Some(finished) => {
+                flush(&mut encoded, finished);
+                Some((sixel, 1))
+            }
+            None => Some((sixel, 1)),
+        };
+    }
+    if let Some(finished) = run {
+        flush(&mut encoded, finished);
+    }
+    encoded
+}
+
+/// The image as lines of ▀, the top pixel is the colour of the character and the bottom one its background.
+pub fn half_blocks(image: &Image) -> Vec<Spans<'static>> {
+    let colour = |column: usize, row: usize| {
+        image.pixels.get(row * image.width + column).map_or(Color::Reset, |&[red, green, blue]| Color::Rgb(red, green, blue))
+    };
+    (0..image.height)
+        .step_by(2)
+        .map(|row| {
+            let cells: Vec<Span> = (0..image.width).map(|column| Span::styled("▀", Style::default().fg(colour(column, row)).bg(colour(column, row + 1)))).collect();
+            Spans::from(cells)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: usize, height: usize, pixels: &[[u8; 3]]) -> Image {
+        Image { width, height, pixels: pixels.to_vec() }
+    }
+
+    #[test]
+    fn protocols() {
+        assert_eq!(Protocol::guess("xterm-kitty", "", false), Protocol::Kitty);
+        assert_eq!(Protocol::guess("xterm-256color", "WezTerm", false), Protocol::Kitty);
+        assert_eq!(Protocol::guess("xterm-256color", "", true), Protocol::Kitty);
+        assert_eq!(Protocol::guess("foot-extra", "", false), Protocol::Sixel);
+        assert_eq!(Protocol::guess("xterm-256color", "", false), Protocol::HalfBlocks);
+        assert_eq!("Sixel".parse(), Ok(Protocol::Sixel));
+        assert!("png".parse::<Protocol>().is_err());
+    }
+
+    #[test]
+    fn kitty_in_pieces() {
+        let escape = kitty(&image(1, 2, &[[255, 0, 0], [0, 0, 255]]), 3, 4);
+        assert_eq!(escape, "\x1b_Ga=T,f=24,s=1,v=2,c=3,r=4,C=1,q=2,m=0;/wAAAAD/\x1b\\");
+        let large = image(2000, 1, &[[1, 2, 3]; 2000]);
+        let escape = kitty(&large, 1, 1);
+        assert_eq!(escape.matches("\x1b_G").count(), 2);
+        assert!(escape.contains("m=1;") && escape.ends_with("\x1b\\"));
+    }
+
+    #[test]
+    fn sixel_bands() {
+        let white = [255, 255, 255];
+        let black = [0, 0, 0];
+        let mut pixels = vec![white; 5 * 7];
+        pixels[0] = black;
+        pixels[6 * 5] = black;
+        let escape = sixel(&image(5, 7, &pixels));
+        assert!(escape.starts_with("\x1bPq\"1;1;5;7#0;2;0;0;0"));
+        // The first band has the black pixel at the top of the first column, the second band at the top again.
+        assert!(escape.ends_with("#0@!4?$#215}!4~$-#0@!4?$#215?!4@$-\x1b\\"), "{:?}", escape);
+    }
+
+    #[test]
+    fn runs() {
+        assert_eq!(run_length_encoded("??~~~~~~@".chars()), "??!6~@");
+        assert_eq!(run_length_encoded("".chars()), "");
+    }
+
+    #[test]
+    fn two_pixels_per_cell() {
+        let lines = half_blocks(&image(1, 3, &[[1, 1, 1], [2, 2, 2], [3, 3, 3]]));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].0[0].style, Style::default().fg(Color::Rgb(1, 1, 1)).bg(Color::Rgb(2, 2, 2)));
+        assert_eq!(lines[1].0[0].style, Style::default().fg(Color::Rgb(3, 3, 3)).bg(Color::Reset));
+    }
+}
@@ -1,22 +1,40 @@
 use std::{io, thread, time::Duration};
+use std::io::Write;
 use std::panic::panic_any;
 use std::path::{Path, PathBuf};
 use std::process::exit;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
 use std::thread::sleep;
 use std::time::Instant;
 use tui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Corner, Direction, Layout},
+    layout::{Constraint, Corner, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Span, Spans},
-    widgets::{Widget, Block, Borders, List, ListItem, ListState},
+    widgets::{Widget, Block, Borders, Clear, List, ListItem, ListState},
     Frame, Terminal,
 };
-use crossterm::{event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode}, execute, terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen}, terminal};
+use crossterm::{event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers}, execute, terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen}, terminal};
 use crossterm::terminal::ClearType;
+use crossterm::{cursor::MoveTo, queue};
 use tui::widgets::{Paragraph, Wrap};
+use crate::config::Config;
+use crate::fuzzy_finder::{FilteredMatch, FuzzyFinder};
+use crate::highlight::{Highlighter, Highlights};
+use crate::keymap::{Action, Keymap};
+use crate::page_image::PageImage;
+use crate::page_preview::PagePreview;
 use crate::pdf_opener;
+use crate::ranking::SortOrder;
+use crate::search_error::SearchError;
 use crate::search_match::SearchMatch;
+use crate::search_options::{CaseMode, SearchOptions, Syntax};
+use crate::searcher::SearchHandler;
+use crate::search_status::SearchStatus;
+use crate::terminal_image::{cell_size, half_blocks, kitty, sixel, Protocol, KITTY_DELETE};
+
+// How long typing has to pause before the search is run again.
+const SEARCH_DEBOUNCE: Duration = Duration::from_millis(300);
 
 struct StatefulList<T> {
     state: ListState,
@@ -24,18 +42,23 @@ struct StatefulList<T> {
 }
 
 impl<T> StatefulList<T> {
-    fn get_selected_item(&self) -> &T {
-        &self.items[self.state.selected().unwrap()]
+    fn get_selected_item(&self) -> Option<&T> {
+        self.items.get(self.state.selected()?)
     }
     fn with_items(items: Vec<T>) -> StatefulList<T> {
         let mut sl = StatefulList {
             state: ListState::default(),
             items,
         };
-        sl.state.select(Some(0));
+        if !sl.items.is_empty() {
+            sl.state.select(Some(0));
+        }
         sl
     }
     fn next(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i >= self.items.len() - 1 {
@@ -50,6 +73,9 @@ impl<T> StatefulList<T> {
     }
 
     fn previous(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i == 0 {
@@ -62,23 +88,409 @@ impl<T> StatefulList<T> {
         };
         self.state.select(Some(i));
     }
+
+    // Moves by more than one item, stopping at the ends instead of wrapping around.
+    fn move_by(&mut self, items: isize) {
+        if self.items.is_empty() {
+            return;
+        }
+        let i = self.state.selected().unwrap_or(0).saturating_add_signed(items);
+        self.state.select(Some(i.min(self.items.len() - 1)));
+    }
+
+    fn first(&mut self) {
+        if !self.items.is_empty() {
+            self.state.select(Some(0));
+        }
+    }
+
+    fn last(&mut self) {
+        if !self.items.is_empty() {
+            self.state.select(Some(self.items.len() - 1));
+        }
+    }
+}
+
+/// What enter does with the selected match.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TuiMode {
+    // Open it in the pdf viewer and keep going.
+    Open,
+    // Close the tui and hand it back, like fzf.
+    Pick,
+}
+
+// Which part of the input line is being typed in.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum InputField {
+    Query,
+    Glob,
+    // Narrows the results that are already there, without searching again.
+    Filter,
+}
+
+impl InputField {
+    fn next(self) -> InputField {
+        match self {
+            InputField::Query => InputField::Glob,
+            InputField::Glob => InputField::Filter,
+            InputField::Filter => InputField::Query,
+        }
+    }
+
+    fn previous(self) -> InputField {
+        match self {
+            InputField::Query => InputField::Filter,
+            InputField::Glob => InputField::Query,
+            InputField::Filter => InputField::Glob,
+        }
+    }
+}
+
+// What the preview shows of the selected match, p goes to the next one.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum PreviewMode {
+    // The lines around the match the backend found.
+    Context,
+    Page,
+    // The page rendered by pdftoppm.
+    Image,
+}
+
+impl PreviewMode {
+    fn next(self) -> PreviewMode {
+        match self {
+            PreviewMode::Context => PreviewMode::Page,
+            PreviewMode::Page => PreviewMode::Image,
+            PreviewMode::Image => PreviewMode::Context,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum InputMode {
+    Normal,
+    Editing(InputField),
 }
 
-pub fn run(items: Vec<SearchMatch>, search_term: &str) -> Result<SearchMatch, io::Error> {
-    let mut stateful_list = StatefulList::with_items(items);
+// What the threads of the page preview and the image preview send back.
+type Pages = Result<Vec<String>, SearchError>;
+type Rendered = Result<PageImage, SearchError>;
+
+struct App<'a> {
+    search_handler: &'a mut SearchHandler,
+    stateful_list: StatefulList<FilteredMatch>,
+    fuzzy_finder: FuzzyFinder,
+    // The options shown in the input line. They are handed to the search handler once typing pauses.
+    options: SearchOptions,
+    input_mode: InputMode,
+    search_due: Option<Instant>,
+    message: Option<String>,
+    sort_order: SortOrder,
+    // For the search that is shown, not the one being typed.
+    highlighter: Highlighter,
+    preview_mode: PreviewMode,
+    page_preview: Option<PagePreview>,
+    // The match the page preview was last moved to, it only moves again when the selection changes.
+    previewed_match: Option<SearchMatch>,
+    page_image: Option<PageImage>,
+    // The pdf whose pages are read for the page preview and the page that is rendered for the image,
+    // both on threads of their own so holding j doesn't freeze the tui.
+    loading_pages: Option<(PathBuf, Receiver<Pages>)>,
+    rendering: Option<((PathBuf, usize), Receiver<Rendered>)>,
+    image_protocol: Protocol,
+    // The page and the terminal size a kitty or sixel image was drawn for. tui doesn't know about it,
+    // so it stays on the screen until it is removed.
+    image_on_screen: Option<(PathBuf, usize, Rect)>,
+    // Every search term gets the next one.
+    term_colours: Vec<Color>,
+    selection_colour: Color,
+    viewer: Option<String>,
+    keymap: Keymap,
+    show_help: bool,
+    // About how many matches fit in the list, a match takes two lines and more at the start of a pdf.
+    list_page: usize,
+}
+
+impl<'a> App<'a> {
+    fn new(search_handler: &'a mut SearchHandler, sort_order: SortOrder, config: &Config) -> App<'a> {
+        let mut app = App {
+            options: search_handler.options().clone(),
+            highlighter: Highlighter::new(search_handler.options()),
+            search_handler,
+            stateful_list: StatefulList::with_items(vec![]),
+            fuzzy_finder: FuzzyFinder::new(),
+            input_mode: InputMode::Normal,
+            search_due: None,
+            message: None,
+            sort_order,
+            preview_mode: PreviewMode::Context,
+            page_preview: None,
+            previewed_match: None,
+            page_image: None,
+            loading_pages: None,
+            rendering: None,
+            image_protocol: config.images.value.unwrap_or_else(Protocol::detect),
+            image_on_screen: None,
+            term_colours: config.term_colours.value.clone(),
+            selection_colour: config.selection_colour.value,
+            viewer: config.viewer.value.clone(),
+            keymap: config.keymap(),
+            show_help: false,
+            list_page: 1,
+        };
+        app.search_handler.sort(sort_order);
+        app.refilter();
+        app
+    }
+
+    fn selected_match(&self) -> Option<&SearchMatch> {
+        self.stateful_list.get_selected_item().map(|filtered_match| &filtered_match.search_match)
+    }
+
+    // The selection stays on the same match when it is still in the list, results keep coming in while a search runs.
+    fn refilter(&mut self) {
+        let selected_match = self.selected_match().cloned();
+        // The search handler keeps the matches in the sort order. The filter sorts by its own score,
+        // matches it scores the same stay in that order.
+        let search_matches = self.search_handler.search_matches.as_deref().unwrap_or_default();
+        self.stateful_list = StatefulList::with_items(self.fuzzy_finder.filter(search_matches));
+        let selected = selected_match.and_then(|selected_match| {
+            self.stateful_list.items.iter().position(|filtered_match| filtered_match.search_match == selected_match)
+        });
+        if selected.is_some() {
+            self.stateful_list.state.select(selected);
+        }
+    }
+
+    fn receive_results(&mut self) {
+        match self.search_handler.receive_results() {
+            Ok(false) => return,
+            Ok(true) => {}
+            Err(err) => self.message = Some(err.to_string()),
+        }
+        if matches!(self.search_handler.search_status, SearchStatus::Cancelled(_)) {
+            self.message = Some(self.search_handler.search_status.get_status_string());
+        }
+        self.refilter();
+    }
+
+    fn edit_options(&mut self, edit: impl FnOnce(&mut SearchOptions)) {
+        edit(&mut self.options);
+        self.search_due = Some(Instant::now() + SEARCH_DEBOUNCE);
+    }
+
+    fn edit_field(&mut self, field: InputField, edit: impl FnOnce(&mut String)) {
+        match field {
+            InputField::Query => self.edit_options(|options| edit(&mut options.search_term)),
+            InputField::Glob => self.edit_options(|options| edit(&mut options.glob)),
+            InputField::Filter => {
+                edit(&mut self.fuzzy_finder.query);
+                self.refilter();
+            }
+        }
+    }
+
+    // What an action does that is the same while typing and while browsing.
+    fn act(&mut self, action: Action) {
+        match action {
+            Action::Down => self.stateful_list.next(),
+            Action::Up => self.stateful_list.previous(),
+            Action::PageDown => self.stateful_list.move_by(self.list_page as isize),
+            Action::PageUp => self.stateful_list.move_by(-(self.list_page as isize)),
+            Action::First => self.stateful_list.first(),
+            Action::Last => self.stateful_list.last(),
+            Action::NextFile => self.next_file(),
+            Action::PreviousFile => self.previous_file(),
+            Action::Preview => self.next_preview_mode(),
+            Action::Case => self.edit_options(|options| {
+                options.case = match options.case {
+                    CaseMode::Insensitive => CaseMode::Sensitive,
+                    CaseMode::Sensitive => CaseMode::Smart,
+                    CaseMode::Smart => CaseMode::Insensitive,
+                }
+            }),
+            Action::Syntax => self.edit_options(|options| {
+                options.syntax = match options.syntax {
+                    Syntax::Regex => Syntax::Fixed,
+                    Syntax::Fixed => Syntax::Query,
+                    Syntax::Query => Syntax::Regex,
+                }
+            }),
+            // Only the order changes, nothing is searched again.
+            Action::Order => {
+                self.sort_order = self.sort_order.next();
+                self.search_handler.sort(self.sort_order);
+                self.refilter();
+            }
+            Action::PreviewDown | Action::PreviewUp | Action::NextPage | Action::PreviousPage => {
+                if let Some(page_preview) = self.page_preview.as_mut().filter(|_| self.preview_mode != PreviewMode::Context) {
+                    match action {
+                        Action::PreviewDown => page_preview.scroll_by(1),
+                        Action::PreviewUp => page_preview.scroll_by(-1),
+                        Action::NextPage => page_preview.step_page(1),
+                        _ => page_preview.step_page(-1),
+                    }
+                }
+            }
+            Action::Help => self.show_help = true,
+            // The rest depend on where the key was pressed, see handle_normal_key and handle_editing_key.
+            _ => {}
+        }
+    }
+
+    // The first match after the selected one that is in another pdf. In relevance order that is not
+    // always the next pdf in the folder, the matches of a pdf are not together.
+    fn next_file(&mut self) {
+        let Some(selected) = self.stateful_list.state.selected() else {
+            return;
+        };
+        let items = &self.stateful_list.items;
+        let path = &items[selected].search_match.path;
+        if let Some(offset) = items[selected..].iter().position(|item| item.search_match.path != *path) {
+            self.stateful_list.state.select(Some(selected + offset));
+        }
+    }
+
+    // The first match of the matches before the selected one's that are in another pdf.
+    fn previous_file(&mut self) {
+        let Some(selected) = self.stateful_list.state.selected() else {
+            return;
+        };
+        let items = &self.stateful_list.items;
+        let path = &items[selected].search_match.path;
+        if let Some(end) = items[..selected].iter().rposition(|item| item.search_match.path != *path) {
+            let other = &items[end].search_match.path;
+            let start = items[..end].iter().rposition(|item| item.search_match.path != *other).map_or(0, |index| index + 1);
+            self.stateful_list.state.select(Some(start));
+        }
+    }
+
+    fn next_preview_mode(&mut self) {
+        self.preview_mode = self.preview_mode.next();
+        self.previewed_match = None;
+        self.update_preview();
+    }
+
+    fn update_preview(&mut self) {
+        let Some(selected_match) = self.selected_match().cloned() else {
+            return;
+        };
+        if self.preview_mode != PreviewMode::Context && self.previewed_match.as_ref() != Some(&selected_match) {
+            self.update_page_preview(&selected_match);
+        }
+        if self.preview_mode == PreviewMode::Image {
+            self.update_page_image(&selected_match);
+        }
+    }
+
+    // Moves the page preview to the selected match. The pdf is only read again when the match is in another one,
+    // until its pages have come in the preview stays where it was.
+    fn update_page_preview(&mut self, selected_match: &SearchMatch) {
+        if self.page_preview.as_ref().map(|preview| &preview.path) != Some(&selected_match.path) {
+            if self.loading_pages.as_ref().map(|(path, _)| path) != Some(&selected_match.path) {
+                self.page_preview = None;
+                self.loading_pages = Some((selected_match.path.clone(), self.search_handler.pages_in_background(&selected_match.path)));
+            }
+            let Some((_, receiver)) = &self.loading_pages else {
+                return;
+            };
+            let pages = match receiver.try_recv() {
+                Ok(pages) => pages,
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => Err(SearchError::UnreadablePdf("reading it crashed".to_string())),
+            };
+            self.loading_pages = None;
+            self.page_preview = match pages {
+                Ok(pages) => Some(PagePreview::new(&selected_match.path, &pages)),
+                Err(err) => {
+                    self.message = Some(err.to_string());
+                    None
+                }
+            };
+        }
+        if let Some(page_preview) = &mut self.page_preview {
+            page_preview.show_match(selected_match);
+        }
+        self.previewed_match = Some(selected_match.clone());
+    }
+
+    // The image is of the page the page preview is on, the arrow keys step through the pages of both.
+    // Without pdftoppm the page is shown as text.
+    fn update_page_image(&mut self, selected_match: &SearchMatch) {
+        let page = self.page_preview.as_ref().filter(|preview| preview.path == selected_match.path).map_or(selected_match.page, |preview| preview.page);
+        if self.page_image.as_ref().is_some_and(|image| image.path == selected_match.path && image.page == page) {
+            return;
+        }
+        let wanted = (selected_match.path.clone(), page);
+        if self.rendering.as_ref().map(|(rendering, _)| rendering) != Some(&wanted) {
+            let content = if page == selected_match.page { selected_match.content.clone() } else { String::new() };
+            let mut highlighter = self.highlighter.clone();
+            let path = selected_match.path.clone();
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                let _ = sender.send(PageImage::render(&path, page, &mut highlighter, &content));
+            });
+            self.page_image = None;
+            self.rendering = Some((wanted, receiver));
+        }
+        let Some((_, receiver)) = &self.rendering else {
+            return;
+        };
+        let rendered = match receiver.try_recv() {
+            Ok(rendered) => rendered,
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => Err(SearchError::Io(io::Error::other("rendering crashed"))),
+        };
+        self.rendering = None;
+        match rendered {
+            Ok(page_image) => self.page_image = Some(page_image),
+            Err(err) => {
+                self.message = Some(format!("Could not show the page as an image: {}", err));
+                self.preview_mode = PreviewMode::Page;
+            }
+        }
+    }
+
+    fn is_loading_preview(&self) -> bool {
+        self.loading_pages.is_some() || self.rendering.is_some()
+    }
+
+    fn run_search_if_due(&mut self) {
+        match self.search_due {
+            Some(due) if Instant::now() >= due => self.search_due = None,
+            _ => return,
+        }
+        if self.options.search_term.is_empty() || self.options == *self.search_handler.options() {
+            return;
+        }
+        self.search_handler.set_options(self.options.clone());
+        self.highlighter = Highlighter::new(&self.options);
+        // The outlines are for the old search terms.
+        self.page_image = None;
+        self.rendering = None;
+        self.search_handler.start_search();
+        self.message = None;
+        self.refilter();
+    }
+}
+
+// Returns the picked match, None when the user quit without picking one.
+// The search handler is left with whatever was searched last.
+pub fn run(search_handler: &mut SearchHandler, mode: TuiMode, sort_order: SortOrder, config: &Config) -> Result<Option<SearchMatch>, io::Error> {
+    let mut app = App::new(search_handler, sort_order, config);
 
     // setup terminal
+    // The tui is drawn on stderr, so stdout can be piped into another program.
     enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
+    let mut stderr = io::stderr();
+    execute!(stderr, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(stderr);
     let mut terminal = Terminal::new(backend)?;
 
 
-    let selected_search_match = run_app(&mut terminal, &mut stateful_list, search_term)?;
-
-    // draw_ui(&mut terminal, &mut stateful_list)?;
-    // thread::sleep(Duration::from_millis(4000));
+    let selected_search_match = run_app(&mut terminal, &mut app, mode);
+    remove_terminal_image(&mut terminal, &mut app)?;
 
     // restore terminal
     disable_raw_mode()?;
@@ -89,56 +501,196 @@ pub fn run(items: Vec<SearchMatch>, search_term: &str) -> Result<SearchMatch, io
     )?;
     terminal.show_cursor()?;
     terminal.clear()?;
-    Ok(selected_search_match)
+    selected_search_match
 }
 
-fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, stateful_list: &mut StatefulList<SearchMatch>, search_term: &str) -> Result<SearchMatch, io::Error> {
+fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stderr>>, app: &mut App, mode: TuiMode) -> Result<Option<SearchMatch>, io::Error> {
     let tick_rate = Duration::from_millis(250);
     let mut last_tick = Instant::now();
     loop {
-        draw_ui(terminal, stateful_list, search_term)?;
-        let timeout = tick_rate
+        app.update_preview();
+        if app.image_on_screen.is_some() && app.image_on_screen != wanted_terminal_image(terminal, app)? {
+            remove_terminal_image(terminal, app)?;
+        }
+        let image_area = draw_ui(terminal, app)?;
+        show_terminal_image(terminal, app, image_area)?;
+        let mut timeout = tick_rate
             .checked_sub(last_tick.elapsed())
             .unwrap_or_else(|| Duration::from_secs(0));
+        if let Some(due) = app.search_due {
+            timeout = timeout.min(due.saturating_duration_since(Instant::now()));
+        }
+        // Results and previews are picked up between key presses, so wait less while they are coming in.
+        if app.search_handler.is_searching() || app.is_loading_preview() {
+            timeout = timeout.min(Duration::from_millis(50));
+        }
         if event::poll(timeout)? {
             if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Enter => {
-                        let selected_match = stateful_list.get_selected_item();
-                        pdf_opener::open_pdf(selected_match);
-                        // I want to redraw the screen after the pdf has opened so it doesnt look weird. We wait a little and then do it.
-                        sleep(Duration::from_millis(500));
-                        terminal.clear()?;
-                    }
-                    KeyCode::Char('q') => {
-                        let selected_match = stateful_list.items[stateful_list.state.selected().unwrap()].clone();
-                        return Ok(selected_match);
+                // Any key closes the help, it does nothing else.
+                if app.show_help {
+                    app.show_help = false;
+                    continue;
+                }
+                let done = match app.input_mode {
+                    InputMode::Editing(field) => {
+                        handle_editing_key(app, field, key);
+                        None
                     }
-                    KeyCode::Down => stateful_list.next(),
-                    KeyCode::Up => stateful_list.previous(),
-                    _ => {}
+                    InputMode::Normal => handle_normal_key(terminal, app, mode, key)?,
+                };
+                if let Some(result) = done {
+                    return Ok(result);
                 }
             }
         }
+        app.run_search_if_due();
+        app.receive_results();
         if last_tick.elapsed() >= tick_rate {
             last_tick = Instant::now();
         }
     }
 }
 
-fn draw_ui(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, stateful_list: &mut StatefulList<SearchMatch>, search_term: &str) -> Result<(), io::Error> {
+// Returns Some when the tui should close, with the picked match if there is one.
+fn handle_normal_key(terminal: &mut Terminal<CrosstermBackend<io::Stderr>>, app: &mut App, mode: TuiMode, key: KeyEvent) -> Result<Option<Option<SearchMatch>>, io::Error> {
+    let Some(action) = app.keymap.action(key) else {
+        return Ok(None);
+    };
+    match action {
+        Action::Open if mode == TuiMode::Pick => {
+            if let Some(selected_match) = app.selected_match() {
+                return Ok(Some(Some(selected_match.clone())));
+            }
+        }
+        Action::Open => {
+            if let Some(selected_match) = app.selected_match() {
+                app.message = pdf_opener::open_pdf(selected_match, app.viewer.as_deref())
+                    .err()
+                    .map(|err| format!("Could not open pdf: {}", err));
+                // I want to redraw the screen after the pdf has opened so it doesnt look weird. We wait a little and then do it.
+                sleep(Duration::from_millis(500));
+                remove_terminal_image(terminal, app)?;
+                terminal.clear()?;
+            }
+        }
+        // Esc stops a running search first, the results so far stay.
+        Action::Cancel if app.search_handler.is_searching() => app.search_handler.cancel(),
+        Action::Cancel | Action::Quit => {
+            return Ok(Some(None));
+        }
+        Action::EditQuery => app.input_mode = InputMode::Editing(InputField::Query),
+        Action::Filter => app.input_mode = InputMode::Editing(InputField::Filter),
+        _ => app.act(action),
+    }
+    Ok(None)
+}
+
+fn handle_editing_key(app: &mut App, field: InputField, key: KeyEvent) {
+    let typed = !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+    match app.keymap.editing_action(key) {
+        Some(Action::EndEditing) => {
+            app.input_mode = InputMode::Normal;
+            // No need to wait for the debounce when the user is done typing.
+            if app.search_due.is_some() {
+                app.search_due = Some(Instant::now());
+            }
+        }
+        Some(Action::NextField) => app.input_mode = InputMode::Editing(field.next()),
+        Some(Action::PreviousField) => app.input_mode = InputMode::Editing(field.previous()),
+        Some(Action::ClearField) => app.edit_field(field, |text| text.clear()),
+        Some(Action::DeleteLast) => app.edit_field(field, |text| {
+            text.pop();
+        }),
+        _ => match key.code {
+            KeyCode::Char(c) if typed => app.edit_field(field, |text| text.push(c)),
+            // The toggles and moving around work while typing as well.
+            _ => {
+                if let Some(action) = app.keymap.action(key).filter(Action::while_typing) {
+                    app.act(action);
+                }
+            }
+        },
+    }
+}
+
+fn input_line(app: &App) -> Spans<'static> {
+    let field_style = |field: InputField| {
+        if app.input_mode == InputMode::Editing(field) {
+            Style::default().bg(Color::LightYellow).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        }
+    };
+    let hint = |action: Action| app.keymap.hint(action);
+    let case = match app.options.case {
+        CaseMode::Insensitive => "ignore case",
+        CaseMode::Sensitive => "case sensitive",
+        CaseMode::Smart => "smart case",
+    };
+    let kind = app.options.syntax.name();
+    Spans::from(vec![
+        Span::raw("Query: "),
+        Span::styled(app.options.search_term.clone(), field_style(InputField::Query)),
+        Span::raw("  Glob: "),
+        Span::styled(app.options.glob.clone(), field_style(InputField::Glob)),
+        Span::raw("  Filter: "),
+        Span::styled(app.fuzzy_finder.query.clone(), field_style(InputField::Filter)),
+        Span::styled(
+            format!("  [{} {}] [{} {}] [{} {} order]", hint(Action::Case), case, hint(Action::Syntax), kind, hint(Action::Order), app.sort_order.name()),
+            Style::default().add_modifier(Modifier::ITALIC),
+        ),
+    ])
+}
+
+// Returns where a kitty or sixel image goes, tui only leaves the space for it.
+fn draw_ui(terminal: &mut Terminal<CrosstermBackend<io::Stderr>>, app: &mut App) -> Result<Option<Rect>, io::Error> {
+    let hint = |action: Action| app.keymap.hint(action);
+    let input_title = match app.input_mode {
+        InputMode::Normal => format!("Search ({} to edit, {} to filter, {} for keys)", hint(Action::EditQuery), hint(Action::Filter), hint(Action::Help)),
+        InputMode::Editing(_) => format!("Search ({} switches field, {} when done)", hint(Action::NextField), hint(Action::EndEditing)),
+    };
+    let page_title = format!("{}/{} scroll, {}/{} page", hint(Action::PreviewUp), hint(Action::PreviewDown), hint(Action::PreviousPage), hint(Action::NextPage));
+    let preview_key = hint(Action::Preview);
+    let help = app.show_help.then(|| help_lines(&app.keymap));
+    let input = Paragraph::new(input_line(app)).block(Block::default().borders(Borders::ALL).title(input_title));
+    let filtering = !app.fuzzy_finder.query.is_empty();
+    let searching = app.search_handler.is_searching().then_some(app.search_handler.files_with_matches);
+    let cancel_key = hint(Action::Cancel);
+    let stateful_list = &mut app.stateful_list;
+    let highlighter = &mut app.highlighter;
+    let term_colours = &app.term_colours;
+    let selection_colour = app.selection_colour;
+    let message = app.message.as_deref();
+    let page_preview = app.page_preview.as_mut().filter(|_| app.preview_mode != PreviewMode::Context);
+    let page_image = app.page_image.as_ref().filter(|_| app.preview_mode == PreviewMode::Image);
+    let image_protocol = app.image_protocol;
+    let rendering = app.rendering.as_ref().filter(|_| app.preview_mode == PreviewMode::Image).map(|((_, page), _)| *page);
+    let loading_pages = app.loading_pages.is_some() && app.preview_mode != PreviewMode::Context;
+    let mut image_area = None;
+    let mut list_page = app.list_page;
     terminal.draw(|f| {
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+            .split(f.size());
+        f.render_widget(input, rows[0]);
         // Create two chunks with equal horizontal screen space
         let chunks = Layout::default()
             .direction(Direction::Horizontal)
             .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
-            .split(f.size());
-        let selected_match = &stateful_list.items[stateful_list.state.selected().unwrap()];
+            .split(rows[1]);
+        let selected_match = stateful_list.get_selected_item().map(|filtered_match| filtered_match.search_match.clone());
         // Iterate through all elements in the `items` app and append some debug text to it.
         let mut current_file_path: Option<PathBuf> = None;
         let items: Vec<ListItem> = stateful_list.items
             .iter()
-            .map(|search_match| {
+            .map(|filtered_match| {
+                let search_match = &filtered_match.search_match;
+                // A filtered list is sorted by score, so it is not grouped by file. Each row shows what the filter matched.
+                if filtering {
+                    let line = highlighted(&search_match.fuzzy_display(), &filtered_match.fuzzy_match.positions);
+                    return ListItem::new(line).style(Style::default().fg(Color::Black).bg(Color::White));
+                }
                 let mut lines = if current_file_path.is_none() || *current_file_path.as_ref().unwrap() != search_match.path {
                     let mut vec = vec![Spans::from("-".repeat((f.size().width / 2) as usize))];
                     current_file_path = Some(search_match.path.clone());
@@ -150,7 +702,7 @@ fn draw_ui(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, stateful_list:
                 } else {
                     vec![]
                 };
-                lines.push(Spans::from(search_match.content.to_string()));
+                lines.push(highlighted_terms(&search_match.content, &highlighter.find_in_match(search_match), Style::default(), term_colours));
                 lines.push(Spans::from(Span::styled(
                     format!("Page: {} Line: {}", search_match.page, search_match.line),
                     Style::default().add_modifier(Modifier::ITALIC),
@@ -159,42 +711,417 @@ fn draw_ui(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, stateful_list:
             })
             .collect();
 
+        let list_title = selected_match
+            .as_ref()
+            .map(|selected_match| selected_match.path.display().to_string())
+            .unwrap_or_else(|| "No matches".to_string());
         // We can now render the item list
         let items = List::new(items)
-            .block(Block::default().borders(Borders::ALL).title(selected_match.path.to_str().unwrap_or("Unknown File")))
+            .block(Block::default().borders(Borders::ALL).title(list_title))
             .highlight_style(
                 Style::default()
-                    .bg(Color::LightGreen)
+                    .bg(selection_colour)
                     .add_modifier(Modifier::BOLD),
             )
             .highlight_symbol(">> ");
 
         // We can now render the item list
         f.render_stateful_widget(items, chunks[0], &mut stateful_list.state);
+        list_page = (chunks[0].height.saturating_sub(2) / 2).max(1) as usize;
 
 
-        // let mut spans = context.lines().map(Spans::from).collect::<Vec<_>>();
         let mut spans = Vec::new();
-        for line in selected_match.context.lines() {
-            let lowercase_line = line.to_lowercase();
-            let lowercase_term = search_term.to_lowercase();
-            if lowercase_line.contains(lowercase_term.as_str()) {
-                spans.push(Spans::from(Span::styled(line, Style::default().add_modifier(Modifier::BOLD).bg(Color::LightYellow))));
-                // let span = Spans::from(Span::styled(format!(), Style::default().bg(Color::LightBlue).fg(Color::Black)));
-            } else {
-                spans.push(Spans::from(line));
+        // Lines with a term in them are bold, the terms get their colour.
+        let mut push_line = |line: &str, style: Style| {
+            let highlights = highlighter.find(line);
+            let style = if highlights.is_empty() { style } else { style.add_modifier(Modifier::BOLD) };
+            spans.push(highlighted_terms(line, &highlights, style, term_colours));
+        };
+        // The borders and the result info take three lines.
+        let preview_height = chunks[1].height.saturating_sub(3);
+        let preview_title = match (page_image, page_preview, rendering) {
+            (Some(page_image), _, _) => {
+                let area = Rect::new(chunks[1].x + 1, chunks[1].y + 2, chunks[1].width.saturating_sub(2), preview_height);
+                if image_protocol == Protocol::HalfBlocks {
+                    spans.extend(half_blocks(&page_image.fitted(area.width as usize, area.height as usize * 2)));
+                } else {
+                    image_area = Some(area);
+                }
+                format!("Page {} ({} image, {}/{} page, {} for context)", page_image.page, image_protocol.name(), hint(Action::PreviousPage), hint(Action::NextPage), preview_key)
             }
-        }
-        let result_info_span = Spans::from(Span::styled(format!("Number of results: {}, below is preview.", stateful_list.items.len()), Style::default().bg(Color::LightBlue).fg(Color::Black)));
+            (None, _, Some(page)) => {
+                spans.push(Spans::from("Rendering the page…"));
+                format!("Page {} (rendering…, {} for context)", page, preview_key)
+            }
+            (None, None, None) if loading_pages => {
+                spans.push(Spans::from("Reading the pdf…"));
+                format!("Preview (reading the pdf…, {} for context)", preview_key)
+            }
+            (None, Some(page_preview), None) => {
+                page_preview.fit(preview_height as usize);
+                let matched_line = page_preview.matched_line();
+                for (index, line) in page_preview.lines().iter().enumerate() {
+                    let style = if matched_line == Some(page_preview.scroll + index) { Style::default().bg(selection_colour) } else { Style::default() };
+                    push_line(line, style);
+                }
+                format!("Page {} of {} ({}, {} for image)", page_preview.page, page_preview.page_count(), page_title, preview_key)
+            }
+            (None, None, None) => {
+                let context = selected_match.as_ref().map(|selected_match| selected_match.context.as_str()).unwrap_or("");
+                for line in context.lines() {
+                    push_line(line, Style::default());
+                }
+                format!("Preview ({} for the whole page)", preview_key)
+            }
+        };
+        let result_info_span = match (message, searching) {
+            (Some(message), _) => Spans::from(Span::styled(message.to_string(), Style::default().bg(Color::LightRed).fg(Color::Black))),
+            (None, Some(files_with_matches)) => Spans::from(Span::styled(
+                format!("Searching… {} files with matches, {} results so far, {} to stop.", files_with_matches, stateful_list.items.len(), cancel_key),
+                Style::default().bg(Color::LightYellow).fg(Color::Black),
+            )),
+            (None, None) => Spans::from(Span::styled(format!("Number of results: {}, below is preview.", stateful_list.items.len()), Style::default().bg(Color::LightBlue).fg(Color::Black))),
+        };
         spans.insert(0, result_info_span);
 
         let paragraph = Paragraph::new(spans).style(Style::default()).wrap(Wrap { trim: true })
             .block(Block::default()
                 .borders(Borders::ALL)
                 .style(Style::default().bg(Color::White).fg(Color::Black))
-                .title("Preview"));
+                .title(preview_title));
 
         f.render_widget(paragraph, chunks[1]);
+
+        if let Some(help) = help {
+            let size = f.size();
+            let width = size.width.min(80);
+            let height = size.height.min(help.len() as u16 + 2);
+            let area = Rect::new((size.width - width) / 2, (size.height - height) / 2, width, height);
+            let paragraph = Paragraph::new(help)
+                .block(Block::default().borders(Borders::ALL).title("Keys (any key closes, [keys] in the config changes them)"))
+                .style(Style::default().bg(Color::White).fg(Color::Black));
+            f.render_widget(Clear, area);
+            f.render_widget(paragraph, area);
+        }
     })?;
+    app.list_page = list_page;
+    Ok(image_area)
+}
+
+// Every action with its keys, unbound ones too so it is clear they can be bound.
+fn help_lines(keymap: &Keymap) -> Vec<Spans<'static>> {
+    Action::ALL
+        .iter()
+        .map(|action| {
+            let keys: Vec<String> = keymap.keys(*action).iter().map(ToString::to_string).collect();
+            let keys = if keys.is_empty() { "unbound".to_string() } else { keys.join(", ") };
+            Spans::from(vec![
+                Span::styled(format!("{:<28}", keys), Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(action.description()),
+            ])
+        })
+        .collect()
+}
+
+// What a kitty or sixel image should show now, None when there should not be one.
+fn wanted_terminal_image(terminal: &Terminal<CrosstermBackend<io::Stderr>>, app: &App) -> Result<Option<(PathBuf, usize, Rect)>, io::Error> {
+    // The help goes over the preview, tui can't draw over the image.
+    if app.preview_mode != PreviewMode::Image || app.image_protocol == Protocol::HalfBlocks || app.show_help {
+        return Ok(None);
+    }
+    let size = terminal.size()?;
+    Ok(app.page_image.as_ref().map(|page_image| (page_image.path.clone(), page_image.page, size)))
+}
+
+// Writes the image over the space draw_ui left for it, only when it is not there already.
+fn show_terminal_image(terminal: &mut Terminal<CrosstermBackend<io::Stderr>>, app: &mut App, area: Option<Rect>) -> Result<(), io::Error> {
+    let wanted = wanted_terminal_image(terminal, app)?;
+    let (Some(area), Some(page_image)) = (area, &app.page_image) else {
+        return Ok(());
+    };
+    if app.image_on_screen.is_some() || wanted.is_none() {
+        return Ok(());
+    }
+    let (cell_width, cell_height) = cell_size();
+    let image = page_image.fitted(area.width as usize * cell_width, area.height as usize * cell_height);
+    let escape = match app.image_protocol {
+        Protocol::Kitty => kitty(&image, image.width.div_ceil(cell_width), image.height.div_ceil(cell_height)),
+        Protocol::Sixel => sixel(&image),
+        Protocol::HalfBlocks => return Ok(()),
+    };
+    let backend = terminal.backend_mut();
+    queue!(backend, MoveTo(area.x, area.y))?;
+    backend.write_all(escape.as_bytes())?;
+    Write::flush(backend)?;
+    app.image_on_screen = wanted;
     Ok(())
 }
+
+fn remove_terminal_image(terminal: &mut Terminal<CrosstermBackend<io::Stderr>>, app: &mut App) -> Result<(), io::Error> {
+    if app.image_on_screen.take().is_none() {
+        return Ok(());
+    }
+    match app.image_protocol {
+        Protocol::Kitty => {
+            terminal.backend_mut().write_all(KITTY_DELETE.as_bytes())?;
+            Write::flush(terminal.backend_mut())
+        }
+        // Sixels replace the text in their cells, drawing every cell again removes them.
+        _ => terminal.clear(),
+    }
+}
+
+// The text with every term in the colour of its term number.
+fn highlighted_terms(text: &str, highlights: &Highlights, style: Style, colours: &[Color]) -> Spans<'static> {
+    let mut spans = Vec::new();
+    let mut end = 0;
+    for (range, term) in highlights {
+        if range.start > end {
+            spans.push(Span::styled(text[end..range.start].to_string(), style));
+        }
+        let colour = colours[term % colours.len()];
+        spans.push(Span::styled(text[range.clone()].to_string(), style.fg(Color::Black).bg(colour).add_modifier(Modifier::BOLD)));
+        end = range.end;
+    }
+    if end < text.len() || spans.is_empty() {
+        spans.push(Span::styled(text[end..].to_string(), style));
+    }
+    Spans::from(spans)
+}
+
+// The characters at the given char positions are drawn in red.
+fn highlighted(text: &str, positions: &[usize]) -> Spans<'static> {
+    let mut spans = Vec::new();
+    let mut run = String::new();
+    let mut run_highlighted = false;
+    for (i, c) in text.chars().enumerate() {
+        let is_highlighted = positions.contains(&i);
+        if is_highlighted != run_highlighted && !run.is_empty() {
+            spans.push(highlight_span(std::mem::take(&mut run), run_highlighted));
+        }
+        run_highlighted = is_highlighted;
+        run.push(c);
+    }
+    if !run.is_empty() {
+        spans.push(highlight_span(run, run_highlighted));
+    }
+    Spans::from(spans)
+}
+
+fn highlight_span(text: String, is_highlighted: bool) -> Span<'static> {
+    if is_highlighted {
+        Span::styled(text, Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
+    } else {
+        Span::raw(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf_text::PdfTextBackend;
+    use crate::runner::find_program;
+
+    // The page text and the image come in from threads of their own.
+    fn update_preview_and_wait(app: &mut App) {
+        app.update_preview();
+        while app.is_loading_preview() {
+            thread::sleep(Duration::from_millis(5));
+            app.update_preview();
+        }
+    }
+
+    fn finish(app: &mut App) {
+        while app.search_handler.is_searching() {
+            app.receive_results();
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn empty_list() {
+        let mut list: StatefulList<u32> = StatefulList::with_items(vec![]);
+        list.next();
+        list.previous();
+        assert_eq!(list.get_selected_item(), None);
+        let mut list = StatefulList::with_items(vec![1, 2]);
+        list.previous();
+        assert_eq!(list.get_selected_item(), Some(&2));
+    }
+
+    #[test]
+    fn edits_are_searched_after_debounce() {
+        let options = SearchOptions::new("test_assets/test.pdf", "subheading");
+        let mut search_handler = SearchHandler::with_backend(options, Box::new(PdfTextBackend::new()));
+        search_handler.search().unwrap();
+        let mut app = App::new(&mut search_handler, SortOrder::Relevance, &Config::default());
+        assert_eq!(app.stateful_list.items.len(), 1);
+
+        app.edit_field(InputField::Query, |text| text.clear());
+        app.edit_field(InputField::Query, |text| text.push_str("no such words"));
+        app.run_search_if_due();
+        assert_eq!(app.search_handler.options().search_term, "subheading");
+
+        app.search_due = Some(Instant::now());
+        app.run_search_if_due();
+        finish(&mut app);
+        assert_eq!(app.search_handler.options().search_term, "no such words");
+        assert_eq!(app.selected_match(), None);
+    }
+
+    #[test]
+    fn filter_narrows_without_searching() {
+        let options = SearchOptions::new("test_assets/test.pdf", "test");
+        let mut search_handler = SearchHandler::with_backend(options, Box::new(PdfTextBackend::new()));
+        search_handler.search().unwrap();
+        let mut app = App::new(&mut search_handler, SortOrder::Relevance, &Config::default());
+        let all = app.stateful_list.items.len();
+        assert!(all > 1);
+        app.edit_field(InputField::Filter, |text| text.push_str("subhead"));
+        assert_eq!(app.search_due, None);
+        assert_eq!(app.stateful_list.items.len(), 1);
+        assert_eq!(app.selected_match().unwrap().content, "This is a subheading - Test");
+        app.edit_field(InputField::Filter, |text| text.clear());
+        assert_eq!(app.stateful_list.items.len(), all);
+    }
+
+    #[test]
+    fn streamed_results_keep_the_selection() {
+        let options = SearchOptions::new("test_assets/test.pdf", "test");
+        let mut search_handler = SearchHandler::with_backend(options, Box::new(PdfTextBackend::new()));
+        search_handler.search().unwrap();
+        let mut app = App::new(&mut search_handler, SortOrder::Relevance, &Config::default());
+        app.stateful_list.next();
+        let selected = app.selected_match().cloned();
+        app.search_handler.start_search();
+        finish(&mut app);
+        assert!(app.stateful_list.items.len() > 1);
+        assert_eq!(app.selected_match().cloned(), selected);
+    }
+
+    #[test]
+    fn order_toggles_without_searching() {
+        let options = SearchOptions::new("test_assets/test.pdf", "test");
+        let mut search_handler = SearchHandler::with_backend(options, Box::new(PdfTextBackend::new()));
+        search_handler.search().unwrap();
+        let mut app = App::new(&mut search_handler, SortOrder::Path, &Config::default());
+        let lines = |app: &App| -> Vec<usize> { app.stateful_list.items.iter().map(|item| item.search_match.line).collect() };
+        let path_order = lines(&app);
+        assert!(path_order.windows(2).all(|pair| pair[0] < pair[1]));
+        app.stateful_list.next();
+        let selected = app.selected_match().cloned();
+        handle_editing_key(&mut app, InputField::Query, KeyEvent::new(KeyCode::F(4), KeyModifiers::NONE));
+        assert_eq!(app.sort_order, SortOrder::Page);
+        assert_eq!(app.search_due, None);
+        assert_eq!(app.selected_match().cloned(), selected);
+        app.act(Action::Order);
+        assert_eq!(app.sort_order, SortOrder::Relevance);
+        let mut relevance_order = lines(&app);
+        relevance_order.sort();
+        assert_eq!(relevance_order, path_order);
+    }
+
+    #[test]
+    fn page_preview_follows_the_selection() {
+        let options = SearchOptions::new("test_assets/test.pdf", "test");
+        let mut search_handler = SearchHandler::with_backend(options, Box::new(PdfTextBackend::new()));
+        search_handler.search().unwrap();
+        let mut app = App::new(&mut search_handler, SortOrder::Path, &Config::default());
+        app.update_preview();
+        assert!(app.page_preview.is_none());
+        app.next_preview_mode();
+        // The pdf is read on another thread, the preview waits for it.
+        assert!(app.loading_pages.is_some() && app.previewed_match.is_none());
+        update_preview_and_wait(&mut app);
+        let page_preview = app.page_preview.as_ref().unwrap();
+        assert_eq!(page_preview.path, PathBuf::from("test_assets/test.pdf"));
+        assert!(page_preview.matched_line().is_some());
+        let last = app.stateful_list.items.last().unwrap().search_match.clone();
+        app.stateful_list.previous();
+        app.update_preview();
+        assert_eq!(app.page_preview.as_ref().unwrap().page, last.page);
+        assert_eq!(app.previewed_match, Some(last));
+        // The test pdf has a single page, there is nowhere to step to.
+        app.act(Action::NextPage);
+        assert_eq!(app.page_preview.as_ref().unwrap().page, 1);
+        app.next_preview_mode();
+        update_preview_and_wait(&mut app);
+        if find_program("pdftoppm").is_some() {
+            assert_eq!(app.page_image.as_ref().unwrap().page, 1);
+        } else {
+            // Without poppler the page stays text.
+            assert_eq!(app.preview_mode, PreviewMode::Page);
+            assert!(app.message.as_ref().unwrap().contains("pdftoppm"));
+        }
+    }
+
+    #[test]
+    fn moves_by_file_and_screen() {
+        let options = SearchOptions::new("test_assets/test.pdf", "test");
+        let mut search_handler = SearchHandler::with_backend(options, Box::new(PdfTextBackend::new()));
+        let mut app = App::new(&mut search_handler, SortOrder::Path, &Config::default());
+        let search_match = |path: &str, line: usize| SearchMatch::new(PathBuf::from(path), 1, line, "test".to_string(), String::new());
+        app.search_handler.search_matches = Some(vec![search_match("a.pdf", 1), search_match("a.pdf", 2), search_match("b.pdf", 1), search_match("c.pdf", 1), search_match("c.pdf", 2)]);
+        app.refilter();
+        let selected = |app: &App| app.stateful_list.state.selected();
+        app.act(Action::NextFile);
+        assert_eq!(selected(&app), Some(2));
+        app.act(Action::NextFile);
+        app.act(Action::Down);
+        assert_eq!(selected(&app), Some(4));
+        app.act(Action::NextFile);
+        assert_eq!(selected(&app), Some(4));
+        app.act(Action::PreviousFile);
+        assert_eq!(selected(&app), Some(2));
+        app.act(Action::PreviousFile);
+        assert_eq!(selected(&app), Some(0));
+        app.list_page = 3;
+        app.act(Action::PageDown);
+        app.act(Action::PageDown);
+        assert_eq!(selected(&app), Some(4));
+        app.act(Action::PageUp);
+        assert_eq!(selected(&app), Some(1));
+        // The keys go through the keymap, G arrives with shift.
+        handle_editing_key(&mut app, InputField::Query, KeyEvent::new(KeyCode::End, KeyModifiers::NONE));
+        assert_eq!(selected(&app), Some(4));
+        assert_eq!(app.keymap.action(KeyEvent::new(KeyCode::Char('G'), KeyModifiers::SHIFT)), Some(Action::Last));
+        // j is text while typing.
+        handle_editing_key(&mut app, InputField::Filter, KeyEvent::new(KeyCode::Char('j'), KeyModifiers::NONE));
+        assert_eq!(app.fuzzy_finder.query, "j");
+        // So are the keys for editing.
+        app.keymap.bind(Action::ClearField, &["ctrl-w".parse().unwrap()]);
+        handle_editing_key(&mut app, InputField::Filter, KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL));
+        assert_eq!(app.fuzzy_finder.query, "");
+        handle_editing_key(&mut app, InputField::Filter, KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE));
+        assert_eq!(app.input_mode, InputMode::Editing(InputField::Filter.next()));
+    }
+
+    #[test]
+    fn terms_in_their_colour() {
+        let colours = [Color::Yellow, Color::Cyan, Color::Green];
+        let spans = highlighted_terms("bolt and torque", &vec![(0..4, 0), (9..15, 7)], Style::default(), &colours);
+        let texts: Vec<(&str, Option<Color>)> = spans.0.iter().map(|span| (span.content.as_ref(), span.style.bg)).collect();
+        assert_eq!(texts, vec![("bolt", Some(Color::Yellow)), (" and ", None), ("torque", Some(Color::Cyan))]);
+        assert_eq!(highlighted_terms("", &vec![], Style::default(), &colours).0.len(), 1);
+    }
+
+    #[test]
+    fn highlighted_runs() {
+        let spans = highlighted("torque", &[0, 1, 4]);
+        let texts: Vec<&str> = spans.0.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(texts, vec!["to", "rq", "u", "e"]);
+    }
+
+    #[test]
+    fn invalid_regex_is_a_message() {
+        let options = SearchOptions::new("test_assets/test.pdf", "subheading");
+        let mut search_handler = SearchHandler::with_backend(options, Box::new(PdfTextBackend::new()));
+        let mut app = App::new(&mut search_handler, SortOrder::Relevance, &Config::default());
+        app.edit_field(InputField::Query, |text| text.push('('));
+        app.search_due = Some(Instant::now());
+        app.run_search_if_due();
+        finish(&mut app);
+        assert!(app.message.is_some());
+        assert!(app.stateful_list.items.is_empty());
+    }
+}
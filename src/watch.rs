@@ -0,0 +1,247 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use walkdir::WalkDir;
+use crate::index::{Index, IndexChanges};
+use crate::pdf_text::is_pdf;
+use crate::search_error::SearchError;
+
+// How long a folder has to be quiet before the index is updated. A pdf that is still being copied
+// keeps sending events, so it is not extracted halfway.
+pub const DEBOUNCE: Duration = Duration::from_secs(2);
+// pdfs that keep coming in less than the debounce apart would hold the update off forever.
+// The ones still being written then are left for the next update.
+pub const MAX_WAIT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Builds the index of the root and then keeps it up to date as pdfs appear, change or disappear.
+/// Only returns when something goes wrong, `on_update` is called after every update.
+pub fn watch(root: &Path, on_file: &mut dyn FnMut(&Path), on_update: &mut dyn FnMut(&Index, &IndexChanges)) -> Result<(), SearchError> {
+    let mut watcher = Watcher::new(root)?;
+    let (mut index, changes) = Index::build(root, on_file)?;
+    index.save(root)?;
+    on_update(&index, &changes);
+    loop {
+        watcher.wait_for_changes(DEBOUNCE, MAX_WAIT)?;
+        let busy = watcher.still_writing(DEBOUNCE);
+        let changes = index.update_except(root, &busy, on_file)?;
+        index.save(root)?;
+        on_update(&index, &changes);
+    }
+}
+
+/// Watches a folder and every folder in it with inotify, which does not do that by itself.
+struct Watcher {
+    inotify: Inotify,
+    root: PathBuf,
+    folders: HashMap<WatchDescriptor, PathBuf>,
+    // The pdfs that were written to and not closed since, with when they were last written to.
+    writing: HashMap<PathBuf, Instant>,
+    buffer: Vec<u8>,
+}
+
+impl Watcher {
+    fn new(root: &Path) -> io::Result<Watcher> {
+        let mut watcher = Watcher {
+            inotify: Inotify::init()?,
+            root: root.to_path_buf(),
+            folders: HashMap::new(),
+            writing: HashMap::new(),
+            buffer: vec![0; 4096],
+        };
+        watcher.add_folder(root)?;
+        Ok(watcher)
+    }
+
+    // Hidden folders are skipped like when searching, the index itself is written in one.
+    fn add_folder(&mut self, folder: &Path) -> io::Result<()> {
+        let mask = WatchMask::CREATE
+            | WatchMask::MODIFY
+            | WatchMask::CLOSE_WRITE
+            | WatchMask::DELETE
+            | WatchMask::MOVED_FROM
+            | WatchMask::MOVED_TO;
+        let folders = WalkDir::new(folder)
+            .into_iter()
+            .filter_entry(|entry| entry.depth() == 0 || !is_hidden(entry.file_name()))
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_dir());
+        for entry in folders {
+            let descriptor = self.inotify.watches().add(entry.path(), mask)?;
+            self.folders.insert(descriptor, entry.path().to_path_buf());
+        }
+        Ok(())
+    }
+
+    // Reads all the events that are waiting, without blocking. Returns whether any of them can change the index.
+    fn read(&mut self) -> io::Result<bool> {
+        let mut new_folders = Vec::new();
+        let mut relevant = false;
+        let mut overflowed = false;
+        loop {
+            let events = match self.inotify.read_events(&mut self.buffer) {
+                Ok(events) => events,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            };
+            for event in events {
+                // The kernel dropped events, a new folder could be among them.
+                if event.mask.contains(EventMask::Q_OVERFLOW) {
+                    overflowed = true;
+                    continue;
+                }
+                // The folder was removed or moved out.
+                if event.mask.contains(EventMask::IGNORED) {
+                    self.folders.remove(&event.wd);
+                    continue;
+                }
+                let name = match event.name {
+                    Some(name) if !is_hidden(name) => name,
+                    _ => continue,
+                };
+                let is_folder = event.mask.contains(EventMask::ISDIR);
+                if is_folder && event.mask.intersects(EventMask::CREATE | EventMask::MOVED_TO) {
+                    if let Some(parent) = self.folders.get(&event.wd) {
+                        new_folders.push(parent.join(name));
+                    }
+                }
+                let is_pdf = !is_folder && is_pdf(Path::new(name));
+                if let Some(path) = self.folders.get(&event.wd).map(|folder| folder.join(name)).filter(|_| is_pdf) {
+                    if event.mask.intersects(EventMask::CREATE | EventMask::MODIFY) {
+                        self.writing.insert(path, Instant::now());
+                    } else {
+                        // Closed, moved in finished or gone.
+                        self.writing.remove(&path);
+                    }
+                }
+                relevant |= is_folder || is_pdf;
+            }
+        }
+        // Watching a folder again is harmless, so after an overflow every folder is added again.
+        if overflowed {
+            new_folders = vec![self.root.clone()];
+        }
+        // A folder that was moved in can already have pdfs in it, those are found by the update.
+        for folder in new_folders {
+            self.add_folder(&folder)?;
+        }
+        Ok(relevant || overflowed)
+    }
+
+    /// The pdfs, relative to the root, that are still open for writing and were written to within `quiet`.
+    /// Every write changes the size or the time of the pdf and sends an event. One that was left open
+    /// but not written to for longer is taken as done.
+    fn still_writing(&mut self, quiet: Duration) -> BTreeSet<PathBuf> {
+        self.writing.retain(|_, last_write| last_write.elapsed() < quiet);
+        self.writing
+            .keys()
+            .map(|path| path.strip_prefix(&self.root).unwrap_or(path).to_path_buf())
+            .collect()
+    }
+
+    // Returns once something changed and then nothing did for the debounce time, or once the
+    // first change has waited for max_wait. Pdfs that were still being written at the last update
+    // count as a change, so they are indexed once they are done.
+    fn wait_for_changes(&mut self, debounce: Duration, max_wait: Duration) -> io::Result<()> {
+        let requeued = (!self.writing.is_empty()).then(Instant::now);
+        let mut first_change: Option<Instant> = requeued;
+        let mut last_change: Option<Instant> = requeued;
+        loop {
+            if self.read()? {
+                last_change = Some(Instant::now());
+                first_change.get_or_insert(Instant::now());
+            }
+            if last_change.is_some_and(|last_change| last_change.elapsed() >= debounce) || first_change.is_some_and(|first_change| first_change.elapsed() >= max_wait) {
+                return Ok(());
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+fn is_hidden(name: &OsStr) -> bool {
+    name.to_str().is_some_and(|name| name.starts_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    fn sees_pdfs_in_new_folders() {
+        let root = std::env::temp_dir().join(format!("pdf_search_watch_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let mut watcher = Watcher::new(&root).unwrap();
+        assert!(!watcher.read().unwrap());
+
+        fs::write(root.join("notes.txt"), "not a pdf").unwrap();
+        fs::create_dir_all(root.join(".pdf_search")).unwrap();
+        assert!(!watcher.read().unwrap());
+
+        fs::create_dir(root.join("datasheets")).unwrap();
+        assert!(watcher.read().unwrap());
+        fs::copy("test_assets/test.pdf", root.join("datasheets/test.pdf")).unwrap();
+        let started = Instant::now();
+        watcher.wait_for_changes(Duration::from_millis(200), Duration::from_secs(10)).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(200));
+
+        // A removed folder is not watched any more.
+        let folders = watcher.folders.len();
+        fs::remove_dir_all(root.join("datasheets")).unwrap();
+        assert!(watcher.read().unwrap());
+        assert_eq!(watcher.folders.len(), folders - 1);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn steady_copies_still_update() {
+        let root = std::env::temp_dir().join(format!("pdf_search_watch_steady_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let mut watcher = Watcher::new(&root).unwrap();
+        let copying = root.clone();
+        let copier = thread::spawn(move || {
+            for number in 0..30 {
+                fs::copy("test_assets/test.pdf", copying.join(format!("{}.pdf", number))).unwrap();
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+        let started = Instant::now();
+        watcher.wait_for_changes(Duration::from_millis(500), Duration::from_millis(600)).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(1200), "{:?}", started.elapsed());
+        copier.join().unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn open_pdfs_are_still_being_written() {
+        let root = std::env::temp_dir().join(format!("pdf_search_watch_open_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let mut watcher = Watcher::new(&root).unwrap();
+        let mut copying = fs::File::create(root.join("copying.pdf")).unwrap();
+        copying.write_all(b"%PDF-1.4").unwrap();
+        fs::copy("test_assets/test.pdf", root.join("done.pdf")).unwrap();
+        assert!(watcher.read().unwrap());
+        assert_eq!(watcher.still_writing(Duration::from_secs(10)), [PathBuf::from("copying.pdf")].into());
+
+        // It is waited for again, and indexed once it is closed.
+        drop(copying);
+        watcher.wait_for_changes(Duration::from_millis(100), Duration::from_secs(10)).unwrap();
+        assert!(watcher.still_writing(Duration::from_secs(10)).is_empty());
+
+        // Or once it was not written to for the quiet time.
+        let _open = fs::File::create(root.join("left_open.pdf")).unwrap();
+        assert!(watcher.read().unwrap());
+        thread::sleep(Duration::from_millis(50));
+        assert!(watcher.still_writing(Duration::from_millis(10)).is_empty());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}